@@ -93,7 +93,7 @@ fn test_spin(){
     let mut pids = [0u16; THREAD_COUNT];
 
     for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
+        let pid = sys_thread().expect("Failed to create a thread");
         if pid == 0 {
             do_counter_inc_spin();
             sys_exit(0);
@@ -108,7 +108,7 @@ fn test_spin(){
 
     for i in 0..THREAD_COUNT {
         println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+        sys_wait_pid(pids[i]).expect("Failed to wait for the child");
     }
 
     println!("COUNTER result: {}", unsafe { COUNTER });
@@ -125,7 +125,7 @@ fn test_semaphore(){
     }
     //print!("ret = {}", ret);
     for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
+        let pid = sys_thread().expect("Failed to create a thread");
         if pid == 0 {
             do_counter_inc_sema();
             sys_exit(0);
@@ -140,7 +140,7 @@ fn test_semaphore(){
 
     for i in 0..THREAD_COUNT {
         println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+        sys_wait_pid(pids[i]).expect("Failed to wait for the child");
     }
 
     println!("COUNTER result: {}", unsafe { COUNTER });
@@ -153,14 +153,20 @@ fn test_semaphore(){
 }
 
 fn main() -> isize {
-    let pid = sys_fork();
+    let pid = match sys_fork() {
+        Ok(pid) => pid,
+        Err(err) => {
+            println!("Failed to fork: {}", err);
+            return -1;
+        }
+    };
 
     if pid == 0 {
         print!("\x1b[32m test semaphore begin now\n\x1b[0m");
         test_semaphore();
         print!("\x1b[32m test semaphore end\n\x1b[0m");
     } else {
-        sys_wait_pid(pid);
+        sys_wait_pid(pid).expect("Failed to wait for the child");
         print!("\x1b[32m test spin begin now\n\x1b[0m");
         unsafe{
             COUNTER = 0;
@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+extern crate lib;
+
+use lib::*;
+
+fn main() -> isize {
+    let old = sys_fd_limit(Some(5)).expect("Failed to set the fd limit");
+
+    // stdin, stdout and stderr take 0-2, the pipe takes the last two fds
+    let (read_fd, write_fd) = sys_pipe().expect("Failed to create a pipe");
+    assert_eq!((read_fd, write_fd), (3, 4));
+
+    assert_eq!(sys_dup(0), Err(SysError::TooManyFiles));
+    assert_eq!(sys_pipe(), Err(SysError::TooManyFiles));
+    assert_eq!(sys_open("/dev/null", OpenFlags::empty()), Err(SysError::TooManyFiles));
+    assert_eq!(sys_dup2(0, 5), Err(SysError::BadFd));
+
+    // a closed fd is free for the next one
+    sys_close_file(read_fd).expect("Failed to close the pipe");
+    assert_eq!(sys_dup(0), Ok(3));
+
+    assert_eq!(sys_fd_limit(Some(old)), Ok(5));
+    assert_eq!(sys_dup(0), Ok(5));
+
+    println!("fd limit test passed");
+    0
+}
+
+entry!(main);
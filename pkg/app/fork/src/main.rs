@@ -12,9 +12,16 @@ fn main() -> isize {
     let mut c = 32;
     let m_ptr = &raw mut M;
 
-    // do not alloc heap before `fork`
-    // which may cause unexpected behavior since we won't copy the heap in `fork`
-    let pid = sys_fork();
+    // the child gets a copy of the memory, except the heap:
+    // do not alloc heap before `fork`, the kernel-provided user heap
+    // stays shared between the parent and the child
+    let pid = match sys_fork() {
+        Ok(pid) => pid,
+        Err(err) => {
+            println!("Failed to fork: {}", err);
+            return -1;
+        }
+    };
 
     if pid == 0 {
         println!("I am the child process");
@@ -37,7 +44,7 @@ fn main() -> isize {
 
         println!("Waiting for child to exit...");
 
-        let ret = sys_wait_pid(pid);
+        let ret = sys_wait_pid(pid).expect("Failed to wait for the child");
 
         println!("Child exited with status {}", ret);
 
@@ -45,7 +52,7 @@ fn main() -> isize {
 
         unsafe {
             println!("parent read value of M: {:#x}", *m_ptr);
-            assert_eq!(*m_ptr, 0x2333);
+            assert_eq!(*m_ptr, 0xdeadbeef);
         }
 
         c += 1024;
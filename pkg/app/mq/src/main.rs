@@ -32,7 +32,7 @@ fn main() -> isize {
     WRITE_MUTEX.init(1);
 
     for i in 0..THREAD_COUNT {
-        let pid = sys_fork();
+        let pid = sys_thread().expect("Failed to create a thread");
 
         if i < THREAD_COUNT / 2 {
             if pid == 0 {
@@ -61,7 +61,7 @@ fn main() -> isize {
 
     for i in 0..THREAD_COUNT {
         println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+        sys_wait_pid(pids[i]).expect("Failed to wait for the child");
     }
 
     println!("Message Queue: {:?}", unsafe { MQ.queue });
@@ -65,17 +65,52 @@ fn main() -> isize {
                 run(path);
             }
             "ls" =>{
-                  sys_list_dir(command.next().unwrap_or("/"));
+                ls(command.next().unwrap_or("/"));
+            }
+            "stat" => {
+                stat(command.next().unwrap_or("/"));
+            }
+            "du" => {
+                let path = command.next().unwrap_or("/");
+                match du(path) {
+                    Ok(size) => println!("{}\t{}", size, path),
+                    Err(err) => println!("du: {}: {}", path, err),
+                }
+            }
+            "mount" => {
+                let (source, target) = match (command.next(), command.next()) {
+                    (Some(source), Some(target)) => (source, target),
+                    _ => {
+                        println!("usage: mount <partition> <path>");
+                        continue;
+                    }
+                };
+                if let Err(err) = sys_mount(source, target) {
+                    println!("mount: {}: {}", source, err);
+                }
+            }
+            "umount" => {
+                let target = command.next().unwrap_or("");
+                if let Err(err) = sys_umount(target) {
+                    println!("umount: {}: {}", target, err);
+                }
             }
             "cat" => {
-                let fd = sys_open_file(command.next().unwrap_or(""));
+                let path = command.next().unwrap_or("");
+                let fd = match sys_open_file(path) {
+                    Ok(fd) => fd,
+                    Err(err) => {
+                        println!("cat: {}: {}", path, err);
+                        continue;
+                    }
+                };
                 let buf = &mut [0u8; 1024];
-                sys_read(fd, buf);
+                let len = sys_read(fd, buf).unwrap_or(0);
                 println!(
                     "{}",
-                    core::str::from_utf8(buf).unwrap_or("Failed to read file")
+                    core::str::from_utf8(&buf[..len]).unwrap_or("Failed to read file")
                 );
-                sys_close_file(fd);
+                let _ = sys_close_file(fd);
             }
             _ => {
                 println!("Unknown command: {}", input);
@@ -1,7 +1,7 @@
 use alloc::string::String;
 extern crate lib;
 use lib::*;
-use chrono::Timelike;
+use chrono::{DateTime, Datelike, Timelike};
 
 const RESET: &str = "\x1b[0m";
 const BOLD: &str = "\x1b[1m";
@@ -61,33 +61,121 @@ pub fn help() {
     println!("  help - Show this help message");
     println!("  clock - Show the current clock counter value");
     println!("  echo <message> - Print the message to the console");
+    println!("  mount <partition> <path> - Mount a partition, e.g. mount hda2 /data");
+    println!("  umount <path> - Detach the filesystem mounted at path");
+    println!("  ls [path] - List the entries of a directory");
+    println!("  stat <path> - Show the status of a file");
+    println!("  du [path] - Show the total size of a directory");
+}
+
+fn format_millis(millis: Option<i64>) -> String {
+    match millis.and_then(DateTime::from_timestamp_millis) {
+        Some(time) => alloc::format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            time.year(),
+            time.month(),
+            time.day(),
+            time.hour(),
+            time.minute(),
+            time.second()
+        ),
+        None => String::from("-"),
+    }
+}
+
+pub fn ls(path: &str) {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) => {
+            println!("ls: {}: {}", path, err);
+            return;
+        }
+    };
+
+    println!("{:<20} {:>10} {:<20}", "Name", "Size", "Last Modified");
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                println!("ls: {}: {}", path, err);
+                return;
+            }
+        };
+        let mut name = entry.name;
+        if entry.stat.is_dir() {
+            name.push('/');
+        }
+        println!(
+            "{:<20} {:>10} {:<20}",
+            name,
+            entry.stat.size,
+            format_millis(entry.stat.modified)
+        );
+    }
+}
+
+pub fn stat(path: &str) {
+    match fs::stat(path) {
+        Ok(entry) => {
+            println!("  Name: {}", entry.name);
+            println!("  Type: {:?}", entry.stat.kind);
+            println!("  Size: {}", entry.stat.size);
+            println!("Create: {}", format_millis(entry.stat.created));
+            println!("Modify: {}", format_millis(entry.stat.modified));
+            println!("Access: {}", format_millis(entry.stat.accessed));
+        }
+        Err(err) => println!("stat: {}: {}", path, err),
+    }
+}
+
+/// Total size of files under `path`
+pub fn du(path: &str) -> SysResult<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.is_dir() {
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            let child = alloc::format!("{}/{}", path.trim_end_matches('/'), entry.name);
+            total += du(&child)?;
+        } else {
+            total += entry.stat.size;
+        }
+    }
+    Ok(total)
 }
 
 pub fn run(path: &str) {
     let name: vec::Vec<&str> = path.rsplit('/').collect();
-    let pid = sys_spawn(path);
-    if pid == 0 {
-        println!("{BOLD}{R1}⚠ Failed to run app: {}{RESET}", name[0]);
-    } else {
-        sys_stat();
-        println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], sys_wait_pid(pid));
-        // loop {
-        //     let ret = sys_wait_pid(pid);
-        //     if ret == 2333 {
-        //         println!("{BOLD}{R1}⚠ {} is still running...{RESET}", name[0]);
-        //     } else {
-        //         println!("{BOLD}{R1}⚠ {} exited with {}{RESET}, yeah", name[0], ret);
-        //         break;
-        //     }
-        // }
-        // loop {
-        //     let ret = sys_wait_pid(pid);
-        //     if ret == 0 {
-        //         println!("{BOLD}{R1}⚠ {} exited with {}{RESET}, yeah", name[0], ret);
-        //         break;
-        //     }
-        // }
+    let pid = match sys_spawn(path) {
+        Ok(pid) => pid,
+        Err(err) => {
+            println!("{BOLD}{R1}⚠ Failed to run app: {}: {}{RESET}", name[0], err);
+            return;
+        }
+    };
+    sys_stat();
+    match sys_wait_pid(pid) {
+        Ok(ret) => println!("{BOLD}{R3}✓ {} exited with {}{RESET}", name[0], ret),
+        Err(err) => println!("{BOLD}{R1}⚠ Failed to wait for {}: {}{RESET}", name[0], err),
     }
+    // loop {
+    //     let ret = sys_wait_pid(pid);
+    //     if ret == 2333 {
+    //         println!("{BOLD}{R1}⚠ {} is still running...{RESET}", name[0]);
+    //     } else {
+    //         println!("{BOLD}{R1}⚠ {} exited with {}{RESET}, yeah", name[0], ret);
+    //         break;
+    //     }
+    // }
+    // loop {
+    //     let ret = sys_wait_pid(pid);
+    //     if ret == 0 {
+    //         println!("{BOLD}{R1}⚠ {} exited with {}{RESET}, yeah", name[0], ret);
+    //         break;
+    //     }
+    // }
 }
 
 pub fn echo(message: &str) {
@@ -164,7 +164,7 @@ fn main() -> isize {
     S2.init(1);
     let mut pids: [u16; PHI_NUM] = [0u16; PHI_NUM];
     for i in 0..PHI_NUM{
-        let pid = sys_fork();
+        let pid = sys_thread().expect("Failed to create a thread");
         if pid == 0{
             if s == "1"{
                 philosopher1(i);
@@ -184,7 +184,7 @@ fn main() -> isize {
     let cpid = sys_get_pid();
     for i in 0..PHI_NUM {
         //println!("#{} waiting for #{}...", cpid, pids[i]);
-        sys_wait_pid(pids[i]);
+        sys_wait_pid(pids[i]).expect("Failed to wait for the child");
     }
     //销毁信号量
     for i in 0..PHI_NUM{
@@ -0,0 +1,174 @@
+//! Buddy System
+//!
+//! Free frames are kept in blocks of `2^order` frames aligned to their size,
+//! a freed block merges with its buddy when that one is free as well.
+//!
+//! reference: https://en.wikipedia.org/wiki/Buddy_memory_allocation
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+use alloc::collections::BTreeSet;
+use core::ops::Range;
+
+/// Blocks hold up to `2^MAX_ORDER` frames, 4 MiB
+pub const MAX_ORDER: usize = 10;
+
+/// Free blocks of each order, by the number of their first frame
+pub struct BuddyAllocator {
+    free: [BTreeSet<u32>; MAX_ORDER + 1],
+}
+
+impl BuddyAllocator {
+    pub const fn new() -> Self {
+        Self {
+            free: [const { BTreeSet::new() }; MAX_ORDER + 1],
+        }
+    }
+
+    /// Add the free frames of the range, in the largest aligned blocks
+    pub fn add_range(&mut self, frames: Range<u32>) {
+        let mut start = frames.start;
+        while start < frames.end {
+            let order = (start.trailing_zeros() as usize)
+                .min((frames.end - start).ilog2() as usize)
+                .min(MAX_ORDER);
+            self.free(start, order);
+            start += 1 << order;
+        }
+    }
+
+    /// Take a block of `2^order` frames, return its first frame
+    ///
+    /// A larger block is split if there is none of the order,
+    /// the lowest block is taken so low memory goes first.
+    pub fn alloc(&mut self, order: usize) -> Option<u32> {
+        let from = (order..=MAX_ORDER).find(|&o| !self.free[o].is_empty())?;
+        let block = self.free[from].pop_first()?;
+
+        // the upper halves go back to the smaller orders
+        for o in (order..from).rev() {
+            self.free[o].insert(block + (1 << o));
+        }
+        Some(block)
+    }
+
+    /// Return a block of `2^order` frames, merged with its free buddies
+    pub fn free(&mut self, mut block: u32, mut order: usize) {
+        while order < MAX_ORDER {
+            let buddy = block ^ (1 << order);
+            if !self.free[order].remove(&buddy) {
+                break;
+            }
+            block = block.min(buddy);
+            order += 1;
+        }
+        self.free[order].insert(block);
+    }
+
+    /// Free blocks of each order
+    pub fn free_blocks(&self) -> [usize; MAX_ORDER + 1] {
+        core::array::from_fn(|order| self.free[order].len())
+    }
+
+    pub fn free_frames(&self) -> usize {
+        self.free_blocks()
+            .iter()
+            .enumerate()
+            .map(|(order, count)| count << order)
+            .sum()
+    }
+}
+
+impl Default for BuddyAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_range_aligned() {
+        let mut buddy = BuddyAllocator::new();
+        buddy.add_range(3..16);
+
+        // 3, 4..8, 8..16
+        let mut expected = [0; MAX_ORDER + 1];
+        expected[0] = 1;
+        expected[2] = 1;
+        expected[3] = 1;
+        assert_eq!(buddy.free_blocks(), expected);
+        assert_eq!(buddy.free_frames(), 13);
+    }
+
+    #[test]
+    fn test_alloc_split() {
+        let mut buddy = BuddyAllocator::new();
+        buddy.add_range(0..16);
+
+        assert_eq!(buddy.alloc(0), Some(0));
+
+        // the upper halves 1, 2..4, 4..8, 8..16 are left
+        let mut expected = [0; MAX_ORDER + 1];
+        expected[..4].fill(1);
+        assert_eq!(buddy.free_blocks(), expected);
+        assert_eq!(buddy.free_frames(), 15);
+
+        // the lowest fitting block is split first
+        assert_eq!(buddy.alloc(1), Some(2));
+        assert_eq!(buddy.alloc(0), Some(1));
+        assert_eq!(buddy.alloc(4), None);
+    }
+
+    #[test]
+    fn test_free_coalesce() {
+        let mut buddy = BuddyAllocator::new();
+        buddy.add_range(0..16);
+
+        let blocks: Vec<_> = (0..16).map(|_| buddy.alloc(0).unwrap()).collect();
+        assert_eq!(buddy.free_frames(), 0);
+        assert_eq!(buddy.alloc(0), None);
+
+        // odd frames have no free buddy yet
+        for &block in blocks.iter().filter(|&&b| b % 2 == 1) {
+            buddy.free(block, 0);
+        }
+        assert_eq!(buddy.free_blocks()[0], 8);
+
+        for &block in blocks.iter().filter(|&&b| b % 2 == 0) {
+            buddy.free(block, 0);
+        }
+        let mut expected = [0; MAX_ORDER + 1];
+        expected[4] = 1;
+        assert_eq!(buddy.free_blocks(), expected);
+        assert_eq!(buddy.alloc(4), Some(0));
+    }
+
+    #[test]
+    fn test_alloc_alignment() {
+        let mut buddy = BuddyAllocator::new();
+        buddy.add_range(5..4096);
+
+        for order in [0, 3, 1, 5, 2, MAX_ORDER, 4] {
+            let block = buddy.alloc(order).unwrap();
+            assert_eq!(block % (1 << order), 0, "order {order} at {block}");
+        }
+    }
+
+    #[test]
+    fn test_max_order() {
+        let mut buddy = BuddyAllocator::new();
+        buddy.add_range(0..4 << MAX_ORDER);
+
+        // blocks never merge beyond the max order
+        assert_eq!(buddy.free_blocks()[MAX_ORDER], 4);
+        assert_eq!(buddy.alloc(MAX_ORDER + 1), None);
+        let block = buddy.alloc(MAX_ORDER).unwrap();
+        buddy.free(block, MAX_ORDER);
+        assert_eq!(buddy.free_blocks()[MAX_ORDER], 4);
+    }
+}
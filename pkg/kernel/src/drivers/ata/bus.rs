@@ -5,14 +5,20 @@
 //! reference: https://github.com/theseus-os/Theseus/blob/HEAD/kernel/ata/src/lib.rs
 
 use super::consts::*;
+use super::dma::*;
+use super::queue::*;
+use crate::proc::ProcessId;
 use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use storage::Block512;
 use x86_64::instructions::port::*;
 
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct AtaBus {
     id: u8,              // 总线标识符（主/从通道）
-    irq: u8,             // 中断号（实际未使用）
+    irq: u8,             // 中断号（IRQ 14/15，请求完成时触发）
     io_base: u16,        // I/O端口基地址（数据寄存器组）
     ctrl_base: u16,      // 控制端口基地址（控制寄存器组）
     
@@ -35,13 +41,24 @@ pub struct AtaBus {
     alternate_status: PortReadOnly<u8>,  // 替代状态(0x3F6)
     control: PortWriteOnly<u8>,         // 设备控制(0x3F6)
     drive_blockess: PortReadOnly<u8>,    // 驱动器地址(0x3F7)
+
+    /// Requests waiting for the completion interrupt
+    queue: RequestQueue,
+    /// Bus Master DMA of the channel, reads use PIO without it
+    dma: Option<BusMaster>,
+    /// Sectors per interrupt of `ReadMultiple` for each drive, 0 if not set
+    multiple: [u16; 2],
+    /// Whether each drive takes 48-bit LBA commands
+    lba48: [bool; 2],
+    /// Sectors of the running PIO read received so far
+    transfer: Vec<Block512>,
 }
 
 impl AtaBus {
-    pub fn new(id: u8, irq: u8, io_base: u16, ctrl_base: u16) -> Self { // 创建ATA总线实例
+    pub(super) fn new(id: u8, irq: u8, io_base: u16, ctrl_base: u16, dma: Option<BusMaster>) -> Self { // 创建ATA总线实例
         Self {
             id,
-            irq, // 实际未使用（使用轮询替代中断）
+            irq,
             io_base,
             ctrl_base,
             // 初始化所有端口寄存器
@@ -59,6 +76,11 @@ impl AtaBus {
             alternate_status: PortReadOnly::new(ctrl_base),     // 0x3F6
             control: PortWriteOnly::new(ctrl_base),             // 0x3F6
             drive_blockess: PortReadOnly::new(ctrl_base + 1),   // 0x3F7
+            queue: RequestQueue::default(),
+            dma,
+            multiple: [0; 2],
+            lba48: [false; 2],
+            transfer: Vec::new(),
         }
     }
 
@@ -139,12 +161,53 @@ impl AtaBus {
     /// Writes the given command
     ///
     /// reference: https://wiki.osdev.org/ATA_PIO_Mode#28_bit_PIO
-    fn write_command(&mut self, drive: u8, block: u32, cmd: AtaCommand) -> storage::FsResult {
+    fn write_command(&mut self, drive: u8, block: u64, cmd: AtaCommand) -> storage::FsResult {
+        self.issue_command(drive, block, 1, cmd)?;
+
+        // FIXME: poll for the status to be not BUSY
+        self.poll(AtaStatus::BUSY, false);
+
+        if self.is_error() {
+            warn!("ATA error: {cmd:?} command error");
+            self.debug();
+            return Err(storage::DeviceError::InvalidOperation.into());
+        }
+
+        // FIXME: poll for the status to be not BUSY and DATA_REQUEST_READY
+        // self.poll(AtaStatus::BUSY | AtaStatus::DATA_REQUEST_READY, true);
+        self.poll(AtaStatus::BUSY, false);
+        self.poll(AtaStatus::DATA_REQUEST_READY,true);
+
+        Ok(())
+    }
+
+    /// Writes the given command for `count` sectors without waiting for the drive
+    ///
+    /// The 48-bit variant of `cmd` is used if the drive supports it.
+    ///
+    /// reference: https://wiki.osdev.org/ATA_PIO_Mode#48_bit_PIO
+    fn issue_command(&mut self, drive: u8, block: u64, count: u16, cmd: AtaCommand) -> storage::FsResult {
         // drive: 设备选择（0=主设备，1=从设备）
-        let bytes = block.to_le_bytes(); // a trick to convert u32 to [u8; 4]
+        let lba48 = self.lba48[drive as usize & 1];
+        let cmd = if lba48 { cmd.ext() } else { cmd };
+        let limit = if lba48 { 1 << 48 } else { 1 << 28 };
+        if block + count.max(1) as u64 > limit {
+            warn!("ATA error: block {block} is out of the address range");
+            return Err(storage::DeviceError::InvalidOperation.into());
+        }
+
+        let bytes = block.to_le_bytes(); // a trick to convert u64 to [u8; 8]
         unsafe {
-            // just 1 sector for current implementation
-            self.sector_count.write(1);
+            if lba48 {
+                // the high bytes go first, the registers keep both
+                self.sector_count.write((count >> 8) as u8);
+                self.lba_low.write(bytes[3]);
+                self.lba_mid.write(bytes[4]);
+                self.lba_high.write(bytes[5]);
+            }
+
+            // 0 means 256 sectors, or 65536 with LBA48
+            self.sector_count.write(count as u8);
 
             // FIXME: store the LBA28 address into four 8-bit registers
             //      - read the documentation for more information
@@ -154,21 +217,9 @@ impl AtaBus {
             self.lba_high.write(bytes[2]); // LBA high
 
             // 0-3是lba的24-27位，4是分辨主从盘，5、7必须为1，6是lba模式/chs模式
-            // 构建drive寄存器值
-            // let mut drive_val = 0xA0; // 二进制10100000 (位7,5=1 + LBA模式=1)
-            
-            // // 设置驱动器选择(位4)
-            // if drive != 0 { // 假设drive=0表示主盘，非0表示从盘
-            //     drive_val |= 0x10; // 设置位4为1
-            // }
-            
-            // // 设置LBA最高4位(位3-0)
-            // drive_val |= (bytes[3] & 0x0F); // 取block的第24-27位
-            
-            // // 写入drive寄存器
-            // self.drive.write(drive_val);
-
-            self.drive.write(0xE0 | ((drive & 1) << 4) | ((bytes[3] & 0x0F)));
+            // LBA48 leaves bits 0-3 unused
+            let high = if lba48 { 0 } else { bytes[3] & 0x0F };
+            self.drive.write(0xE0 | ((drive & 1) << 4) | high);
 
             // FIXME: write the command register (cmd as u8)
             self.command.write(cmd as u8);
@@ -179,20 +230,6 @@ impl AtaBus {
             return Err(storage::DeviceError::UnknownDevice.into());
         }
 
-        // FIXME: poll for the status to be not BUSY
-        self.poll(AtaStatus::BUSY, false);
-
-        if self.is_error() {
-            warn!("ATA error: {:?} command error", cmd);
-            self.debug();
-            return Err(storage::DeviceError::InvalidOperation.into());
-        }
-
-        // FIXME: poll for the status to be not BUSY and DATA_REQUEST_READY
-        // self.poll(AtaStatus::BUSY | AtaStatus::DATA_REQUEST_READY, true);
-        self.poll(AtaStatus::BUSY, false);
-        self.poll(AtaStatus::DATA_REQUEST_READY,true);
-
         Ok(())
     }
 
@@ -200,68 +237,292 @@ impl AtaBus {
     ///
     /// reference: https://wiki.osdev.org/ATA_PIO_Mode#IDENTIFY_command
     pub(super) fn identify_drive(&mut self, drive: u8) -> storage::FsResult<AtaDeviceType> {
-        info!("Identifying drive {}", drive);
+        info!("Identifying drive {}@{}", self.id, drive);
+
+        // the status floats high if there is no drive on the bus at all
+        if self.status() == AtaStatus::all() {
+            return Ok(AtaDeviceType::None);
+        }
 
         // FIXME: use `AtaCommand::IdentifyDevice` to identify the drive
         //      - call `write_command` with `drive` and `0` as the block number
         //      - if the status is empty, return `AtaDeviceType::None`
         //      - else return `DeviceError::Unknown` as `FsError`
-        self.write_command(drive, 0, AtaCommand::IdentifyDevice)?;
+        self.issue_command(drive, 0, 1, AtaCommand::IdentifyDevice)?;
 
         // FIXME: poll for the status to be not BUSY
         self.poll(AtaStatus::BUSY, false);
 
+        // packet devices abort the command and leave their signature
         Ok(match (self.cylinder_low(), self.cylinder_high()) {
-            // we only support PATA drives
-            (0x00, 0x00) => AtaDeviceType::Pata(Box::new([0u16; 256].map(|_| self.read_data()))),
+            (0x00, 0x00) => {
+                self.wait_data()?;
+                AtaDeviceType::Pata(Box::new([0u16; 256].map(|_| self.read_data())))
+            }
+            (0x14, 0xEB) => {
+                self.issue_command(drive, 0, 1, AtaCommand::IdentifyPacket)?;
+                self.wait_data()?;
+                AtaDeviceType::PataPi(Box::new([0u16; 256].map(|_| self.read_data())))
+            }
             // ignore the data as we don't support following types
-            (0x14, 0xEB) => AtaDeviceType::PataPi,
             (0x3C, 0xC3) => AtaDeviceType::Sata,
             (0x69, 0x96) => AtaDeviceType::SataPi,
             _ => AtaDeviceType::None,
         })
     }
 
-    /// Reads a block from the given drive and block number into the given buffer.
+    /// Waits until the drive is not busy, then fails unless it has data ready
+    fn wait_data(&mut self) -> storage::FsResult {
+        self.poll(AtaStatus::BUSY, false);
+        let status = self.status();
+        if status.contains(AtaStatus::ERROR) || !status.contains(AtaStatus::DATA_REQUEST_READY) {
+            self.debug();
+            return Err(storage::DeviceError::ReadError.into());
+        }
+        Ok(())
+    }
+
+    /// Sends a SCSI command to an ATAPI drive and reads its reply into `buf`,
+    /// the drive is polled so the bus has to be idle.
     ///
-    /// reference: https://wiki.osdev.org/ATA_PIO_Mode#28_bit_PIO
-    /// reference: https://wiki.osdev.org/IDE#Read.2FWrite_From_ATA_Drive
-    pub(super) fn read_pio(
+    /// reference: https://wiki.osdev.org/ATAPI
+    pub(super) fn send_packet(&mut self, drive: u8, packet: &[u8; 12], buf: &mut [u8]) -> storage::FsResult {
+        let len = buf.len().min(u16::MAX as usize & !1);
+        unsafe {
+            self.drive.write(0xA0 | ((drive & 1) << 4));
+            // PIO, the most bytes per DRQ is in the LBA mid and high registers
+            self.features.write(0);
+            self.lba_mid.write(len as u8);
+            self.lba_high.write((len >> 8) as u8);
+            self.command.write(AtaCommand::Packet as u8);
+        }
+
+        self.wait_data()?;
+        self.write_data_from(packet);
+        self.wait_data()?;
+
+        // the drive tells how many bytes it sends
+        let size = (self.cylinder_high() as usize) << 8 | self.cylinder_low() as usize;
+        let size = size.min(len);
+        self.read_data_into(&mut buf[..size]);
+        self.poll(AtaStatus::BUSY, false);
+        Ok(())
+    }
+
+    /// Sets the sectors per interrupt of `ReadMultiple`, the maximum is in
+    /// word 47 of the IDENTIFY data, reads transfer one sector at a time if unset.
+    pub(super) fn set_multiple(&mut self, drive: u8, count: u8) -> storage::FsResult {
+        if count == 0 {
+            return Ok(());
+        }
+
+        self.issue_command(drive, 0, count as u16, AtaCommand::SetMultiple)?;
+        self.poll(AtaStatus::BUSY, false);
+        if self.is_error() {
+            warn!("ATA error: drive {drive} does not support {count} sectors per block");
+            return Err(storage::DeviceError::InvalidOperation.into());
+        }
+
+        self.multiple[drive as usize & 1] = count as u16;
+        Ok(())
+    }
+
+    /// Selects the 48-bit LBA commands for the drive, reported in word 83 of the IDENTIFY data
+    pub(super) fn set_lba48(&mut self, drive: u8, enabled: bool) {
+        self.lba48[drive as usize & 1] = enabled;
+    }
+
+    /// Queues a request and puts it on the drive if the bus is idle,
+    /// the data of a write is sent at once, a read waits for the interrupt.
+    pub(super) fn submit(
+        &mut self,
+        drive: u8,
+        block: u64,
+        op: AtaOp,
+        waiter: Option<ProcessId>,
+    ) -> RequestId {
+        let id = self.queue.submit(drive, block, op, waiter);
+        // nobody is blocked on a request that fails to start here
+        self.start_next();
+        id
+    }
+
+    /// Takes the result of the request, `None` if it is not finished yet
+    pub(super) fn take(&mut self, id: RequestId) -> Option<storage::FsResult<Vec<Block512>>> {
+        self.queue.take(id)
+    }
+
+    /// Takes the data of a finished read of the sectors
+    pub(super) fn take_read(
         &mut self,
         drive: u8,
-        block: u32,
-        buf: &mut [u8],
-    ) -> storage::FsResult {
-        self.write_command(drive, block, AtaCommand::ReadPio)?;
+        block: u64,
+        count: u16,
+    ) -> Option<storage::FsResult<Vec<Block512>>> {
+        self.queue.take_read(drive, block, count)
+    }
+
+    pub(super) fn is_read_done(&self, drive: u8, block: u64, count: u16) -> bool {
+        self.queue.is_read_done(drive, block, count)
+    }
+
+    pub(super) fn is_idle(&self) -> bool {
+        self.queue.is_idle()
+    }
 
+    pub(super) fn is_write_full(&self) -> bool {
+        self.queue.is_write_full()
+    }
+
+    /// Takes the first write failed since the last call
+    pub(super) fn take_write_error(&mut self) -> Option<storage::FsError> {
+        self.queue.take_write_error()
+    }
+
+    /// Writes the write cache of the drive to the disk, the bus must be idle
+    ///
+    /// reference: https://wiki.osdev.org/ATA_PIO_Mode#Cache_Flush
+    pub(super) fn flush_cache(&mut self, drive: u8) -> storage::FsResult {
+        self.issue_command(drive, 0, 0, AtaCommand::CacheFlush)?;
+        self.poll(AtaStatus::BUSY, false);
+        if self.is_error() {
+            self.debug();
+            return Err(storage::DeviceError::WriteError.into());
+        }
+        Ok(())
+    }
+
+    /// Finishes the running request when the drive is done with it,
+    /// then starts the next one.
+    ///
+    /// Called on the bus interrupt, reading the status register acknowledges it.
+    /// Returns the processes waiting for the finished requests.
+    pub(super) fn handle_interrupt(&mut self) -> BTreeSet<ProcessId> {
+        let status = self.status();
+        let Some(req) = self.queue.running().cloned() else {
+            return BTreeSet::new();
+        };
+        if status.contains(AtaStatus::BUSY) {
+            return BTreeSet::new();
+        }
+
+        let result = match req.op {
+            AtaOp::Read(count) if self.dma.is_some() => match self.finish_dma(count, status) {
+                Some(result) => result,
+                None => return BTreeSet::new(),
+            },
+            _ if status.contains(AtaStatus::ERROR) => {
+                warn!("ATA error: {:?} of block {} failed", req.op, req.block);
+                self.debug();
+                self.transfer.clear();
+                Err(match req.op {
+                    AtaOp::Read(_) => storage::DeviceError::ReadError.into(),
+                    AtaOp::Write(_) => storage::DeviceError::WriteError.into(),
+                })
+            }
+            AtaOp::Read(count) if status.contains(AtaStatus::DATA_REQUEST_READY) => {
+                // one block of sectors is ready on each interrupt
+                let left = count as usize - self.transfer.len();
+                let sectors = left.min(self.multiple[req.drive as usize & 1].max(1) as usize);
+                for _ in 0..sectors {
+                    let mut block = Block512::default();
+                    self.read_data_into(block.as_mut());
+                    self.transfer.push(block);
+                }
+                if sectors < left {
+                    return BTreeSet::new();
+                }
+                Ok(core::mem::take(&mut self.transfer))
+            }
+            // a stale interrupt, the data is not there yet
+            AtaOp::Read(_) => return BTreeSet::new(),
+            AtaOp::Write(_) => Ok(Vec::new()),
+        };
+
+        let mut waiters = self.queue.finish(result);
+        waiters.append(&mut self.start_next());
+        waiters
+    }
+
+    /// Stops the DMA read of `count` sectors, `None` on a stale interrupt
+    fn finish_dma(&mut self, count: u16, status: AtaStatus) -> Option<storage::FsResult<Vec<Block512>>> {
+        let dma = self.dma.as_mut()?;
+        if !dma.status().intersects(BmStatus::INTERRUPT | BmStatus::ERROR) {
+            return None;
+        }
+
+        let bm_status = dma.stop();
+        if bm_status.contains(BmStatus::ERROR) || status.contains(AtaStatus::ERROR) {
+            warn!("ATA error: DMA read failed: {bm_status:?} {status:?}");
+            return Some(Err(storage::DeviceError::ReadError.into()));
+        }
+        Some(Ok(dma.read_buffer(count as usize)))
+    }
+
+    /// Puts queued requests on the drive until one starts,
+    /// returns the waiters of the requests failed to start.
+    fn start_next(&mut self) -> BTreeSet<ProcessId> {
+        let mut waiters = BTreeSet::new();
+        while let Some(req) = self.queue.start() {
+            match self.start(&req) {
+                Ok(()) => break,
+                Err(err) => waiters.append(&mut self.queue.finish(Err(err))),
+            }
+        }
+        waiters
+    }
+
+    /// Issues the command of the request
+    ///
+    /// reference: https://wiki.osdev.org/ATA_PIO_Mode#28_bit_PIO
+    /// reference: https://wiki.osdev.org/ATA/ATAPI_using_DMA
+    fn start(&mut self, req: &AtaRequest) -> storage::FsResult {
+        match &req.op {
+            AtaOp::Read(count) => {
+                let cmd = match (self.dma.as_mut(), self.multiple[req.drive as usize & 1]) {
+                    (Some(dma), _) => {
+                        dma.prepare(*count as usize);
+                        AtaCommand::ReadDma
+                    }
+                    (None, 0) => AtaCommand::ReadPio,
+                    (None, _) => AtaCommand::ReadMultiple,
+                };
+                self.transfer.clear();
+                self.issue_command(req.drive, req.block, *count, cmd)?;
+                if let Some(dma) = self.dma.as_mut() {
+                    dma.start();
+                }
+                Ok(())
+            }
+            AtaOp::Write(data) => {
+                self.issue_command(req.drive, req.block, 1, AtaCommand::WritePio)?;
+
+                // the drive asks for the data right after the command,
+                // the interrupt comes when it is written to the disk
+                self.poll(AtaStatus::BUSY, false);
+                if self.is_error() {
+                    self.debug();
+                    return Err(storage::DeviceError::WriteError.into());
+                }
+                self.poll(AtaStatus::DATA_REQUEST_READY, true);
+                self.write_data_from(&data[..]);
+                Ok(())
+            }
+        }
+    }
+
+    fn read_data_into(&mut self, buf: &mut [u8]) {
         // FIXME: read the data from the data port into the buffer
         //      - use `buf.chunks_mut(2)`
         //      - use `self.read_data()`
         //      - ! pay attention to data endianness
         for chunk in buf.chunks_mut(2) {
-            // let data = self.read_data();
-            // chunk[0] = (data & 0xFF) as u8; // lower byte
-            // chunk[1] = (data >> 8) as u8;   // upper byte
             let data = self.read_data().to_le_bytes();
             chunk.copy_from_slice(&data);
         }
-
-        if self.is_error() {
-            debug!("ATA error: data read error");
-            self.debug();
-            Err(storage::DeviceError::ReadError.into())
-        } else {
-            Ok(())
-        }
     }
 
-    /// Writes a block to the given drive and block number from the given buffer.
-    ///
-    /// reference: https://wiki.osdev.org/ATA_PIO_Mode#28_bit_PIO
-    /// reference: https://wiki.osdev.org/IDE#Read.2FWrite_From_ATA_Drive
-    pub(super) fn write_pio(&mut self, drive: u8, block: u32, buf: &[u8]) -> storage::FsResult {
-        self.write_command(drive, block, AtaCommand::WritePio)?;
-
+    fn write_data_from(&mut self, buf: &[u8]) {
         // FIXME: write the data from the buffer into the data port
         //      - use `buf.chunks(2)`
         //      - use `self.write_data()`
@@ -270,13 +531,5 @@ impl AtaBus {
             let data = u16::from_le_bytes(chunk.try_into().unwrap_or([0, 0]));
             self.write_data(data);
         }
-
-        if self.is_error() {
-            debug!("ATA error: data write error");
-            self.debug();
-            Err(storage::DeviceError::WriteError.into())
-        } else {
-            Ok(())
-        }
     }
 }
@@ -58,6 +58,16 @@ pub(super) enum AtaCommand {
     WriteDma = 0xCA,
     /// Write sectors using DMA (48-bit LBA)
     WriteDmaExt = 0x35,
+    /// Read sectors using PIO, one interrupt per block of sectors
+    ReadMultiple = 0xC4,
+    /// Write sectors using PIO, one interrupt per block of sectors
+    WriteMultiple = 0xC5,
+    /// Read sectors using PIO, one interrupt per block of sectors (48-bit LBA)
+    ReadMultipleExt = 0x29,
+    /// Write sectors using PIO, one interrupt per block of sectors (48-bit LBA)
+    WriteMultipleExt = 0x39,
+    /// Set the number of sectors per block of `ReadMultiple` / `WriteMultiple`
+    SetMultiple = 0xC6,
     /// Flush the drive's bus cache (28-bit LBA).
     /// This is to be used after each write.
     CacheFlush = 0xE7,
@@ -72,16 +82,32 @@ pub(super) enum AtaCommand {
     IdentifyDevice = 0xEC,
 }
 
+impl AtaCommand {
+    /// The 48-bit LBA variant of a transfer command, others are the same
+    pub(super) fn ext(self) -> Self {
+        match self {
+            Self::ReadPio => Self::ReadPioExt,
+            Self::ReadDma => Self::ReadDmaExt,
+            Self::WritePio => Self::WritePioExt,
+            Self::WriteDma => Self::WriteDmaExt,
+            Self::ReadMultiple => Self::ReadMultipleExt,
+            Self::WriteMultiple => Self::WriteMultipleExt,
+            Self::CacheFlush => Self::CacheFlushExt,
+            cmd => cmd,
+        }
+    }
+}
+
 /// The possible types of drive devices that can be attached to an IDE controller via ATA.
 pub(super) enum AtaDeviceType {
     /// A parallel ATA (PATA) drive, like a hard drive.
     /// This is the type previously known as just "ATA" before SATA existed.
     ///
-    /// **which is the only type of drive that is supported by the current implementation.**
+    /// **which is the only type of drive that can be written by the current implementation.**
     Pata(Box<[u16; 256]>),
     /// A parallel ATA (PATA) drive that uses the packet interface,
     /// like an optical CD-ROM drive.
-    PataPi,
+    PataPi(Box<[u16; 256]>),
     /// A serial ATA (SATA) drive that is operating in legacy IDE emulation mode,
     /// **not the standard AHCI interface for SATA**.
     /// Some systems refer to this as a `SEMB` (SATA Enclosure Management Bridge) device,
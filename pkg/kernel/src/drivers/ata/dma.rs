@@ -0,0 +1,169 @@
+//! ATA Bus Master DMA
+//!
+//! The PIIX IDE controller reads sectors straight into memory described by a
+//! Physical Region Descriptor Table (PRDT), one entry per frame of the buffer.
+//!
+//! reference: https://wiki.osdev.org/ATA/ATAPI_using_DMA
+
+use super::MAX_SECTORS;
+use crate::drivers::pci::{self, PciCommand};
+use crate::memory::{get_frame_alloc_for_sure, physical_to_virtual, FrameOwner, PAGE_SIZE};
+use alloc::vec::Vec;
+use storage::Block512;
+use x86_64::instructions::port::Port;
+use x86_64::structures::paging::{FrameAllocator, PhysFrame};
+
+/// Mass storage controller, IDE interface
+const PCI_CLASS_STORAGE: u8 = 0x01;
+const PCI_SUBCLASS_IDE: u8 = 0x01;
+
+/// The controller is bus master capable
+const PROG_IF_BUS_MASTER: u8 = 0x80;
+
+const SECTOR_SIZE: usize = 512;
+
+const SECTORS_PER_FRAME: usize = PAGE_SIZE as usize / SECTOR_SIZE;
+const BUFFER_FRAMES: usize = MAX_SECTORS.div_ceil(SECTORS_PER_FRAME);
+
+/// Last entry of the PRDT
+const PRD_END_OF_TABLE: u16 = 0x8000;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub(super) struct BmCommand: u8 {
+        const START = 0x01;
+        /// Transfer from the drive into memory
+        const READ = 0x08;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub(super) struct BmStatus: u8 {
+        const ACTIVE = 0x01;
+        const ERROR = 0x02;
+        const INTERRUPT = 0x04;
+    }
+}
+
+/// I/O base of the bus master registers of both channels
+///
+/// Bus mastering is enabled on the controller, `None` if there is no capable one.
+pub(super) fn probe() -> Option<u16> {
+    let dev = pci::find_class(PCI_CLASS_STORAGE, PCI_SUBCLASS_IDE)?;
+    let (_, _, prog_if) = dev.class();
+    if prog_if & PROG_IF_BUS_MASTER == 0 {
+        return None;
+    }
+
+    // BAR4 is an I/O space BAR, the low bits are flags
+    let base = (dev.read(pci::PCI_BAR4) & 0xFFFC) as u16;
+    if base == 0 {
+        return None;
+    }
+
+    dev.set_command(dev.command() | PciCommand::IO_SPACE | PciCommand::BUS_MASTER);
+    info!("IDE bus master at {base:#x} ({dev:?})");
+    Some(base)
+}
+
+/// The bus master registers and DMA memory of one channel
+#[derive(Debug, Clone)]
+pub(super) struct BusMaster {
+    command: Port<u8>,
+    status: Port<u8>,
+    prdt_addr: Port<u32>,
+    /// Holds the PRDT
+    prdt: PhysFrame,
+    buffer: Vec<PhysFrame>,
+}
+
+impl BusMaster {
+    /// Allocate the PRDT and buffer frames, they must be below 4 GiB
+    ///
+    /// The buffer is one block of frames in a row.
+    pub fn new(base: u16) -> Option<Self> {
+        let order = BUFFER_FRAMES.next_power_of_two().ilog2() as usize;
+        let mut frames = {
+            let mut alloc = get_frame_alloc_for_sure();
+            let prdt = alloc.allocate_frame()?;
+            let buffer = alloc.allocate_frames(order)?;
+            for i in BUFFER_FRAMES..1 << order {
+                alloc.dec_ref(buffer + i as u64);
+            }
+            let frames: Vec<_> = core::iter::once(prdt)
+                .chain((0..BUFFER_FRAMES as u64).map(|i| buffer + i))
+                .collect();
+            for &frame in frames.iter() {
+                alloc.set_owner(frame, FrameOwner::DMA);
+            }
+            frames
+        };
+        if frames.iter().any(|frame| frame.start_address().as_u64() > u32::MAX as u64) {
+            warn!("No DMA memory below 4 GiB.");
+            return None;
+        }
+
+        let prdt = frames.remove(0);
+        Some(Self {
+            command: Port::new(base),
+            status: Port::new(base + 2),
+            prdt_addr: Port::new(base + 4),
+            prdt,
+            buffer: frames,
+        })
+    }
+
+    /// Describe the first `sectors` of the buffer in the PRDT and arm the channel
+    ///
+    /// The transfer begins with `start` once the command is sent to the drive.
+    pub fn prepare(&mut self, sectors: usize) {
+        let prdt = physical_to_virtual(self.prdt.start_address().as_u64()) as *mut u64;
+        let frames = sectors.div_ceil(SECTORS_PER_FRAME);
+
+        for (i, frame) in self.buffer.iter().take(frames).enumerate() {
+            let bytes = (sectors - i * SECTORS_PER_FRAME).min(SECTORS_PER_FRAME) * SECTOR_SIZE;
+            let flags = if i + 1 == frames { PRD_END_OF_TABLE } else { 0 };
+            let entry = frame.start_address().as_u64()
+                | (bytes as u64) << 32
+                | (flags as u64) << 48;
+            unsafe { prdt.add(i).write_volatile(entry) };
+        }
+
+        unsafe {
+            self.prdt_addr.write(self.prdt.start_address().as_u64() as u32);
+            self.command.write(BmCommand::READ.bits());
+            // the error and interrupt bits are cleared by writing 1
+            self.status.write((BmStatus::ERROR | BmStatus::INTERRUPT).bits());
+        }
+    }
+
+    pub fn start(&mut self) {
+        unsafe { self.command.write((BmCommand::READ | BmCommand::START).bits()) };
+    }
+
+    pub fn status(&mut self) -> BmStatus {
+        BmStatus::from_bits_truncate(unsafe { self.status.read() })
+    }
+
+    /// Stop the transfer and clear the status, return the status before
+    pub fn stop(&mut self) -> BmStatus {
+        let status = self.status();
+        unsafe {
+            self.command.write(0);
+            self.status.write((BmStatus::ERROR | BmStatus::INTERRUPT).bits());
+        }
+        status
+    }
+
+    /// Copy the first `sectors` out of the buffer
+    pub fn read_buffer(&self, sectors: usize) -> Vec<Block512> {
+        (0..sectors)
+            .map(|i| {
+                let frame = self.buffer[i / SECTORS_PER_FRAME].start_address().as_u64();
+                let addr = physical_to_virtual(frame) as usize + (i % SECTORS_PER_FRAME) * SECTOR_SIZE;
+                Block512::new(unsafe { &*(addr as *const [u8; SECTOR_SIZE]) })
+            })
+            .collect()
+    }
+}
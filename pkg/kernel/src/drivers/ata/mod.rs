@@ -6,17 +6,30 @@
 
 mod bus;
 mod consts;
+mod dma;
+mod queue;
 
-use alloc::{boxed::Box, string::String};
+use crate::proc::{self, processor, ProcessId};
+use alloc::{boxed::Box, format, string::String, sync::Arc, vec, vec::Vec};
 use bus::AtaBus;
 use consts::AtaDeviceType;
+use queue::{AtaOp, RequestId};
 use spin::Mutex;
+use storage::{Block512, BlockDevice};
+use x86_64::instructions::interrupts;
+
+/// Sectors of one read request, the DMA buffer holds this many
+pub const MAX_SECTORS: usize = 128;
 
 lazy_static! {
     pub static ref BUSES: [Mutex<AtaBus>; 2] = {
+        // the secondary channel registers follow the primary ones
+        let bm_base = dma::probe();
+        let bus_master = |channel: u16| bm_base.and_then(|base| dma::BusMaster::new(base + channel * 8));
+
         let buses = [
-            Mutex::new(AtaBus::new(0, 14, 0x1F0, 0x3F6)),
-            Mutex::new(AtaBus::new(1, 15, 0x170, 0x376)),
+            Mutex::new(AtaBus::new(0, 14, 0x1F0, 0x3F6, bus_master(0))),
+            Mutex::new(AtaBus::new(1, 15, 0x170, 0x376, bus_master(1))),
         ];
 
         info!("Initialized ATA Buses.");
@@ -25,6 +38,73 @@ lazy_static! {
     };
 }
 
+/// The process a request is made for, `None` for the kernel
+///
+/// A process does not wait for its reads: they fail with `WouldBlock`,
+/// the completion interrupt wakes it up and its syscall is restarted.
+fn caller() -> Option<ProcessId> {
+    Some(processor::get_pid()).filter(|&pid| pid != proc::KERNEL_PID)
+}
+
+/// Wait until the queued writes of both buses reach the disks
+///
+/// The write caches of the drives are flushed as well. Returns the first
+/// write failed since the last flush.
+pub fn flush() -> storage::FsResult {
+    let mut ret = Ok(());
+    for bus in 0..BUSES.len() {
+        let drives: Vec<u8> = devices()
+            .iter()
+            .filter_map(|dev| match dev {
+                AtaDevice::Pata(drive) if drive.bus as usize == bus => Some(drive.drive),
+                _ => None,
+            })
+            .collect();
+
+        let result = wait_bus(bus, |b| {
+            b.is_idle().then(|| {
+                let flushed = drives.iter().try_for_each(|&drive| b.flush_cache(drive));
+                b.take_write_error().map_or(flushed, Err)
+            })
+        });
+        ret = ret.and(result);
+    }
+    ret
+}
+
+/// Handle the interrupt of the bus, wake up processes waiting for it
+pub fn handle_interrupt(bus: usize) {
+    let waiters = BUSES[bus].lock().handle_interrupt();
+    proc::wake_up_blocked(waiters);
+}
+
+/// Wait until `f` returns `Some`
+///
+/// The drive is checked on every interrupt in case its own one is missed.
+/// With interrupts disabled, e.g. in a syscall, the drive is polled instead
+/// so the caller is never switched out while waiting.
+fn wait_bus<T>(bus: usize, mut f: impl FnMut(&mut AtaBus) -> Option<T>) -> T {
+    let enabled = interrupts::are_enabled();
+    loop {
+        let ret = interrupts::without_interrupts(|| {
+            let mut bus = BUSES[bus].lock();
+            let waiters = bus.handle_interrupt();
+            let ret = f(&mut bus);
+            drop(bus);
+            proc::wake_up_blocked(waiters);
+            ret
+        });
+        if let Some(ret) = ret {
+            break ret;
+        }
+        if enabled {
+            interrupts::enable_and_hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
 // 根据文档
 pub const ATA_IDENT_SERIAL:usize = 20 ;  // 20 bytes
 pub const ATA_IDENT_SERIAL_SIZE:usize = 20;
@@ -32,59 +112,193 @@ pub const ATA_IDENT_MODEL:usize = 54;   // 40 bytes
 pub const ATA_IDENT_MODEL_SIZE:usize = 40;
 pub const ATA_IDENT_MAX_LBA:usize = 120; // 4 bytes (unsigned int)
 pub const ATA_IDENT_MAX_LBA_SIZE:usize = 4;
+pub const ATA_IDENT_COMMAND_SETS:usize = 166; // word 83, bit 10 is LBA48
+pub const ATA_IDENT_MAX_LBA_EXT:usize = 200; // 8 bytes, words 100-103
+pub const ATA_IDENT_MAX_LBA_EXT_SIZE:usize = 8;
+
+/// Bytes of an ATAPI sector
+pub const ATAPI_SECTOR_SIZE: usize = 2048;
+
+/// SCSI commands sent to ATAPI drives
+const SCSI_READ_CAPACITY: u8 = 0x25;
+const SCSI_READ_10: u8 = 0x28;
+
+/// Devices found on both buses
+static DEVICES: spin::Once<Vec<AtaDevice>> = spin::Once::new();
+
+/// Probe the four bus/drive positions on the first call
+pub fn devices() -> &'static [AtaDevice] {
+    DEVICES.call_once(|| {
+        (0..2)
+            .flat_map(|bus| (0..2).map(move |drive| (bus, drive)))
+            .filter_map(|(bus, drive)| AtaDevice::probe(bus, drive))
+            .collect()
+    })
+}
+
+/// A device attached to an ATA bus
+#[derive(Clone)]
+pub enum AtaDevice {
+    Pata(AtaDrive),
+    /// Packet interface drive, only read
+    Atapi(AtapiDrive),
+}
+
+impl AtaDevice {
+    /// Identify the device at the position, `None` if there is nothing usable
+    pub fn probe(bus: u8, drive: u8) -> Option<Self> {
+        trace!("Probing drive {bus}@{drive}...");
+
+        // IDENTIFY is polled, it can not share the bus with queued requests
+        let identify = wait_bus(bus as usize, |b| b.is_idle().then(|| b.identify_drive(drive)));
+
+        let device = match identify {
+            Ok(AtaDeviceType::Pata(res)) => Self::Pata(AtaDrive::new(bus, drive, &res)),
+            Ok(AtaDeviceType::PataPi(res)) => Self::Atapi(AtapiDrive::new(bus, drive, &res)),
+            Ok(AtaDeviceType::None) | Err(_) => {
+                trace!("No drive at {bus}@{drive}");
+                return None;
+            }
+            Ok(_) => {
+                warn!("Drive {bus}@{drive} is not supported");
+                return None;
+            }
+        };
+        info!("Found {} {}: {}", device.kind(), device.name(), device);
+        Some(device)
+    }
+
+    pub fn bus(&self) -> u8 {
+        match self {
+            Self::Pata(drive) => drive.bus,
+            Self::Atapi(drive) => drive.bus,
+        }
+    }
+
+    pub fn drive(&self) -> u8 {
+        match self {
+            Self::Pata(drive) => drive.drive,
+            Self::Atapi(drive) => drive.drive,
+        }
+    }
+
+    /// `hda`, `hdb` on the primary bus, `hdc`, `hdd` on the secondary one
+    pub fn name(&self) -> String {
+        format!("hd{}", (b'a' + self.bus() * 2 + self.drive()) as char)
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Pata(_) => "disk",
+            Self::Atapi(_) => "cdrom",
+        }
+    }
+
+    pub fn model(&self) -> &str {
+        match self {
+            Self::Pata(drive) => &drive.model,
+            Self::Atapi(drive) => &drive.model,
+        }
+    }
+
+    pub fn serial(&self) -> &str {
+        match self {
+            Self::Pata(drive) => &drive.serial,
+            Self::Atapi(drive) => &drive.serial,
+        }
+    }
+
+    /// Size in bytes
+    pub fn size(&self) -> u64 {
+        match self {
+            Self::Pata(drive) => drive.blocks * 512,
+            Self::Atapi(drive) => drive.blocks as u64 * ATAPI_SECTOR_SIZE as u64,
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, Self::Atapi(_))
+    }
+
+    /// The device in 512-byte blocks
+    pub fn block_device(&self) -> Arc<dyn BlockDevice<Block512>> {
+        match self {
+            Self::Pata(drive) => Arc::new(drive.clone()),
+            Self::Atapi(drive) => Arc::new(drive.clone()),
+        }
+    }
+}
+
+impl core::fmt::Display for AtaDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Pata(drive) => write!(f, "{drive}"),
+            Self::Atapi(drive) => write!(f, "{drive}"),
+        }
+    }
+}
+
+/// A string in the IDENTIFY data, `buf` holds the words in big endian
+fn ident_string(buf: &[u8], offset: usize, size: usize) -> Box<str> {
+    String::from_utf8_lossy(&buf[offset..offset + size])
+        // .trim_end_matches('\0')
+        .trim()
+        .into()
+}
 
 #[derive(Clone)]
 pub struct AtaDrive {
     pub bus: u8,
     pub drive: u8,
-    blocks: u32,
+    blocks: u64,
     model: Box<str>,
     serial: Box<str>,
 }
 
 impl AtaDrive {
     pub fn open(bus: u8, drive: u8) -> Option<Self> {
-        trace!("Opening drive {}@{}...", bus, drive);
-
-        // we only support PATA drives
-        if let Ok(AtaDeviceType::Pata(res)) = BUSES[bus as usize].lock().identify_drive(drive) {
-            let buf = res.map(u16::to_be_bytes).concat();
-            let serial = { /* FIXME: get the serial from buf */ 
-                String::from_utf8_lossy(
-                    &buf[ATA_IDENT_SERIAL..ATA_IDENT_SERIAL + ATA_IDENT_SERIAL_SIZE],
-                )
-                // .trim_end_matches('\0')
-                .trim()
-                .into()
-            };
-            let model = { /* FIXME: get the model from buf */ 
-                String::from_utf8_lossy(
-                    &buf[ATA_IDENT_MODEL..ATA_IDENT_MODEL + ATA_IDENT_MODEL_SIZE],
-                )
-                // .trim_end_matches('\0')
-                .trim()
-                .into()
-            };
-            let blocks = { /* FIXME: get the block count from buf */ 
-                u32::from_be_bytes(
-                    buf[ATA_IDENT_MAX_LBA..ATA_IDENT_MAX_LBA + ATA_IDENT_MAX_LBA_SIZE]
-                        .try_into()
-                        .unwrap_or([0; ATA_IDENT_MAX_LBA_SIZE])
-                )
-                .rotate_left(16)
-            };
-            let ata_drive = Self {
-                bus,
-                drive,
-                model,
-                serial,
-                blocks,
-            };
-            info!("Drive {} opened", ata_drive);
-            Some(ata_drive)
-        } else {
-            warn!("Drive {}@{} is not a PATA drive", bus, drive);
-            None
+        match AtaDevice::probe(bus, drive) {
+            Some(AtaDevice::Pata(drive)) => Some(drive),
+            _ => {
+                warn!("Drive {bus}@{drive} is not a PATA drive");
+                None
+            }
+        }
+    }
+
+    /// Set up the drive from its IDENTIFY data
+    fn new(bus: u8, drive: u8, res: &[u16; 256]) -> Self {
+        // the low byte of word 47 is the most sectors per interrupt
+        let multiple = res[47] as u8;
+        wait_bus(bus as usize, |b| b.is_idle().then(|| b.set_multiple(drive, multiple)))
+            .unwrap_or_else(|err| warn!("Drive {bus}@{drive}: READ MULTIPLE disabled: {err:?}"));
+
+        let buf = res.map(u16::to_be_bytes).concat();
+        let serial = ident_string(&buf, ATA_IDENT_SERIAL, ATA_IDENT_SERIAL_SIZE);
+        let model = ident_string(&buf, ATA_IDENT_MODEL, ATA_IDENT_MODEL_SIZE);
+        let lba48 = u16::from_be_bytes([buf[ATA_IDENT_COMMAND_SETS], buf[ATA_IDENT_COMMAND_SETS + 1]]) & (1 << 10) != 0;
+        // words are little endian, their bytes are swapped in `buf`
+        let blocks = if lba48 {
+            buf[ATA_IDENT_MAX_LBA_EXT..ATA_IDENT_MAX_LBA_EXT + ATA_IDENT_MAX_LBA_EXT_SIZE]
+                .chunks(2)
+                .rev()
+                .fold(0u64, |blocks, word| blocks << 16 | u16::from_be_bytes([word[0], word[1]]) as u64)
+        } else { /* FIXME: get the block count from buf */ 
+            u32::from_be_bytes(
+                buf[ATA_IDENT_MAX_LBA..ATA_IDENT_MAX_LBA + ATA_IDENT_MAX_LBA_SIZE]
+                    .try_into()
+                    .unwrap_or([0; ATA_IDENT_MAX_LBA_SIZE])
+            )
+            .rotate_left(16) as u64
+        };
+        wait_bus(bus as usize, |b| b.is_idle().then(|| b.set_lba48(drive, lba48)));
+
+        Self {
+            bus,
+            drive,
+            model,
+            serial,
+            blocks,
         }
     }
 
@@ -107,8 +321,6 @@ impl core::fmt::Display for AtaDrive {
     }
 }
 
-use storage::{Block512, BlockDevice};
-
 impl BlockDevice<Block512> for AtaDrive {
     fn block_count(&self) -> storage::FsResult<usize> {
         // FIXME: return the block count
@@ -116,44 +328,159 @@ impl BlockDevice<Block512> for AtaDrive {
     }
 
     fn read_block(&self, offset: usize, block: &mut Block512) -> storage::FsResult {
-        // FIXME: read the block
-        //      - use `BUSES` and `self` to get bus
-        //      - use `read_pio` to get data
-        BUSES[self.bus as usize]
-            .lock()
-            .read_pio(
-                self.drive, 
-                offset as u32, 
-                block.as_mut()
-            )
-            // .map_err(|e| e.into())
-            // .and_then(|_| {
-            //     if block.len() != 512 {
-            //         Err(storage::DeviceError::ReadError.into())
-            //     } else {
-            //         Ok(())
-            //     }
-            // })
+        self.read_blocks(offset, core::slice::from_mut(block))
     }
 
+    fn read_blocks(&self, offset: usize, blocks: &mut [Block512]) -> storage::FsResult {
+        let drive = self.drive;
+        let runs: Vec<(u64, u16)> = (0..blocks.len())
+            .step_by(MAX_SECTORS)
+            .map(|i| ((offset + i) as u64, (blocks.len() - i).min(MAX_SECTORS) as u16))
+            .collect();
+
+        let waiter = caller();
+        let ready = interrupts::without_interrupts(|| {
+            let mut bus = BUSES[self.bus as usize].lock();
+            // a restarted read goes on once all of its requests are finished
+            if waiter.is_some() {
+                let missing: Vec<_> = runs
+                    .iter()
+                    .filter(|&&(lba, count)| !bus.is_read_done(drive, lba, count))
+                    .collect();
+                for &&(lba, count) in missing.iter() {
+                    bus.submit(drive, lba, AtaOp::Read(count), waiter);
+                }
+                if !missing.is_empty() {
+                    return None;
+                }
+            }
+
+            let ready: Vec<_> = runs
+                .iter()
+                .map(|&(lba, count)| {
+                    bus.take_read(drive, lba, count)
+                        .ok_or_else(|| bus.submit(drive, lba, AtaOp::Read(count), None))
+                })
+                .collect();
+            Some(ready)
+        });
+
+        let Some(ready) = ready else {
+            return Err(storage::DeviceError::WouldBlock.into());
+        };
+        for (ready, chunk) in ready.into_iter().zip(blocks.chunks_mut(MAX_SECTORS)) {
+            let data = match ready {
+                // finished while the process was blocked
+                Ok(data) => data?,
+                Err(id) => self.wait(id)?,
+            };
+            if data.len() != chunk.len() {
+                return Err(storage::DeviceError::ReadError.into());
+            }
+            chunk.clone_from_slice(&data);
+        }
+        Ok(())
+    }
+
+    /// Queue the write, nobody waits for it to finish
+    ///
+    /// Reads of the block queued later are served after it. The writer waits
+    /// for the drive while the queue is full, a failed write is returned by `flush`.
     fn write_block(&self, offset: usize, block: &Block512) -> storage::FsResult {
-        // FIXME: write the block
-        //      - use `BUSES` and `self` to get bus
-        //      - use `write_pio` to write data
-        BUSES[self.bus as usize]
-            .lock()
-            .write_pio(
-                self.drive,
-                offset as u32,
-                block.as_ref()
-            )
-            // .map_err(|e| e.into())
-            // .and_then(|_| {
-            //     if block.len() != 512 {
-            //         Err(storage::DeviceError::WriteError.into())
-            //     } else {
-            //         Ok(())
-            //     }
-            // })
+        wait_bus(self.bus as usize, |bus| {
+            (!bus.is_write_full()).then(|| {
+                let op = AtaOp::Write(Box::new(block.clone()));
+                bus.submit(self.drive, offset as u64, op, None)
+            })
+        });
+        Ok(())
+    }
+}
+
+impl AtaDrive {
+    /// Wait until the request is finished, only the kernel waits here
+    fn wait(&self, id: RequestId) -> storage::FsResult<Vec<Block512>> {
+        wait_bus(self.bus as usize, |bus| bus.take(id))
+    }
+}
+
+/// A packet interface drive, e.g. a CD-ROM
+///
+/// Each sector of the media is 4 blocks, the drive is polled and never written.
+#[derive(Clone)]
+pub struct AtapiDrive {
+    pub bus: u8,
+    pub drive: u8,
+    /// Sectors of the media, 0 if there is none
+    blocks: u32,
+    model: Box<str>,
+    serial: Box<str>,
+}
+
+impl AtapiDrive {
+    /// Set up the drive from its IDENTIFY PACKET data
+    fn new(bus: u8, drive: u8, res: &[u16; 256]) -> Self {
+        let buf = res.map(u16::to_be_bytes).concat();
+        let mut atapi = Self {
+            bus,
+            drive,
+            blocks: 0,
+            model: ident_string(&buf, ATA_IDENT_MODEL, ATA_IDENT_MODEL_SIZE),
+            serial: ident_string(&buf, ATA_IDENT_SERIAL, ATA_IDENT_SERIAL_SIZE),
+        };
+
+        // the last LBA and the sector size, both in big endian
+        let mut capacity = [0u8; 8];
+        match atapi.send_packet(&[SCSI_READ_CAPACITY, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], &mut capacity) {
+            Ok(()) => {
+                let last = u32::from_be_bytes(capacity[..4].try_into().unwrap());
+                let size = u32::from_be_bytes(capacity[4..].try_into().unwrap());
+                if size as usize == ATAPI_SECTOR_SIZE {
+                    atapi.blocks = last + 1;
+                } else {
+                    warn!("Drive {bus}@{drive}: unsupported sector size {size}");
+                }
+            }
+            Err(err) => debug!("Drive {bus}@{drive}: no media: {err:?}"),
+        }
+        atapi
+    }
+
+    fn send_packet(&self, packet: &[u8; 12], buf: &mut [u8]) -> storage::FsResult {
+        let drive = self.drive;
+        wait_bus(self.bus as usize, |b| b.is_idle().then(|| b.send_packet(drive, packet, buf)))
+    }
+}
+
+impl core::fmt::Display for AtapiDrive {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let (size, unit) = crate::humanized_size(self.blocks as u64 * ATAPI_SECTOR_SIZE as u64);
+        write!(f, "{} {} ({} {})", self.model, self.serial, size as f32, unit)
+    }
+}
+
+impl BlockDevice<Block512> for AtapiDrive {
+    fn block_count(&self) -> storage::FsResult<usize> {
+        Ok(self.blocks as usize * (ATAPI_SECTOR_SIZE / 512))
+    }
+
+    fn read_block(&self, offset: usize, block: &mut Block512) -> storage::FsResult {
+        const PER_SECTOR: usize = ATAPI_SECTOR_SIZE / 512;
+        if offset >= self.block_count()? {
+            return Err(storage::DeviceError::ReadError.into());
+        }
+
+        let lba = ((offset / PER_SECTOR) as u32).to_be_bytes();
+        let packet = [SCSI_READ_10, 0, lba[0], lba[1], lba[2], lba[3], 0, 0, 1, 0, 0, 0];
+        let mut sector = vec![0u8; ATAPI_SECTOR_SIZE];
+        self.send_packet(&packet, &mut sector)?;
+
+        let start = offset % PER_SECTOR * 512;
+        block.as_mut().copy_from_slice(&sector[start..start + 512]);
+        Ok(())
+    }
+
+    fn write_block(&self, _offset: usize, _block: &Block512) -> storage::FsResult {
+        Err(storage::FsError::ReadOnly)
     }
 }
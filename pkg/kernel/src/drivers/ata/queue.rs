@@ -0,0 +1,204 @@
+//! ATA Request Queue
+//!
+//! Requests of a bus are served one at a time in FIFO order, the completion
+//! interrupt finishes the running request and starts the next one.
+//! Nobody waits for writes, only the results of reads and the first failed
+//! write are kept.
+
+use crate::proc::ProcessId;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use storage::{Block512, FsError, FsResult};
+
+pub type RequestId = usize;
+
+/// Finished requests kept for waiters which never come back, e.g. killed ones
+const MAX_DONE: usize = 64;
+
+/// Queued writes of a bus, more have to wait for the drive
+const MAX_WRITES: usize = 64;
+
+#[derive(Debug, Clone)]
+pub enum AtaOp {
+    /// Read the given number of sectors
+    Read(u16),
+    /// Write a sector, boxed to keep queued reads small
+    Write(Box<Block512>),
+}
+
+impl AtaOp {
+    pub fn count(&self) -> u16 {
+        match self {
+            AtaOp::Read(count) => *count,
+            AtaOp::Write(_) => 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AtaRequest {
+    pub id: RequestId,
+    pub drive: u8,
+    pub block: u64,
+    pub op: AtaOp,
+    /// Processes blocked until the request is finished
+    pub waiters: BTreeSet<ProcessId>,
+}
+
+impl AtaRequest {
+    fn overlaps(&self, drive: u8, block: u64, count: u16) -> bool {
+        overlaps((self.drive, self.block, self.op.count()), (drive, block, count))
+    }
+}
+
+/// Data of a finished read
+#[derive(Debug, Clone)]
+struct Finished {
+    drive: u8,
+    block: u64,
+    count: u16,
+    result: FsResult<Vec<Block512>>,
+}
+
+fn overlaps(a: (u8, u64, u16), b: (u8, u64, u16)) -> bool {
+    let end = |block: u64, count: u16| block + count as u64;
+    a.0 == b.0 && a.1 < end(b.1, b.2) && b.1 < end(a.1, a.2)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestQueue {
+    next_id: RequestId,
+    /// The front request is on the drive if `running` is set
+    pending: VecDeque<AtaRequest>,
+    running: bool,
+    done: BTreeMap<RequestId, Finished>,
+    /// The first write failed since it was taken
+    write_error: Option<FsError>,
+}
+
+impl RequestQueue {
+    /// Queue a request, a read of the same sectors already queued is merged into it
+    pub fn submit(
+        &mut self,
+        drive: u8,
+        block: u64,
+        op: AtaOp,
+        waiter: Option<ProcessId>,
+    ) -> RequestId {
+        let count = op.count();
+        match op {
+            // a read behind a queued write of the sectors has to wait for it
+            AtaOp::Read(_) => {
+                let last = self
+                    .pending
+                    .iter_mut()
+                    .rev()
+                    .find(|req| req.overlaps(drive, block, count))
+                    .filter(|req| req.block == block && matches!(req.op, AtaOp::Read(n) if n == count));
+                if let Some(req) = last {
+                    req.waiters.extend(waiter);
+                    return req.id;
+                }
+            }
+            // results of earlier reads are stale now
+            AtaOp::Write(_) => self.done.retain(|_, req| {
+                !overlaps((req.drive, req.block, req.count), (drive, block, count))
+            }),
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push_back(AtaRequest {
+            id,
+            drive,
+            block,
+            op,
+            waiters: waiter.into_iter().collect(),
+        });
+        id
+    }
+
+    /// The request on the drive
+    pub fn running(&self) -> Option<&AtaRequest> {
+        self.pending.front().filter(|_| self.running)
+    }
+
+    /// The next request to put on the drive, `None` if the bus is busy or idle
+    pub fn start(&mut self) -> Option<AtaRequest> {
+        if self.running {
+            return None;
+        }
+        let req = self.pending.front()?.clone();
+        self.running = true;
+        Some(req)
+    }
+
+    /// Finish the front request, return its waiters
+    pub fn finish(&mut self, result: FsResult<Vec<Block512>>) -> BTreeSet<ProcessId> {
+        self.running = false;
+        let Some(req) = self.pending.pop_front() else {
+            return BTreeSet::new();
+        };
+
+        // nobody waits for a write
+        let AtaOp::Read(count) = req.op else {
+            if let Err(err) = result {
+                warn!("ATA write of block {} failed: {err:?}", req.block);
+                self.write_error.get_or_insert(err);
+            }
+            return req.waiters;
+        };
+
+        if self.done.len() >= MAX_DONE {
+            self.done.pop_first();
+        }
+        self.done.insert(
+            req.id,
+            Finished {
+                drive: req.drive,
+                block: req.block,
+                count,
+                result,
+            },
+        );
+        req.waiters
+    }
+
+    /// Take the result of the request, `None` if not finished yet
+    pub fn take(&mut self, id: RequestId) -> Option<FsResult<Vec<Block512>>> {
+        self.done.remove(&id).map(|req| req.result)
+    }
+
+    /// The finished read of the sectors
+    fn find_read(&self, drive: u8, block: u64, count: u16) -> Option<RequestId> {
+        self.done.iter().find_map(|(&id, req)| {
+            ((req.drive, req.block, req.count) == (drive, block, count)).then_some(id)
+        })
+    }
+
+    pub fn is_read_done(&self, drive: u8, block: u64, count: u16) -> bool {
+        self.find_read(drive, block, count).is_some()
+    }
+
+    /// Take the data of a finished read of the sectors
+    pub fn take_read(&mut self, drive: u8, block: u64, count: u16) -> Option<FsResult<Vec<Block512>>> {
+        let id = self.find_read(drive, block, count)?;
+        self.take(id)
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// No more writes are taken until some of the queued ones are finished
+    pub fn is_write_full(&self) -> bool {
+        let writes = self.pending.iter().filter(|req| matches!(req.op, AtaOp::Write(_)));
+        writes.count() >= MAX_WRITES
+    }
+
+    /// Take the first write failed since the last call
+    pub fn take_write_error(&mut self) -> Option<FsError> {
+        self.write_error.take()
+    }
+}
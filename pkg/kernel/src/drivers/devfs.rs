@@ -0,0 +1,325 @@
+//! Device File System
+//!
+//! Kernel devices exposed as files under `/dev`, they are opened and read
+//! through the normal file path.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use storage::*;
+use x86_64::instructions::interrupts;
+
+use super::{input, serial::get_serial};
+use crate::proc::{processor, KERNEL_PID};
+
+/// A device node of the devfs
+#[derive(Clone)]
+pub enum Device {
+    /// Discards writes, reads return EOF
+    Null,
+    /// Discards writes, reads return zeros
+    Zero,
+    /// Reads return pseudo random bytes
+    Random,
+    /// Keyboard input and screen output
+    Console,
+    /// Raw bytes of the serial port
+    Serial,
+    /// Byte access of a block device
+    Block(Arc<dyn BlockDevice<Block512>>),
+    /// Read-only byte access of a whole drive, its partitions are written
+    /// through their caches only
+    Drive(Arc<dyn BlockDevice<Block512>>),
+}
+
+impl Device {
+    /// Size in bytes, 0 for character devices
+    fn len(&self) -> usize {
+        match self {
+            Device::Block(dev) | Device::Drive(dev) => {
+                dev.block_count().unwrap_or(0) * Block512::size()
+            }
+            _ => 0,
+        }
+    }
+}
+
+impl core::fmt::Debug for Device {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Device::Null => write!(f, "Null"),
+            Device::Zero => write!(f, "Zero"),
+            Device::Random => write!(f, "Random"),
+            Device::Console => write!(f, "Console"),
+            Device::Serial => write!(f, "Serial"),
+            Device::Block(_) => write!(f, "Block({} bytes)", self.len()),
+            Device::Drive(_) => write!(f, "Drive({} bytes)", self.len()),
+        }
+    }
+}
+
+/// The devfs, a flat directory of devices
+#[derive(Debug)]
+pub struct DevFs {
+    devices: spin::RwLock<BTreeMap<String, Device>>,
+    created: FsTime,
+}
+
+impl DevFs {
+    /// Create the devfs with the character devices
+    pub fn new() -> Self {
+        let devices = [
+            ("null", Device::Null),
+            ("zero", Device::Zero),
+            ("random", Device::Random),
+            ("console", Device::Console),
+            ("serial0", Device::Serial),
+        ]
+        .into_iter()
+        .map(|(name, dev)| (String::from(name), dev))
+        .collect();
+
+        Self {
+            devices: spin::RwLock::new(devices),
+            created: now(),
+        }
+    }
+
+    /// Add a device, replace the old one with the same name
+    pub fn register(&self, name: &str, dev: Device) {
+        self.devices.write().insert(name.into(), dev);
+    }
+
+    fn get(&self, path: &str) -> FsResult<Device> {
+        self.devices
+            .read()
+            .get(path.trim_matches('/'))
+            .cloned()
+            .ok_or(FsError::FileNotFound)
+    }
+
+    fn meta(&self, name: &str, dev: &Device) -> Metadata {
+        Metadata::new(
+            name.into(),
+            FileType::File,
+            dev.len(),
+            Some(self.created),
+            Some(self.created),
+            Some(self.created),
+        )
+    }
+}
+
+impl Default for DevFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for DevFs {
+    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+        if !path.trim_matches('/').is_empty() {
+            return Err(FsError::NotADirectory);
+        }
+
+        let entries: Vec<Metadata> = self
+            .devices
+            .read()
+            .iter()
+            .map(|(name, dev)| self.meta(name, dev))
+            .collect();
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
+        let dev = self.get(path)?;
+        let meta = self.meta(path.trim_matches('/'), &dev);
+        Ok(FileHandle::new(meta, Box::new(DevFile { dev, offset: 0 })))
+    }
+
+    fn metadata(&self, path: &str) -> FsResult<Metadata> {
+        if path.trim_matches('/').is_empty() {
+            return Ok(Metadata::new(
+                String::new(),
+                FileType::Directory,
+                0,
+                Some(self.created),
+                Some(self.created),
+                Some(self.created),
+            ));
+        }
+
+        let dev = self.get(path)?;
+        Ok(self.meta(path.trim_matches('/'), &dev))
+    }
+
+    fn exists(&self, path: &str) -> FsResult<bool> {
+        Ok(path.trim_matches('/').is_empty() || self.get(path).is_ok())
+    }
+
+    // devices can be written, there is nothing to truncate
+    fn create_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.open_file(path)
+    }
+
+    fn append_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.open_file(path)
+    }
+}
+
+/// An opened device
+struct DevFile {
+    dev: Device,
+    /// Offset of block devices
+    offset: usize,
+}
+
+impl Read for DevFile {
+    fn read(&mut self, buf: &mut [u8]) -> FsResult<usize> {
+        match &self.dev {
+            Device::Null => Ok(0),
+            Device::Zero => {
+                buf.fill(0);
+                Ok(buf.len())
+            }
+            Device::Random => {
+                fill_random(buf);
+                Ok(buf.len())
+            }
+            // input from both keyboard and serial goes to the input buffer,
+            // the read syscall is restarted by the next key
+            Device::Console | Device::Serial => match buf.first_mut() {
+                None => Ok(0),
+                Some(byte) => match input::pop_key_or_wait() {
+                    Some(key) => {
+                        *byte = key;
+                        Ok(1)
+                    }
+                    // the kernel does not wait for input
+                    None if processor::get_pid() == KERNEL_PID => Ok(0),
+                    None => Err(DeviceError::WouldBlock.into()),
+                },
+            },
+            Device::Block(dev) | Device::Drive(dev) => {
+                let len = buf.len().min(self.dev.len().saturating_sub(self.offset));
+                let mut block = Block512::default();
+                let mut read = 0;
+
+                while read < len {
+                    let (idx, start) = (self.offset / 512, self.offset % 512);
+                    match dev.read_block(idx, &mut block) {
+                        Ok(()) => {}
+                        // return what has been read, the error shows up on the next read
+                        Err(_) if read > 0 => break,
+                        Err(err) => return Err(err),
+                    }
+                    let count = (512 - start).min(len - read);
+                    buf[read..read + count].copy_from_slice(&block[start..start + count]);
+                    read += count;
+                    self.offset += count;
+                }
+
+                Ok(read)
+            }
+        }
+    }
+}
+
+impl Write for DevFile {
+    fn write(&mut self, buf: &[u8]) -> FsResult<usize> {
+        match &self.dev {
+            Device::Null | Device::Zero | Device::Random => Ok(buf.len()),
+            Device::Console => {
+                print!("{}", String::from_utf8_lossy(buf));
+                Ok(buf.len())
+            }
+            Device::Serial => {
+                interrupts::without_interrupts(|| {
+                    if let Some(mut serial) = get_serial() {
+                        buf.iter().for_each(|&byte| serial.send(byte));
+                    }
+                });
+                Ok(buf.len())
+            }
+            Device::Drive(_) => Err(FsError::ReadOnly),
+            Device::Block(dev) => {
+                let len = buf.len().min(self.dev.len().saturating_sub(self.offset));
+                if len == 0 && !buf.is_empty() {
+                    return Err(FsError::WriteZero);
+                }
+
+                let mut block = Block512::default();
+                let mut written = 0;
+
+                // read-modify-write for partial blocks
+                while written < len {
+                    let (idx, start) = (self.offset / 512, self.offset % 512);
+                    let count = (512 - start).min(len - written);
+                    if count < 512 {
+                        dev.read_block(idx, &mut block)?;
+                    }
+                    block[start..start + count].copy_from_slice(&buf[written..written + count]);
+                    dev.write_block(idx, &block)?;
+                    written += count;
+                    self.offset += count;
+                }
+
+                Ok(written)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> FsResult {
+        Ok(())
+    }
+}
+
+impl Seek for DevFile {
+    fn seek(&mut self, pos: SeekFrom) -> FsResult<usize> {
+        let (Device::Block(_) | Device::Drive(_)) = self.dev else {
+            return Err(FsError::NotSupported);
+        };
+
+        let len = self.dev.len();
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+        };
+
+        match offset {
+            Some(offset) if offset <= len => {
+                self.offset = offset;
+                Ok(offset)
+            }
+            _ => Err(FsError::InvalidOffset),
+        }
+    }
+}
+
+static RANDOM_STATE: spin::Mutex<u64> = spin::Mutex::new(0);
+
+/// Fill the buffer with RDRAND, or xorshift seeded by the TSC if not supported
+fn fill_random(buf: &mut [u8]) {
+    let rdrand = x86_64::instructions::random::RdRand::new();
+    let mut state = RANDOM_STATE.lock();
+
+    for chunk in buf.chunks_mut(8) {
+        let value = match rdrand.and_then(|r| r.get_u64()) {
+            Some(value) => value,
+            None => {
+                if *state == 0 {
+                    *state = unsafe { core::arch::x86_64::_rdtsc() } | 1;
+                }
+                *state ^= *state << 13;
+                *state ^= *state >> 7;
+                *state ^= *state << 17;
+                *state
+            }
+        };
+        chunk.copy_from_slice(&value.to_le_bytes()[..chunk.len()]);
+    }
+}
@@ -1,44 +1,324 @@
-use super::ata::*;
+use super::ata::{self, AtaDevice, AtaDrive};
+use super::devfs::{DevFs, Device};
+use crate::proc::procfs::ProcFs;
 use alloc::boxed::Box;
 use chrono::DateTime;
 use storage::fat16::Fat16;
+use storage::fat32::Fat32;
+use storage::gpt::*;
+use storage::tmpfs::TmpFs;
 use storage::mbr::*;
 use storage::*;
 use alloc::format;
 use crate::alloc::string::ToString;
-pub static ROOTFS: spin::Once<Mount> = spin::Once::new();
+use syscall_def::{FileKind, FileStat, OpenFlags, SysError};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::string::String;
+use alloc::vec::Vec;
 
-pub fn get_rootfs() -> &'static Mount {
-    ROOTFS.get().unwrap()
+/// Max bytes of file data kept in `/tmp`
+const TMPFS_SIZE: usize = 2 * 1024 * 1024;
+
+/// Blocks cached for each partition
+const BLOCK_CACHE_SIZE: usize = 256;
+
+pub type DiskPartition = Partition<AtaDrive, Block512>;
+
+/// A partition behind its block cache, shared by the filesystem and devfs
+pub type CachedPartition = Arc<CachedBlockDevice<DiskPartition, Block512>>;
+
+/// The mount table of the whole system
+pub static VFS: MountTable = MountTable::new();
+
+/// Partitions found on the disks, named `hda1`, `hda2`, `hdb1`...
+static PARTITIONS: spin::Once<Vec<(String, CachedPartition)>> = spin::Once::new();
+
+/// Mount point -> (source, type), a partition can be mounted only once
+static MOUNTED: spin::Mutex<BTreeMap<Box<str>, (String, &'static str)>> =
+    spin::Mutex::new(BTreeMap::new());
+
+pub fn get_vfs() -> &'static MountTable {
+    &VFS
 }
 
 pub fn init() {
-    info!("Opening disk device...");
-
-    let drive = AtaDrive::open(0, 0).expect("Failed to open disk device");
+    info!("Opening disk devices...");
 
-    // only get the first partition
-    let part = MbrTable::parse(drive)
-        .expect("Failed to parse MBR")
-        .partitions()
-        .expect("Failed to get partitions")
-        .remove(0);
+    // partitions of every disk are named after it, `hda1`, `hdb1`...
+    let partitions = PARTITIONS.call_once(|| {
+        let mut found = Vec::new();
+        for device in ata::devices() {
+            let AtaDevice::Pata(drive) = device else {
+                continue;
+            };
+            match read_partitions(drive) {
+                Ok(parts) => found.extend(parts.into_iter().enumerate().map(|(i, part)| {
+                    let cached = Arc::new(CachedBlockDevice::new(part, BLOCK_CACHE_SIZE));
+                    (format!("{}{}", device.name(), i + 1), cached)
+                })),
+                Err(err) => warn!("No partitions on {}: {:?}", device.name(), err),
+            }
+        }
+        found
+    });
 
     info!("Mounting filesystem...");
 
-    ROOTFS.call_once(|| Mount::new(Box::new(Fat16::new(part)), "/".into()));
+    storage::set_clock(|| crate::interrupt::clock::current_datetime().and_utc());
+
+    // the first partition of the first disk is the root, others go to /mnt/<name>
+    for (i, (name, _)) in partitions.iter().enumerate() {
+        let target = match i {
+            0 => String::from("/"),
+            _ => format!("/mnt/{name}"),
+        };
+
+        match mount(name, &target) {
+            Ok(()) => info!("Mounted {name} at {target}"),
+            Err(err) if i == 0 => panic!("Failed to mount root filesystem: {:?}", err),
+            Err(err) => warn!("Failed to mount {name}: {err:?}"),
+        }
+    }
+
+    // the drives and their partitions are also reachable as /dev/hd*,
+    // a whole drive is read-only and not cached, it may see stale partition data
+    let devfs = DevFs::new();
+    for device in ata::devices() {
+        devfs.register(&device.name(), Device::Drive(device.block_device()));
+    }
+    for (name, part) in partitions.iter() {
+        devfs.register(name, Device::Block(part.clone()));
+    }
+
+    let virtual_fs: [(Box<dyn FileSystem>, &str, &str); 3] = [
+        (Box::new(devfs), "devfs", "/dev"),
+        (Box::new(TmpFs::new(TMPFS_SIZE)), "tmpfs", "/tmp"),
+        (Box::new(ProcFs), "proc", "/proc"),
+    ];
+    for (fs, fs_type, target) in virtual_fs {
+        mount_fs(fs, fs_type, fs_type, target).expect("Failed to mount virtual filesystem");
+        info!("Mounted {fs_type} at {target}");
+    }
 
-    trace!("Root filesystem: {:#?}", ROOTFS.get().unwrap());
+    trace!("Mount table: {VFS:#?}");
 
     info!("Initialized Filesystem.");
 }
 
-pub fn ls(root_path: &str) {
-    let iter = match get_rootfs().read_dir(root_path) {
+/// Partitions of the disk, from its GPT or else its MBR
+fn read_partitions(drive: &AtaDrive) -> FsResult<Vec<DiskPartition>> {
+    match GptTable::parse(drive.clone()) {
+        Ok(gpt) => {
+            info!("Found GPT partition table.");
+            gpt.partitions()
+        }
+        Err(err) => {
+            debug!("No valid GPT: {err:?}, trying MBR");
+            MbrTable::parse(drive.clone())?.partitions()
+        }
+    }
+}
+
+/// Open the FAT filesystem on a partition, return it with its type name
+fn open_fs(part: CachedPartition) -> FsResult<(Box<dyn FileSystem>, &'static str)> {
+    let fat_type = FatType::detect(&part)?;
+    info!("Detected {fat_type:?} filesystem.");
+
+    match fat_type {
+        FatType::Fat16 => Ok((Box::new(Fat16::new(part)), "fat16")),
+        FatType::Fat32 => Ok((Box::new(Fat32::new(part)), "fat32")),
+        FatType::Fat12 => Err(FsError::NotSupported),
+    }
+}
+
+/// Add `fs` to the mount table and record where it comes from
+fn mount_fs(fs: Box<dyn FileSystem>, source: &str, fs_type: &'static str, target: &str) -> FsResult {
+    let point = MountTable::normalize(target)?;
+    VFS.mount(fs, &point)?;
+    MOUNTED.lock().insert(point, (source.into(), fs_type));
+    Ok(())
+}
+
+/// Mount the partition `source` (`hda1` or `/dev/hda1`) at `target`
+pub fn mount(source: &str, target: &str) -> FsResult {
+    let name = source.trim_start_matches("/dev/");
+    let part = PARTITIONS
+        .get()
+        .and_then(|parts| parts.iter().find(|(n, _)| n == name))
+        .map(|(_, part)| part.clone())
+        .ok_or(FsError::FileNotFound)?;
+
+    if MOUNTED.lock().values().any(|(n, _)| n == name) {
+        return Err(DeviceError::Busy.into());
+    }
+
+    load_reserved(&part)?;
+    let (fs, fs_type) = open_fs(part)?;
+    mount_fs(fs, name, fs_type, target)
+}
+
+/// Load the reserved sectors of a FAT partition into its cache
+///
+/// The filesystem constructors read the boot sector and the FSInfo there
+/// and can not fail, a read which would block has to happen before them.
+fn load_reserved(part: &CachedPartition) -> FsResult {
+    let mut boot = Block512::default();
+    part.read_block(0, &mut boot)?;
+
+    // BPB_RsvdSecCnt
+    let reserved = u16::from_le_bytes([boot[14], boot[15]]) as usize;
+    let mut blocks = alloc::vec![Block512::default(); reserved.clamp(1, BLOCK_CACHE_SIZE / 2)];
+    part.read_blocks(0, &mut blocks)
+}
+
+/// Detach the filesystem mounted at `target`, the root can not be detached
+pub fn umount(target: &str) -> FsResult {
+    if target.trim_end_matches('/').is_empty() {
+        return Err(DeviceError::Busy.into());
+    }
+
+    let mount = VFS.umount(target)?;
+    MOUNTED.lock().remove(&mount.mount_point);
+    sync()
+}
+
+/// Write dirty cached blocks of all partitions to the disk
+/// Write back the cached blocks and wait until they reach the disks
+///
+/// Returns the first disk write failed since the last sync.
+pub fn sync() -> FsResult {
+    for (_, part) in PARTITIONS.get().into_iter().flatten() {
+        part.sync()?;
+    }
+    ata::flush()
+}
+
+/// Block cache statistics of each partition
+pub fn cache_stats() -> Vec<(String, CacheStats)> {
+    PARTITIONS
+        .get()
+        .into_iter()
+        .flatten()
+        .map(|(name, part)| (name.clone(), part.stats()))
+        .collect()
+}
+
+/// Mounted filesystems as `(source, mount point, type)`
+pub fn mounts() -> Vec<(String, String, &'static str)> {
+    MOUNTED
+        .lock()
+        .iter()
+        .map(|(point, (source, fs_type))| (source.clone(), point.to_string(), *fs_type))
+        .collect()
+}
+
+/// Map a storage error to the syscall error code
+pub fn fs_error_to_sys(err: FsError) -> SysError {
+    match err {
+        FsError::FileNotFound => SysError::NotFound,
+        FsError::NotADirectory => SysError::NotADirectory,
+        FsError::NotAFile => SysError::IsADirectory,
+        FsError::ReadOnly => SysError::ReadOnly,
+        FsError::WriteZero => SysError::NoSpace,
+        FsError::NotSupported => SysError::NotSupported,
+        FsError::InvalidOffset => SysError::InvalidSeek,
+        FsError::AlreadyExists => SysError::AlreadyExists,
+        FsError::DirectoryNotEmpty => SysError::NotEmpty,
+        FsError::InvalidOperation | FsError::InvalidPath(_) => SysError::InvalidArgument,
+        FsError::FileNameError(FilenameError::NameTooLong) => SysError::NameTooLong,
+        FsError::FileNameError(_) => SysError::InvalidArgument,
+        FsError::DeviceError(DeviceError::Busy) => SysError::Busy,
+        FsError::DeviceError(DeviceError::WouldBlock) => SysError::WouldBlock,
+        FsError::NotInSector
+        | FsError::EndOfFile
+        | FsError::BadCluster
+        | FsError::InvalidPartitionTable
+        | FsError::DeviceError(_) => SysError::IoError,
+    }
+}
+
+/// Run `f` with its writes to the partitions kept aside until it is done
+///
+/// A read which would block fails `f` halfway, its writes are dropped then
+/// so the restarted syscall runs it again from the start. Not nested.
+pub fn atomic<T>(f: impl FnOnce() -> FsResult<T>) -> FsResult<T> {
+    let parts = PARTITIONS.get().map(Vec::as_slice).unwrap_or_default();
+    for (_, part) in parts {
+        part.begin();
+    }
+
+    let ret = f();
+    let would_block = matches!(ret, Err(FsError::DeviceError(DeviceError::WouldBlock)));
+    for (name, part) in parts {
+        if would_block {
+            part.rollback();
+        } else if let Err(err) = part.commit() {
+            warn!("Failed to write to {name}: {err:?}");
+        }
+    }
+    ret
+}
+
+/// Open a file of the VFS according to `flags`
+pub fn open_file(path: &str, flags: OpenFlags) -> FsResult<FileHandle> {
+    let fs = get_vfs();
+
+    atomic(|| match fs.exists(path)? {
+        true if flags.contains(OpenFlags::TRUNCATE) => fs.create_file(path),
+        true if flags.contains(OpenFlags::APPEND) => fs.append_file(path),
+        true => fs.open_file(path),
+        false if flags.contains(OpenFlags::CREATE) => fs.create_file(path),
+        false => Err(FsError::FileNotFound),
+    })
+}
+
+/// Read all entries of a directory of the VFS
+pub fn read_dir(path: &str) -> FsResult<Vec<Metadata>> {
+    Ok(get_vfs().read_dir(path)?.collect())
+}
+
+pub fn metadata(path: &str) -> FsResult<Metadata> {
+    get_vfs().metadata(path)
+}
+
+/// Convert the metadata to the record passed to userspace
+pub fn file_stat(meta: &Metadata) -> FileStat {
+    FileStat {
+        kind: match meta.is_dir() {
+            true => FileKind::Directory,
+            false => FileKind::File,
+        },
+        size: meta.len as u64,
+        created: meta.created.map(|time| time.timestamp_millis()),
+        modified: meta.modified.map(|time| time.timestamp_millis()),
+        accessed: meta.accessed.map(|time| time.timestamp_millis()),
+    }
+}
+
+// directory changes are written back right away, like closed files
+
+pub fn remove_file(path: &str) -> FsResult {
+    atomic(|| get_vfs().remove_file(path))?;
+    sync()
+}
+
+pub fn create_dir(path: &str) -> FsResult {
+    atomic(|| get_vfs().create_dir(path))?;
+    sync()
+}
+
+pub fn remove_dir(path: &str) -> FsResult {
+    atomic(|| get_vfs().remove_dir(path))?;
+    sync()
+}
+
+pub fn ls(root_path: &str) -> FsResult {
+    let iter = match get_vfs().read_dir(root_path) {
         Ok(iter) => iter,
         Err(err) => {
-            warn!("{:?}", err);
-            return;
+            warn!("{err:?}");
+            return Err(err);
         }
     };
 
@@ -65,7 +345,7 @@ pub fn ls(root_path: &str) {
             name.push('/');
         }
         let (num, unit) = crate::humanized_size(meta.len as u64);
-        let size = format!("{:.2} {}", num, unit);
+        let size = format!("{num:.2} {unit}");
         let created_time = meta
             .created
             .unwrap()
@@ -87,4 +367,6 @@ pub fn ls(root_path: &str) {
             name, filetype, size, created_time, last_modified, last_access
         );
     }
+
+    Ok(())
 }
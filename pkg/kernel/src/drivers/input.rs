@@ -3,6 +3,8 @@ use lazy_static::lazy_static;
 use alloc::string::String;
 use spin::Mutex;
 use alloc::vec::Vec;
+use alloc::collections::BTreeSet;
+use crate::proc::{self, processor, ProcessId};
 
 const BUFFER_SIZE: usize = 128;
 
@@ -12,6 +14,9 @@ lazy_static! {
     static ref INPUT_BUF: ArrayQueue<Key> = ArrayQueue::new(128);
 }
 
+/// Processes whose read is restarted by the next key
+static WAITERS: Mutex<BTreeSet<ProcessId>> = Mutex::new(BTreeSet::new());
+
 lazy_static! {
     static ref UTF8_BUF: Mutex<Vec<u8>> = Mutex::new(Vec::with_capacity(4));
 }
@@ -21,6 +26,7 @@ pub fn push_key(key: Key) {
     if INPUT_BUF.push(key).is_err() {
         warn!("Input buffer is full. Dropping key '{:?}'", key);
     }
+    proc::wake_up_blocked(core::mem::take(&mut *WAITERS.lock()));
 }
 
 #[inline]
@@ -28,6 +34,17 @@ pub fn try_pop_key() -> Option<Key> {
     INPUT_BUF.pop()
 }
 
+/// Pop a key, or let the next key wake up the current process if there is none
+pub fn pop_key_or_wait() -> Option<Key> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let key = try_pop_key();
+        if key.is_none() {
+            WAITERS.lock().insert(processor::get_pid());
+        }
+        key
+    })
+}
+
 /// 一直循环等待输入，直到有输入为止
 /// 但是这样占用cpu，所以没有数据的时候就该让出cpu
 pub fn pop_key() -> Key {
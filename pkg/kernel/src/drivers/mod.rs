@@ -2,4 +2,6 @@ mod uart16550;
 pub mod serial;
 pub mod input;
 pub mod ata;
+pub mod pci;
+pub mod devfs;
 pub mod filesystem;
\ No newline at end of file
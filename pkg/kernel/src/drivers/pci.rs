@@ -0,0 +1,88 @@
+//! PCI Configuration Space
+//!
+//! Only the legacy I/O port mechanism is supported.
+//!
+//! reference: https://wiki.osdev.org/PCI#Configuration_Space_Access_Mechanism_.231
+
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xCF8;
+const CONFIG_DATA: u16 = 0xCFC;
+
+/// Offsets in the configuration space header
+pub const PCI_COMMAND: u8 = 0x04;
+pub const PCI_CLASS: u8 = 0x08;
+pub const PCI_BAR4: u8 = 0x20;
+
+bitflags! {
+    /// Bits of the command register
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct PciCommand: u16 {
+        const IO_SPACE = 0x0001;
+        const MEMORY_SPACE = 0x0002;
+        const BUS_MASTER = 0x0004;
+    }
+}
+
+/// A function of a device on the PCI bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+}
+
+impl PciDevice {
+    fn address(&self, offset: u8) -> u32 {
+        0x8000_0000
+            | (self.bus as u32) << 16
+            | (self.device as u32) << 11
+            | (self.function as u32) << 8
+            | (offset & 0xFC) as u32
+    }
+
+    pub fn read(&self, offset: u8) -> u32 {
+        unsafe {
+            Port::new(CONFIG_ADDRESS).write(self.address(offset));
+            Port::new(CONFIG_DATA).read()
+        }
+    }
+
+    pub fn write(&self, offset: u8, value: u32) {
+        unsafe {
+            Port::new(CONFIG_ADDRESS).write(self.address(offset));
+            Port::new(CONFIG_DATA).write(value);
+        }
+    }
+
+    pub fn vendor_id(&self) -> u16 {
+        self.read(0) as u16
+    }
+
+    /// Class code, subclass and programming interface
+    pub fn class(&self) -> (u8, u8, u8) {
+        let [_, prog_if, subclass, class] = self.read(PCI_CLASS).to_le_bytes();
+        (class, subclass, prog_if)
+    }
+
+    pub fn command(&self) -> PciCommand {
+        PciCommand::from_bits_truncate(self.read(PCI_COMMAND) as u16)
+    }
+
+    /// Only the command half of the register is written, status bits are kept
+    pub fn set_command(&self, command: PciCommand) {
+        let value = self.read(PCI_COMMAND) & 0xFFFF_0000;
+        self.write(PCI_COMMAND, value | command.bits() as u32);
+    }
+}
+
+/// Find the first function with the class and subclass on bus 0
+pub fn find_class(class: u8, subclass: u8) -> Option<PciDevice> {
+    (0..32)
+        .flat_map(|device| (0..8).map(move |function| PciDevice { bus: 0, device, function }))
+        .filter(|dev| dev.vendor_id() != 0xFFFF)
+        .find(|dev| {
+            let (c, s, _) = dev.class();
+            (c, s) == (class, subclass)
+        })
+}
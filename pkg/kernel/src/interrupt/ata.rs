@@ -0,0 +1,24 @@
+use super::consts::*;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+use crate::drivers::ata;
+
+pub unsafe fn register_idt(idt: &mut InterruptDescriptorTable) {
+    idt[Interrupts::IrqBase as u8 + Irq::Ide0 as u8]
+        .set_handler_fn(ide0_handler);
+    idt[Interrupts::IrqBase as u8 + Irq::Ide1 as u8]
+        .set_handler_fn(ide1_handler);
+    trace!("ATA Interrupt Handlers Registered.");
+}
+
+/// Primary bus, a request is finished
+pub extern "x86-interrupt" fn ide0_handler(_st: InterruptStackFrame) {
+    ata::handle_interrupt(0);
+    super::ack();
+}
+
+/// Secondary bus, a request is finished
+pub extern "x86-interrupt" fn ide1_handler(_st: InterruptStackFrame) {
+    ata::handle_interrupt(1);
+    super::ack();
+}
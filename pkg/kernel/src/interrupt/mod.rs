@@ -2,6 +2,7 @@ mod apic;
 mod consts;
 pub mod clock;
 mod serial;
+mod ata;
 mod exceptions;
 // use crate::memory::address;
 use apic::*;
@@ -20,6 +21,7 @@ lazy_static! {
             exceptions::register_idt(&mut idt);
             clock::register_idt(&mut idt);
             serial::register_idt(&mut idt);
+            ata::register_idt(&mut idt);
             syscall::register_idt(&mut idt);
         }
         idt
@@ -54,6 +56,8 @@ pub fn init() {
 
     // FIXME: enable serial irq with IO APIC (use enable_irq)
     enable_irq(consts::Irq::Serial0 as u8, 0); // enable IRQ4 for CPU0
+    enable_irq(consts::Irq::Ide0 as u8, 0); // ATA 请求完成中断
+    enable_irq(consts::Irq::Ide1 as u8, 0);
 
     info!("Interrupts Initialized.");
 }
@@ -3,7 +3,7 @@ use alloc::format;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 use x86_64::PrivilegeLevel::Ring3;
 // NOTE: import `ysos_syscall` package as `syscall_def` in Cargo.toml
-use syscall_def::Syscall;
+use syscall_def::{Syscall, SysError, SysResult};
 
 mod service;
 use super::consts;
@@ -38,6 +38,7 @@ pub struct SyscallArgs {
     pub arg0: usize,
     pub arg1: usize,
     pub arg2: usize,
+    pub arg3: usize,
 }
 
 pub fn dispatcher(context: &mut ProcessContext) {
@@ -46,30 +47,37 @@ pub fn dispatcher(context: &mut ProcessContext) {
         context.regs.rdi,
         context.regs.rsi,
         context.regs.rdx,
+        context.regs.r10,
     );
 
     // NOTE: you may want to trace syscall arguments
     // trace!("{}", args);
 
     match args.syscall {
-        // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
+        // fd: arg0 as u8 (BadFd if larger), buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
         Syscall::Read => { /* FIXME: read from fd & return length */
-            context.set_rax(sys_read(&args));
+            sys_read(&args, context);
         },
 
-        // fd: arg0 as u8, buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
+        // fd: arg0 as u8 (BadFd if larger), buf: &[u8] (ptr: arg1 as *const u8, len: arg2)
         Syscall::Write => { /* FIXME: write to fd & return length */
-            context.set_rax(sys_write(&args));
+            sys_write(&args, context);
         },
 
+        // fds: &mut [u8; 2] (ptr: arg0 as *mut u8) -> ret: isize
+        Syscall::Pipe => context.set_rax(SysError::encode_result(sys_pipe(&args))),
+
+        // fd: arg0 as u8, offset: arg1 as isize, whence: arg2 -> offset: usize
+        Syscall::Lseek => set_result(context, sys_lseek(&args)),
+
         // None -> time: u64
         Syscall::GetTime => { /* FIXME: get current time */
             context.set_rax(sys_gettime());
         },
 
-        // None -> brk: usize
+        // addr: arg0 as usize -> brk: usize
         Syscall::Brk => { /* FIXME: set brk */
-            context.set_rax(sys_brk(&args));
+            context.set_rax(SysError::encode_result(sys_brk(&args)));
         },
 
         // None -> pid: u16
@@ -83,23 +91,57 @@ pub fn dispatcher(context: &mut ProcessContext) {
             sys_sem(&args, context);
         },
 
-        // path: &str (ptr: arg0 as *const u8, len: arg1) -> pid: u16
-        Syscall::ListDir => list_dir(&args),
+        // path: &str (ptr: arg0 as *const u8, len: arg1) -> ret: isize
+        Syscall::ListDir => set_result(context, list_dir(&args)),
+
+        // path: &str (ptr: arg0 as *const u8, len: arg1), buf: &mut [u8] (ptr: arg2, len: arg3) -> len: usize
+        Syscall::FileStat => set_result(context, sys_file_stat(&args)),
+
+        // fd: u8, buf: &mut [u8] (ptr: arg1 as *mut u8, len: arg2) -> len: usize
+        Syscall::ReadDir => set_result(context, sys_read_dir(&args)),
+
+        // path: &str (ptr: arg0 as *const u8, len: arg1), flags: OpenFlags (arg2) -> fd: u8
+        Syscall::OpenFile => set_result(context, sys_open_file(&args)),
+
+        // path: &str (ptr: arg0 as *const u8, len: arg1) -> ret: isize
+        Syscall::RemoveFile => set_result(context, sys_remove_file(&args)),
+
+        // path: &str (ptr: arg0 as *const u8, len: arg1) -> ret: isize
+        Syscall::CreateDir => set_result(context, sys_create_dir(&args)),
+
+        // path: &str (ptr: arg0 as *const u8, len: arg1) -> ret: isize
+        Syscall::RemoveDir => set_result(context, sys_remove_dir(&args)),
 
-        // fd: u8, path: &str (ptr: arg1 as *const u8, len: arg2) -> fd: u8
-        Syscall::OpenFile => context.set_rax(sys_open_file(&args)),
+        // source: &str (ptr: arg0 as *const u8, len: arg1),
+        // target: &str (ptr: arg2 as *const u8, len: arg3) -> ret: isize
+        Syscall::Mount => set_result(context, sys_mount(&args)),
 
-        // fd: u8 -> ret: bool
-        Syscall::CloseFile => context.set_rax(sys_close_file(&args) as usize),
+        // target: &str (ptr: arg0 as *const u8, len: arg1) -> ret: isize
+        Syscall::Umount => set_result(context, sys_umount(&args)),
 
-        // None -> pid: u16 or 0 or -1
+        // fd: u8 -> ret: isize
+        Syscall::CloseFile => set_result(context, sys_close_file(&args)),
+
+        // fd: u8 -> new_fd: u8
+        Syscall::Dup => context.set_rax(SysError::encode_result(sys_dup(&args))),
+
+        // old_fd: u8, new_fd: u8 -> new_fd: u8
+        Syscall::Dup2 => context.set_rax(SysError::encode_result(sys_dup2(&args))),
+
+        // limit: arg0 as usize, 0 to keep it -> old limit: usize
+        Syscall::FdLimit => context.set_rax(SysError::encode_result(sys_fd_limit(&args))),
+
+        // None -> pid: u16 or 0, OutOfMemory if no memory for the child
         Syscall::Fork => {
             sys_fork(context);
         },
 
+        // None -> pid: u16 or 0, the child shares the memory
+        Syscall::Thread => sys_thread(context),
+
         // path: &str (ptr: arg0 as *const u8, len: arg1) -> pid: u16
         Syscall::Spawn => { /* FIXME: spawn process from name */
-            context.set_rax(spawn_process(&args));
+            context.set_rax(SysError::encode_result(spawn_process(&args)));
         },
 
         // ret: arg0 as isize
@@ -127,21 +169,33 @@ pub fn dispatcher(context: &mut ProcessContext) {
         // ----------------------------------------------------
 
         // layout: arg0 as *const Layout -> ptr: *mut u8
-        Syscall::Allocate => context.set_rax(sys_allocate(&args)),
+        Syscall::Allocate => context.set_rax(SysError::encode_result(sys_allocate(&args))),
         // ptr: arg0 as *mut u8
-        Syscall::Deallocate => sys_deallocate(&args),
+        Syscall::Deallocate => context.set_rax(SysError::encode_result(sys_deallocate(&args))),
         // Unknown
-        Syscall::Unknown => warn!("Unhandled syscall: {:x?}", context.regs.rax),
+        Syscall::Unknown => {
+            warn!("Unhandled syscall: {:x?}", context.regs.rax);
+            context.set_rax(SysError::NotSupported.encode());
+        },
+    }
+}
+
+/// Return the result, a syscall which would block is restarted later
+fn set_result(context: &mut ProcessContext, ret: SysResult) {
+    match ret {
+        Err(SysError::WouldBlock) => block_and_restart_syscall(context),
+        ret => context.set_rax(SysError::encode_result(ret)),
     }
 }
 
 impl SyscallArgs {
-    pub fn new(syscall: Syscall, arg0: usize, arg1: usize, arg2: usize) -> Self {
+    pub fn new(syscall: Syscall, arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> Self {
         Self {
             syscall,
             arg0,
             arg1,
             arg2,
+            arg3,
         }
     }
 }
@@ -150,11 +204,12 @@ impl core::fmt::Display for SyscallArgs {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
-            "SYSCALL: {:<10} (0x{:016x}, 0x{:016x}, 0x{:016x})",
+            "SYSCALL: {:<10} (0x{:016x}, 0x{:016x}, 0x{:016x}, 0x{:016x})",
             format!("{:?}", self.syscall),
             self.arg0,
             self.arg1,
-            self.arg2
+            self.arg2,
+            self.arg3
         )
     }
 }
@@ -5,51 +5,78 @@ use crate::proc::*;
 use crate::utils;
 use crate::utils::*;
 use crate::filesystem;
+use crate::proc::uaccess::{UserPtr, UserSlice};
+use syscall_def::{OpenFlags, SeekWhence, SysError, SysResult};
 // Virtual address
 use x86_64::VirtAddr;
 
 use super::SyscallArgs;
 
-pub fn spawn_process(args: &SyscallArgs) -> usize {
+pub fn spawn_process(args: &SyscallArgs) -> SysResult {
     // FIXME: get app name by args
     //       - core::str::from_utf8_unchecked
     //       - core::slice::from_raw_parts
-    let name = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(args.arg0 as *const u8, args.arg1))
-    };
+    let user_name = UserSlice::new(args.arg0, args.arg1);
+    let name = user_name.as_str()?;
     // FIXME: spawn the process by name
     let ret = proc::spawn(name);
-    // FIXME: handle spawn error, return 0 if failed
+    // FIXME: handle spawn error, return NotFound if failed
     // FIXME: return pid as usize
     match ret {
-        Some(pid) => {
-            return pid.0 as usize;
-        }
-        None => {
-            return 0;
-        }
+        Some(pid) => Ok(pid.0 as usize),
+        None => Err(SysError::NotFound),
     }
 }
 
-pub fn sys_write(args: &SyscallArgs) -> usize {
+/// The fd passed in a register, `BadFd` if it does not fit in a `u8`
+fn user_fd(arg: usize) -> SysResult<u8> {
+    u8::try_from(arg).map_err(|_| SysError::BadFd)
+}
+
+pub fn sys_write(args: &SyscallArgs, context: &mut ProcessContext) {
     // FIXME: get buffer and fd by args
     //       - core::slice::from_raw_parts
-    let buf = unsafe {
-        core::slice::from_raw_parts(args.arg1 as *const u8, args.arg2)
-    };
-    // FIXME: call proc::write -> isize
-    let ret = proc::write(args.arg0 as u8, buf) as usize;
-    // FIXME: return the result as usize
-    ret
+    let user_buf = UserSlice::new(args.arg1, args.arg2);
+    let ret = user_fd(args.arg0)
+        .and_then(|fd| user_buf.as_slice().and_then(|buf| proc::write(fd, buf)));
+    // FIXME: call proc::write -> SysResult
+    match ret {
+        Err(SysError::WouldBlock) => proc::block_and_restart_syscall(context),
+        ret => context.set_rax(SysError::encode_result(ret)),
+    }
 }
 
-pub fn sys_read(args: &SyscallArgs) -> usize {
+pub fn sys_read(args: &SyscallArgs, context: &mut ProcessContext) {
     // FIXME: just like sys_write
-    let buf = unsafe {
-        core::slice::from_raw_parts_mut(args.arg1 as *mut u8, args.arg2)
+    let mut user_buf = UserSlice::new(args.arg1, args.arg2);
+    let ret = user_fd(args.arg0)
+        .and_then(|fd| user_buf.as_mut_slice().and_then(|buf| proc::read(fd, buf)));
+    match ret {
+        Err(SysError::WouldBlock) => proc::block_and_restart_syscall(context),
+        ret => context.set_rax(SysError::encode_result(ret)),
+    }
+}
+
+pub fn sys_pipe(args: &SyscallArgs) -> SysResult {
+    let fds = UserPtr::<[u8; 2]>::new(args.arg0);
+    let (read_fd, write_fd) = proc::pipe()?;
+    if let Err(err) = fds.write([read_fd, write_fd]) {
+        close_file(read_fd);
+        close_file(write_fd);
+        return Err(err);
+    }
+    Ok(0)
+}
+
+pub fn sys_lseek(args: &SyscallArgs) -> SysResult {
+    let offset = args.arg1 as isize;
+    let pos = match SeekWhence::try_from(args.arg2).map_err(|_| SysError::InvalidArgument)? {
+        SeekWhence::Start if offset >= 0 => storage::SeekFrom::Start(offset as usize),
+        SeekWhence::Start => return Err(SysError::InvalidArgument),
+        SeekWhence::Current => storage::SeekFrom::Current(offset),
+        SeekWhence::End => storage::SeekFrom::End(offset),
     };
-    let ret = proc::read(args.arg0 as u8, buf) as usize;
-    ret
+    proc::seek(user_fd(args.arg0)?, pos)
 }
 
 pub fn sys_gettime() -> usize {
@@ -58,15 +85,15 @@ pub fn sys_gettime() -> usize {
     time as usize
 }
 
-pub fn sys_brk(args: &SyscallArgs) -> usize {
+pub fn sys_brk(args: &SyscallArgs) -> SysResult {
     let new_heap_end = if args.arg0 == 0 {
         None
     } else {
-        Some(VirtAddr::new(args.arg0 as u64))
+        Some(VirtAddr::try_new(args.arg0 as u64).map_err(|_| SysError::InvalidArgument)?)
     };
     match brk(new_heap_end) {
-        Some(new_heap_end) => new_heap_end.as_u64() as usize,
-        None => !0,
+        Some(new_heap_end) => Ok(new_heap_end.as_u64() as usize),
+        None => Err(SysError::OutOfMemory),
     }
 }
 
@@ -90,80 +117,149 @@ pub fn list_process() {
     proc::print_process_list();
 }
 
-pub fn sys_allocate(args: &SyscallArgs) -> usize {
-    let layout = unsafe { (args.arg0 as *const Layout).as_ref().unwrap() };
-
+/// Build the layout from the size and align passed in registers
+fn user_layout(size: usize, align: usize) -> SysResult<Layout> {
+    let layout = Layout::from_size_align(size, align).map_err(|_| SysError::InvalidArgument)?;
     if layout.size() == 0 {
-        return 0;
-    }
-
-    let ret = crate::memory::user::USER_ALLOCATOR
-        .lock()
-        .allocate_first_fit(*layout);
-
-    match ret {
-        Ok(ptr) => ptr.as_ptr() as usize,
-        Err(_) => 0,
+        return Err(SysError::InvalidArgument);
     }
+    Ok(layout)
 }
 
-pub fn sys_deallocate(args: &SyscallArgs) {
-    let layout = unsafe { (args.arg1 as *const Layout).as_ref().unwrap() };
+pub fn sys_allocate(args: &SyscallArgs) -> SysResult {
+    let layout = user_layout(args.arg0, args.arg1)?;
+    crate::memory::user::allocate(layout).ok_or(SysError::OutOfMemory)
+}
 
-    if args.arg0 == 0 || layout.size() == 0 {
-        return;
-    }
+pub fn sys_deallocate(args: &SyscallArgs) -> SysResult {
+    use crate::memory::user::{USER_HEAP_SIZE, USER_HEAP_START};
 
-    let ptr = args.arg0 as *mut u8;
+    let layout = user_layout(args.arg1, args.arg2)?;
 
-    unsafe {
-        crate::memory::user::USER_ALLOCATOR
-            .lock()
-            .deallocate(core::ptr::NonNull::new_unchecked(ptr), *layout);
+    // the block must lie in the user heap, or the allocator would write anywhere
+    let heap = USER_HEAP_START..USER_HEAP_START + USER_HEAP_SIZE;
+    let end = args.arg0.checked_add(layout.size()).ok_or(SysError::BadAddress)?;
+    if !heap.contains(&args.arg0) || end > heap.end || args.arg0 % layout.align() != 0 {
+        return Err(SysError::BadAddress);
     }
+
+    // and be a live block allocated with the same layout
+    crate::memory::user::deallocate(args.arg0, layout)?;
+    Ok(0)
 }
 
 pub fn sys_fork(context: &mut ProcessContext) {
     // let ret = proc::fork(context);
     // context.set_rax(ret as usize);
-    proc::fork(context);
+    proc::fork(context, proc::ForkMode::Process);
+}
+
+pub fn sys_thread(context: &mut ProcessContext) {
+    proc::fork(context, proc::ForkMode::Thread);
 }
 
 pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
     match args.arg0 {
         0 => {
-            let ret = sem_new(args.arg1 as u32, args.arg2);
+            let ret = match sem_new(args.arg1 as u32, args.arg2) {
+                true => 0,
+                false => SysError::AlreadyExists.encode(),
+            };
+            context.set_rax(ret);
+        },
+        1 => {
+            let ret = match sem_remove(args.arg1 as u32) {
+                true => 0,
+                false => SysError::NotFound.encode(),
+            };
             context.set_rax(ret);
         },
-        1 => context.set_rax(sem_remove(args.arg1 as u32)),
         2 => sem_signal(args.arg1 as u32, context),
         3 => sem_wait(args.arg1 as u32, context),
-        _ => context.set_rax(usize::MAX),
+        _ => context.set_rax(SysError::InvalidArgument.encode()),
     }
 }
 
-pub fn list_dir(args: &SyscallArgs) {
-    let path = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
-    };
-    filesystem::ls(path);
+pub fn list_dir(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let path = user_path.as_str()?;
+    filesystem::ls(path).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
+}
 
+pub fn sys_file_stat(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let meta = filesystem::metadata(user_path.as_str()?).map_err(filesystem::fs_error_to_sys)?;
+    let mut user_buf = UserSlice::new(args.arg2, args.arg3);
+    filesystem::file_stat(&meta)
+        .encode(&meta.name, user_buf.as_mut_slice()?)
+        .ok_or(SysError::InvalidArgument)
 }
 
-pub fn sys_open_file(args: &SyscallArgs) -> usize{
-    let path = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
-    };
-    open_file(path) as usize
+pub fn sys_read_dir(args: &SyscallArgs) -> SysResult {
+    let mut user_buf = UserSlice::new(args.arg1, args.arg2);
+    proc::read_dir(user_fd(args.arg0)?, user_buf.as_mut_slice()?)
+}
+
+pub fn sys_open_file(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let path = user_path.as_str()?;
+    let flags = OpenFlags::from_bits(args.arg2).ok_or(SysError::InvalidArgument)?;
+    open_file(path, flags).map(|fd| fd as usize)
+}
+
+pub fn sys_remove_file(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let path = user_path.as_str()?;
+    filesystem::remove_file(path).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
+}
+
+pub fn sys_create_dir(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let path = user_path.as_str()?;
+    filesystem::create_dir(path).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
+}
+
+pub fn sys_remove_dir(args: &SyscallArgs) -> SysResult {
+    let user_path = UserSlice::new(args.arg0, args.arg1);
+    let path = user_path.as_str()?;
+    filesystem::remove_dir(path).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
 }
 
-pub fn sys_close_file(args: &SyscallArgs) -> bool {
-    let fd = args.arg0 as u8;
-    close_file(fd)
+pub fn sys_mount(args: &SyscallArgs) -> SysResult {
+    let source = UserSlice::new(args.arg0, args.arg1);
+    let target = UserSlice::new(args.arg2, args.arg3);
+    filesystem::mount(source.as_str()?, target.as_str()?).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
+}
+
+pub fn sys_umount(args: &SyscallArgs) -> SysResult {
+    let target = UserSlice::new(args.arg0, args.arg1);
+    filesystem::umount(target.as_str()?).map_err(filesystem::fs_error_to_sys)?;
+    Ok(0)
+}
+
+pub fn sys_dup(args: &SyscallArgs) -> SysResult {
+    dup(user_fd(args.arg0)?).map(|fd| fd as usize)
+}
+
+pub fn sys_dup2(args: &SyscallArgs) -> SysResult {
+    dup2(user_fd(args.arg0)?, user_fd(args.arg1)?).map(|fd| fd as usize)
+}
+
+pub fn sys_fd_limit(args: &SyscallArgs) -> SysResult {
+    let limit = (args.arg0 != 0).then_some(args.arg0);
+    Ok(proc::fd_limit(limit))
+}
+
+pub fn sys_close_file(args: &SyscallArgs) -> SysResult {
+    let fd = user_fd(args.arg0)?;
+    match close_file(fd) {
+        // written data reaches the disk when the file is closed
+        true => filesystem::sync().map(|_| 0).map_err(filesystem::fs_error_to_sys),
+        false => Err(SysError::BadFd),
+    }
 }
\ No newline at end of file
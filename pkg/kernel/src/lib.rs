@@ -60,7 +60,6 @@ pub fn init(boot_info: &'static BootInfo) {
     
     drivers::filesystem::init();
     info!("Filesystem initialized.");
-    AtaDrive::open(0, 0);
     
     info!("Test stack grow.");
 
@@ -71,6 +70,10 @@ pub fn init(boot_info: &'static BootInfo) {
 
 pub fn shutdown() -> ! {
     info!("YatSenOS shutting down.");
+    // dirty cached blocks and queued disk writes are not waited for by anyone else
+    if let Err(err) = drivers::filesystem::sync() {
+        warn!("Failed to sync filesystems: {err:?}");
+    }
     uefi::runtime::reset(ResetType::SHUTDOWN, Status::SUCCESS, None);
 }
 
@@ -1,8 +1,8 @@
-use alloc::boxed::Box;
+use buddy::{BuddyAllocator, MAX_ORDER};
+use super::{physical_to_virtual, PAGE_SIZE};
 use boot::{MemoryMap, MemoryType};
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};    // 页帧类型和分配器trait
 use x86_64::PhysAddr;   // 物理地址类型
-use alloc::vec::Vec;
 
 // ! 同步原语宏（自定义）:实现线程安全的单例访问模式
 once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);   // 创建延迟初始化的互斥锁
@@ -12,16 +12,44 @@ guard_access_fn! {
 }
 const RS_ALIGN_4KIB: u64 = 12;
 
-// ! 页帧迭代器类型
-type BootInfoFrameIter = Box<dyn Iterator<Item = PhysFrame> + Send>;
+bitflags! {
+    /// What an allocated frame is used for
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub struct FrameOwner: u8 {
+        /// Mapped into a user address space
+        const USER = 1 << 0;
+        /// Holds a page table
+        const PAGE_TABLE = 1 << 1;
+        /// Buffer of a device
+        const DMA = 1 << 2;
+    }
+}
+
+/// Metadata of a physical frame
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+    /// Mappings of the frame, 0 if it is free
+    pub refs: u32,
+    pub owner: FrameOwner,
+}
+
+impl FrameInfo {
+    const FREE: Self = Self {
+        refs: 0,
+        owner: FrameOwner::empty(),
+    };
+}
 
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
 pub struct BootInfoFrameAllocator {
     size: usize,
     used: usize,
-    frames: BootInfoFrameIter,
-    // recycled: Vec<u32>,
-    recycled: Vec<PhysFrame>,
+    /// Free frames in blocks of `2^order`
+    buddy: BuddyAllocator,
+    /// Frames freed so far
+    recycled: usize,
+    /// Metadata of every frame up to the last usable one, by frame number
+    meta: &'static mut [FrameInfo],
 }
 
 // ! 主结构体
@@ -32,11 +60,41 @@ impl BootInfoFrameAllocator {
     /// memory map is valid. The main requirement is that all frames that are marked
     /// as `USABLE` in it are really unused.
     pub unsafe fn init(memory_map: &MemoryMap, size: usize) -> Self {   // 必须确保标记为USABLE的内存区域确实未被使用，过程：从引导信息的内存映射（MemoryMap）创建迭代器，记录总可用页帧数（由调用者计算提供）
+        // the metadata takes the first usable region large enough for it
+        let count = memory_map
+            .iter()
+            .filter(|r| r.ty == MemoryType::CONVENTIONAL)
+            .map(|r| (r.phys_start / PAGE_SIZE + r.page_count) as usize)
+            .max()
+            .unwrap_or(0);
+        let pages = (count * size_of::<FrameInfo>()).div_ceil(PAGE_SIZE as usize) as u64;
+        let region = memory_map
+            .iter()
+            .find(|r| r.ty == MemoryType::CONVENTIONAL && r.page_count >= pages)
+            .expect("No memory for frame metadata");
+
+        let meta = unsafe {
+            core::slice::from_raw_parts_mut(
+                physical_to_virtual(region.phys_start) as *mut FrameInfo,
+                count,
+            )
+        };
+        meta.fill(FrameInfo::FREE);
+
+        // 仅保留CONVENTIONAL（常规可用）内存区域，跳过元数据所在的页帧
+        let mut buddy = BuddyAllocator::new();
+        for r in memory_map.iter().filter(|r| r.ty == MemoryType::CONVENTIONAL) {
+            let start = r.phys_start / PAGE_SIZE;
+            let skip = if r.phys_start == region.phys_start { pages } else { 0 };
+            buddy.add_range((start + skip) as u32..(start + r.page_count) as u32);
+        }
+
         BootInfoFrameAllocator {
-            size,       // 总可用页帧数
-            frames: create_frame_iter(memory_map),  // 页帧迭代器:动态分发的迭代器（Box<dyn Iterator>），支持遍历所有可用物理页帧
+            size: size - pages as usize,       // 总可用页帧数
+            buddy,
             used: 0,    // 已分配页帧计数
-            recycled: Vec::new(),   // 回收页帧列表
+            recycled: 0,   // 回收页帧计数
+            meta,
         }
     }
 
@@ -48,27 +106,112 @@ impl BootInfoFrameAllocator {
         self.size
     }
 
-    pub fn recycled_count(&self) -> usize {
-        self.recycled.len() as usize
-    }
     pub fn frames_recycled(&self) -> usize {
-        self.recycled.len()
+        self.recycled
+    }
+
+    /// Free blocks of each order
+    pub fn free_blocks(&self) -> [usize; MAX_ORDER + 1] {
+        self.buddy.free_blocks()
+    }
+
+    /// Allocate `2^order` frames in a row, aligned to their size, return the first one
+    ///
+    /// Each frame is referenced once and can be freed on its own.
+    pub fn allocate_frames(&mut self, order: usize) -> Option<PhysFrame> {
+        let block = self.buddy.alloc(order)?;
+        self.used += 1 << order;
+
+        for pfn in block..block + (1 << order) {
+            if let Some(info) = self.meta.get_mut(pfn as usize) {
+                *info = FrameInfo {
+                    refs: 1,
+                    owner: FrameOwner::empty(),
+                };
+            }
+        }
+        Some(u32_to_phys_frame(block))
+    }
+
+    /// Drop a reference to each frame of a block from `allocate_frames`
+    pub fn deallocate_frames(&mut self, frame: PhysFrame, order: usize) {
+        for i in 0..1 << order {
+            self.dec_ref(frame + i);
+        }
+    }
+
+    /// Frames mapped more than once
+    pub fn frames_shared(&self) -> usize {
+        self.meta.iter().filter(|info| info.refs > 1).count()
+    }
+
+    fn info(&self, frame: PhysFrame) -> Option<&FrameInfo> {
+        self.meta.get(phys_frame_to_u32(frame) as usize)
+    }
+
+    fn info_mut(&mut self, frame: PhysFrame) -> Option<&mut FrameInfo> {
+        self.meta.get_mut(phys_frame_to_u32(frame) as usize)
+    }
+
+    /// Mappings of an allocated frame, 0 if it is free
+    pub fn ref_count(&self, frame: PhysFrame) -> usize {
+        self.info(frame).map_or(0, |info| info.refs as usize)
+    }
+
+    /// Count one more mapping of an allocated frame, return the new count
+    pub fn inc_ref(&mut self, frame: PhysFrame) -> usize {
+        match self.info_mut(frame) {
+            Some(info) if info.refs > 0 => {
+                info.refs += 1;
+                info.refs as usize
+            }
+            _ => {
+                warn!("Reference to a free frame {:?}", frame);
+                0
+            }
+        }
+    }
+
+    /// Drop a mapping of an allocated frame, return the mappings left
+    ///
+    /// The frame is recycled when the last one is dropped.
+    pub fn dec_ref(&mut self, frame: PhysFrame) -> usize {
+        let refs = match self.info_mut(frame) {
+            Some(info) if info.refs > 0 => {
+                info.refs -= 1;
+                if info.refs == 0 {
+                    info.owner = FrameOwner::empty();
+                }
+                info.refs as usize
+            }
+            _ => {
+                warn!("Double free of frame {:?}", frame);
+                return 0;
+            }
+        };
+        if refs == 0 {
+            self.buddy.free(phys_frame_to_u32(frame), 0);
+            self.recycled += 1;
+        }
+        refs
+    }
+
+    pub fn owner(&self, frame: PhysFrame) -> FrameOwner {
+        self.info(frame).map_or(FrameOwner::empty(), |info| info.owner)
+    }
+
+    /// Record what an allocated frame is used for
+    pub fn set_owner(&mut self, frame: PhysFrame, owner: FrameOwner) {
+        if let Some(info) = self.info_mut(frame) {
+            info.owner |= owner;
+        }
     }
 }
 
 // ! 实现分配器接口
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        /* 每次调用从迭代器获取下一个可用页帧，递增used计数器（即使返回None也计数，可能存在统计偏差） */
-        // self.used += 1;
-        // self.frames.next()
-        if let Some(frame) = self.recycled.pop() {
-            // Some(u32_to_phys_frame(frame))
-            Some(frame)
-        } else {
-            self.used += 1;
-            self.frames.next()
-        }
+        self.allocate_frames(0)
     }
 }
 
@@ -76,8 +219,8 @@ impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
     unsafe fn deallocate_frame(&mut self, _frame: PhysFrame) {
         // TODO: deallocate frame (not for lab 2)
         // let key = phys_frame_to_u32(_frame);
-        let key = _frame;
-        self.recycled.push(key);
+        // a shared frame only loses one reference
+        self.dec_ref(_frame);
     }
 }
 
@@ -94,24 +237,3 @@ fn phys_frame_to_u32(frame: PhysFrame) -> u32 {
 fn u32_to_phys_frame(key: u32) -> PhysFrame {
     PhysFrame::containing_address(PhysAddr::new((key as u64) << RS_ALIGN_4KIB))
 }
-
-// ! 工具函数
-fn create_frame_iter(memory_map: &MemoryMap) -> BootInfoFrameIter {
-    /*
-    内存布局示例：
-    - 物理区域: 0x1000-0x5000 (16KB)
-    - 生成页帧: [0x1000, 0x2000, 0x3000, 0x4000]
-    */
-    let iter = memory_map
-        .clone()
-        .into_iter()
-        // get usable regions from memory map
-        .filter(|r| r.ty == MemoryType::CONVENTIONAL)   // 过滤出可用内存区域，仅保留CONVENTIONAL（常规可用）内存区域
-        // align to page boundary
-        .flat_map(|r| (0..r.page_count).map(move |v| (v * 4096 + r.phys_start)))    // 过滤可用区域的页帧地址，将每个内存区域按4KB页大小拆分为连续页帧
-        // create `PhysFrame` types from the start addresses
-        .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)));    // 转换为页帧类型
-
-    Box::new(iter)
-}
-
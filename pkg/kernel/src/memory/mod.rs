@@ -6,6 +6,7 @@ pub mod user;// 用户态相关
 pub mod gdt;// GDT相关
 
 pub use address::*;// 导出地址模块的所有公共
+pub use buddy::MAX_ORDER;
 pub use frames::*;// 帧模块
 
 use crate::humanized_size;
@@ -1,7 +1,12 @@
 // use core::range;
 
 use crate::proc::PageTableContext;
+use alloc::collections::BTreeMap;
+use core::alloc::Layout;
+use core::ptr::NonNull;
 use linked_list_allocator::LockedHeap;
+use spin::Mutex;
+use syscall_def::{SysError, SysResult};
 use x86_64::structures::paging::{
     mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, Size4KiB,
 };
@@ -11,7 +16,10 @@ pub const USER_HEAP_START: usize = 0x4000_0000_0000;
 pub const USER_HEAP_SIZE: usize = 1024 * 1024; // 1 MiB
 const USER_HEAP_PAGE: usize = USER_HEAP_SIZE / crate::memory::PAGE_SIZE as usize;
 
-pub static USER_ALLOCATOR: LockedHeap = LockedHeap::empty();
+static USER_ALLOCATOR: LockedHeap = LockedHeap::empty();
+
+/// Blocks handed out from the user heap and their layouts
+static USER_BLOCKS: Mutex<BTreeMap<usize, Layout>> = Mutex::new(BTreeMap::new());
 
 // NOTE: export mod user / call in the kernel init / after frame allocator
 pub fn init() {
@@ -52,3 +60,30 @@ pub fn init_user_heap() -> Result<(), MapToError<Size4KiB>> {
 
     Ok(())
 }
+
+/// Allocate a block from the user heap
+pub fn allocate(layout: Layout) -> Option<usize> {
+    let addr = USER_ALLOCATOR.lock().allocate_first_fit(layout).ok()?.as_ptr() as usize;
+    USER_BLOCKS.lock().insert(addr, layout);
+    Some(addr)
+}
+
+/// Free a block returned by [`allocate`], `layout` must be the one it was
+/// allocated with, anything else would corrupt the heap
+pub fn deallocate(addr: usize, layout: Layout) -> SysResult<()> {
+    let mut blocks = USER_BLOCKS.lock();
+    match blocks.get(&addr) {
+        Some(live) if *live == layout => {}
+        Some(_) => return Err(SysError::InvalidArgument),
+        // never allocated, or freed already
+        None => return Err(SysError::BadAddress),
+    }
+    blocks.remove(&addr);
+
+    unsafe {
+        USER_ALLOCATOR
+            .lock()
+            .deallocate(NonNull::new_unchecked(addr as *mut u8), layout);
+    }
+    Ok(())
+}
@@ -5,9 +5,10 @@ use x86_64::structures::paging::{
     Page,
 };
 
-use crate::resource::{ResourceSet ,Resource};
+use crate::resource::{DirHandle, ResourceSet ,Resource};
 use super::*;
-use crate::filesystem::get_rootfs;
+use crate::filesystem::{self, fs_error_to_sys};
+use syscall_def::{OpenFlags, SysResult};
 
 #[derive(Debug, Clone)]
 pub struct ProcessData {
@@ -32,14 +33,38 @@ impl ProcessData {
         Self::default()
     }
 
-    pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
+    /// Data of a forked process, with its own copy of the fd table
+    ///
+    /// `close` and `dup2` in the child do not change the parent's fds.
+    pub fn fork(&self) -> Self {
+        Self {
+            resources: Arc::new(RwLock::new(self.resources.read().clone())),
+            ..self.clone()
+        }
+    }
+
+    /// Data of a spawned process, it starts with a copy of the fd table
+    ///
+    /// Fds redirected with `dup2` before spawning become its stdin and stdout.
+    pub fn spawn(&self) -> Self {
+        Self {
+            resources: Arc::new(RwLock::new(self.resources.read().clone())),
+            ..Self::default()
+        }
+    }
+
+    pub fn read(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
         self.resources.read().read(fd, buf)
     }
     
-    pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
+    pub fn write(&self, fd: u8, buf: &[u8]) -> SysResult<usize> {
         self.resources.read().write(fd, buf)
     }
 
+    pub fn seek(&self, fd: u8, pos: storage::SeekFrom) -> SysResult<usize> {
+        self.resources.read().seek(fd, pos)
+    }
+
     pub fn env(&self, key: &str) -> Option<String> {
         self.env.read().get(key).cloned()
     }
@@ -65,12 +90,45 @@ impl ProcessData {
         self.semaphores.read().signal(key)
     }
 
-    pub fn open_file(&self, path: &str) -> u8 {
-        let handle: storage::FileHandle = get_rootfs().fs.open_file(path).unwrap();
-        self.resources.write().open(Resource::File(handle))
+    pub fn open_file(&self, path: &str, flags: OpenFlags) -> SysResult<u8> {
+        let res = if flags.contains(OpenFlags::DIRECTORY) {
+            let entries = filesystem::read_dir(path).map_err(fs_error_to_sys)?;
+            Resource::Dir(DirHandle::new(entries))
+        } else {
+            let handle: storage::FileHandle =
+                filesystem::open_file(path, flags).map_err(fs_error_to_sys)?;
+            Resource::File(handle)
+        };
+        self.resources.write().open(res)
+    }
+
+    pub fn read_dir(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+        self.resources.read().read_dir(fd, buf)
     }
 
     pub fn close_file(&self, fd: u8) -> bool {
         self.resources.write().close(fd)
     }
+
+    pub fn pipe(&self) -> SysResult<(u8, u8)> {
+        self.resources.write().pipe()
+    }
+
+    pub fn dup(&self, fd: u8) -> SysResult<u8> {
+        self.resources.write().dup(fd)
+    }
+
+    pub fn dup2(&self, old_fd: u8, new_fd: u8) -> SysResult<u8> {
+        self.resources.write().dup2(old_fd, new_fd)
+    }
+
+    /// Set the max number of open fds if `limit` is given, return the old one
+    pub fn fd_limit(&self, limit: Option<usize>) -> usize {
+        let mut resources = self.resources.write();
+        let old = resources.fd_limit();
+        if let Some(limit) = limit {
+            resources.set_fd_limit(limit);
+        }
+        old
+    }
 }
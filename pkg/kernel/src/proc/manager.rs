@@ -7,6 +7,7 @@ use crate::memory::{
 use crate::{proc::vm::ProcessVm};
 use alloc::{collections::*, format, string::String, sync::Arc, sync::Weak};
 use spin::{Mutex, RwLock};
+use syscall_def::{OpenFlags, SysResult};
 use crate::utils::humanized_size;
 use vm::stack::STACK_INIT_TOP;
 
@@ -33,6 +34,12 @@ pub fn init(init: Arc<Process>, app_list: boot::AppListRef) {
     debug!("Process Manager: {:#?}", PROCESS_MANAGER.get().unwrap().ready_queue.lock().is_empty());
 }
 
+/// Keep the low 8 bits of an exit code like Unix does, so the status
+/// returned to waiters never falls in the errno range of a syscall return
+fn exit_status(ret: isize) -> isize {
+    ret & 0xff
+}
+
 pub fn get_process_manager() -> &'static ProcessManager {
     PROCESS_MANAGER
         .get()
@@ -78,6 +85,11 @@ impl ProcessManager {
     }
 
     #[inline]
+    /// All processes, ordered by pid
+    pub fn processes(&self) -> Vec<Arc<Process>> {
+        self.processes.read().values().cloned().collect()
+    }
+
     pub fn get_proc(&self, pid: &ProcessId) -> Option<Arc<Process>> {
         self.processes.read().get(pid).cloned()
     }
@@ -87,14 +99,22 @@ impl ProcessManager {
             .expect("No current process")
     }
 
-    pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
+    pub fn read(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
         self.current().read().read(fd, buf)
     }
 
-    pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
+    pub fn write(&self, fd: u8, buf: &[u8]) -> SysResult<usize> {
         self.current().read().write(fd, buf)
     }
 
+    pub fn seek(&self, fd: u8, pos: storage::SeekFrom) -> SysResult<usize> {
+        self.current().read().seek(fd, pos)
+    }
+
+    pub fn read_dir(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+        self.current().read().read_dir(fd, buf)
+    }
+
     pub fn save_current(&self, context: &ProcessContext) {
         // FIXME: update current process's tick count
         // FIXME: save current process's context
@@ -254,7 +274,14 @@ impl ProcessManager {
 
     pub fn handle_page_fault(&self, addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
         // FIXME: handle page fault
-        if err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION){
+        if err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE) {
+            // a write to a page shared since fork
+            if self.current().write().vm_mut().handle_cow_fault(addr) {
+                return true;
+            }
+            warn!("Page fault: write to a read-only page at {:#x}", addr);
+            return false;
+        }else if err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION){
             warn!("Page fault: protection violation at {:#x}", addr);
             return false;
         }else if err_code.contains(PageFaultErrorCode::MALFORMED_TABLE) {
@@ -334,9 +361,14 @@ impl ProcessManager {
 
         proc.kill(ret);
 
+        // writes to files the process left open are not lost
+        if let Err(err) = crate::filesystem::sync() {
+            warn!("Failed to sync after process #{pid} exited: {err:?}");
+        }
+
         if let Some(pids) = self.wait_queue.lock().remove(&pid) {
             for pid in pids {
-                self.wake_up(pid, Some(ret));
+                self.wake_up(pid, Some(exit_status(ret)));
             }
         }
     }
@@ -384,7 +416,15 @@ impl ProcessManager {
         let used = (frames_used - frames_recycled) * PAGE_SIZE as usize;
         let total = frames_total * PAGE_SIZE as usize;
         output += &Self::format_usage("Memory", used, total);
+        let free_blocks = alloc.free_blocks();
         drop(alloc);
+
+        // free blocks of 2^order frames
+        output += "Buddy  :";
+        for (order, count) in free_blocks.iter().enumerate() {
+            output += format!(" {}:{}", order, count).as_str();
+        }
+        output += "\n";
         output += format!("Queue  : {:?}\n", self.ready_queue.lock()).as_str();
         output += &processor::print_processors();
 
@@ -409,12 +449,13 @@ impl ProcessManager {
         )
     }
 
-    pub fn fork(&self) {
+    /// Fork the current process, `None` if there is no memory for the child
+    pub fn fork(&self, mode: ForkMode) -> Option<ProcessId> {
         // FIXME: get current process
         let current = self.current();
 
         // FIXME: fork to get child
-        let child = current.fork();
+        let child = current.fork(mode)?;
 
         // FIXME: add child to process list
         // self.add_proc(child.pid(), child);
@@ -426,6 +467,7 @@ impl ProcessManager {
 
         // FOR DBG: maybe print the process ready queue?
         debug!("Process ready queue: {:#?}", self.ready_queue.lock());
+        Some(child_pid)
     }
 
     /// Block the process with the given pid
@@ -436,10 +478,11 @@ impl ProcessManager {
         }
     }
 
+    /// The exit status of a dead process, as `wait_pid` reports it
     pub fn get_exit_code(&self, pid: ProcessId) -> Option<isize> {
         if let Some(proc) = self.get_proc(&pid) {
             if proc.read().status() == ProgramStatus::Dead {
-                return proc.read().exit_code();
+                return proc.read().exit_code().map(exit_status);
             }
         }
         None
@@ -500,13 +543,31 @@ impl ProcessManager {
         ret
     }
 
-    pub fn open_file(&self, path: &str) -> u8 {
-        self.current().write().open_file(path)
+    pub fn open_file(&self, path: &str, flags: OpenFlags) -> SysResult<u8> {
+        // do not hold the process lock, procfs may read the process itself
+        let data = self.current().read().data().clone();
+        data.open_file(path, flags)
     }
     
     pub fn close_file(&self, fd: u8) -> bool {
         self.current().write().close_file(fd)
     }
+
+    pub fn pipe(&self) -> SysResult<(u8, u8)> {
+        self.current().read().pipe()
+    }
+
+    pub fn dup(&self, fd: u8) -> SysResult<u8> {
+        self.current().read().dup(fd)
+    }
+
+    pub fn dup2(&self, old_fd: u8, new_fd: u8) -> SysResult<u8> {
+        self.current().read().dup2(old_fd, new_fd)
+    }
+
+    pub fn fd_limit(&self, limit: Option<usize>) -> usize {
+        self.current().read().fd_limit(limit)
+    }
 }
 
 impl core::fmt::Debug for ProcessManager {
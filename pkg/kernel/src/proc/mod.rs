@@ -7,6 +7,8 @@ mod vm;
 pub mod processor;
 pub mod manager;
 pub mod sync;
+pub mod procfs;
+pub mod uaccess;
 
 use manager::*;
 use process::*;
@@ -27,6 +29,7 @@ pub use paging::PageTableContext;
 pub use data::ProcessData;
 pub use pid::ProcessId;
 
+use syscall_def::{OpenFlags, SysError, SysResult};
 use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::VirtAddr;
 pub const KERNEL_PID: ProcessId = ProcessId(1);
@@ -224,9 +227,10 @@ pub fn elf_spawn(name: String, elf: &ElfFile) -> Option<ProcessId> {
     let pid = x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let process_name = name.to_lowercase();
-        let parent = Arc::downgrade(&manager.current());
+        let current = manager.current();
+        let data = current.read().data().spawn();
         // info!("Spawning process: {}", process_name);
-        let pid = manager.spawn(elf, name, Some(parent), None);
+        let pid = manager.spawn(elf, name, Some(Arc::downgrade(&current)), Some(data));
 
         debug!("Spawned process: {}#{}", process_name, pid);
         pid
@@ -235,14 +239,22 @@ pub fn elf_spawn(name: String, elf: &ElfFile) -> Option<ProcessId> {
     Some(pid)
 }
 
-pub fn read(fd: u8, buf: &mut [u8]) -> isize {
+pub fn read(fd: u8, buf: &mut [u8]) -> SysResult<usize> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().read(fd, buf))
 }
 
-pub fn write(fd: u8, buf: &[u8]) -> isize {
+pub fn write(fd: u8, buf: &[u8]) -> SysResult<usize> {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().write(fd, buf))
 }
 
+pub fn seek(fd: u8, pos: storage::SeekFrom) -> SysResult<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().seek(fd, pos))
+}
+
+pub fn read_dir(fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().read_dir(fd, buf))
+}
+
 pub fn exit(ret: isize, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
@@ -257,7 +269,10 @@ pub fn exit(ret: isize, context: &mut ProcessContext) {
 pub fn wait_process(pid: ProcessId, context: &mut ProcessContext){
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        if let Some(ret) = manager.get_exit_code(pid) {
+        if pid == processor::get_pid() || manager.get_proc(&pid).is_none() {
+            // nobody would ever wake us up
+            context.set_rax(SysError::NoProcess.encode());
+        } else if let Some(ret) = manager.get_exit_code(pid) {
             context.set_rax(ret as usize);
         } else {
             manager.wait_pid(pid);
@@ -325,7 +340,16 @@ pub fn still_alive(pid: ProcessId) -> bool {
     })
 }
 
-pub fn fork(context: &mut ProcessContext) {
+/// How the child of a fork gets its memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ForkMode {
+    /// A private copy of the address space, copied on write
+    Process,
+    /// The same address space, on a stack of its own
+    Thread,
+}
+
+pub fn fork(context: &mut ProcessContext, mode: ForkMode) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         // FIXME: save_current as parent
@@ -334,7 +358,10 @@ pub fn fork(context: &mut ProcessContext) {
         manager.save_current(context);
         // FIXME: fork to get child
         // let child = manager.fork();
-        manager.fork();
+        if manager.fork(mode).is_none() {
+            // the parent goes on without a child
+            manager.current().write().set_rax(SysError::OutOfMemory.encode());
+        }
         // FIXME: push to child & parent to ready queue
         // manager.push_ready(child); // 这里不用吧？
         manager.push_ready(parent_pid);
@@ -348,19 +375,19 @@ pub fn fork(context: &mut ProcessContext) {
 //         get_process_manager().sem_new(key, value) as usize
 //     })
 // }
-pub fn sem_new(key: u32, val: usize) -> usize {
+pub fn sem_new(key: u32, val: usize) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         // info!("Creating new semaphore with key: {}, value: {}", key, val);
         let ret = manager.current().write().sem_new(key, val);
         // info!("returned from sem_new: {}", ret);
-        ret as usize
+        ret
     })
 }
 
-pub fn sem_remove(key: u32) -> usize {
+pub fn sem_remove(key: u32) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| {
-        get_process_manager().sem_remove(key) as usize
+        get_process_manager().sem_remove(key)
     })
 }
 
@@ -372,14 +399,14 @@ pub fn sem_signal(key: u32, context: &mut ProcessContext){
                 context.set_rax(0); // 成功
             }
             SemaphoreResult::NotExist => {
-                context.set_rax(1); // 信号量不存在
+                context.set_rax(SysError::NotFound.encode()); // 信号量不存在
             }
             SemaphoreResult::WakeUp(pid) => {
-                context.set_rax(2); // 唤醒了一个进程
-                get_process_manager().wake_up(pid, None);
+                context.set_rax(0); // 唤醒了一个进程
+                get_process_manager().wake_up(pid, Some(0));
             }
             _ => {
-                context.set_rax(usize::MAX); // 未知错误
+                context.set_rax(SysError::Unknown.encode()); // 未知错误
             }
         }
     })
@@ -392,7 +419,7 @@ pub fn sem_wait(key: u32, context: &mut ProcessContext) {
         let ret = manager.sem_wait(key, pid);
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
+            SemaphoreResult::NotExist => context.set_rax(SysError::NotFound.encode()),
             SemaphoreResult::Block(pid) => {
                 // FIXME: save, block it, then switch to next
                 //        use `save_current` and `switch_next`
@@ -405,14 +432,59 @@ pub fn sem_wait(key: u32, context: &mut ProcessContext) {
     })
 }
 
-pub fn open_file(path: &str) -> u8 {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().open_file(path))
+pub fn open_file(path: &str, flags: OpenFlags) -> SysResult<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().open_file(path, flags))
 }
 
 pub fn close_file(fd: u8) -> bool {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().close_file(fd))
 }
 
+pub fn pipe() -> SysResult<(u8, u8)> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().pipe())
+}
+
+/// Block current process until it is woken up, then restart the syscall
+///
+/// used when a read / write would block, e.g. on an empty pipe
+pub fn block_and_restart_syscall(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        // `int 0x80` is 2 bytes, rax still holds the syscall number
+        context.value.stack_frame.instruction_pointer -= 2u64;
+        manager.save_current(context);
+        manager.current().write().block();
+        manager.switch_next(context);
+    })
+}
+
+/// Wake up blocked processes, they will restart the syscall
+pub fn wake_up_blocked(pids: impl IntoIterator<Item = ProcessId>) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        for pid in pids {
+            let blocked = manager
+                .get_proc(&pid)
+                .is_some_and(|proc| proc.read().status() == ProgramStatus::Blocked);
+            if blocked {
+                manager.wake_up(pid, None);
+            }
+        }
+    })
+}
+
+pub fn dup(fd: u8) -> SysResult<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().dup(fd))
+}
+
+pub fn dup2(old_fd: u8, new_fd: u8) -> SysResult<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().dup2(old_fd, new_fd))
+}
+
+pub fn fd_limit(limit: Option<usize>) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().fd_limit(limit))
+}
+
 pub fn brk(addr: Option<VirtAddr>) -> Option<VirtAddr> {
     x86_64::instructions::interrupts::without_interrupts(|| {
         // NOTE: `brk` does not need to get write lock
@@ -1,13 +1,23 @@
 use crate::memory::*;
+use crate::memory::user::USER_HEAP_START;
 use core::ptr::copy_nonoverlapping;
 
 use alloc::sync::Arc;
 use x86_64::{
     registers::control::{Cr3, Cr3Flags},
     structures::paging::*,
-    VirtAddr,
+    PhysAddr, VirtAddr,
 };
 
+/// Marks a page shared by a fork, its frame is copied on the first write
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/// Entries of a page table
+const ENTRY_COUNT: usize = 512;
+
+/// Entries of the P4 table below the kernel space
+const USER_P4_ENTRIES: usize = ENTRY_COUNT / 2;
+
 pub struct Cr3RegValue {
     pub addr: PhysFrame,
     pub flags: Cr3Flags,
@@ -38,6 +48,7 @@ impl PageTableContext {
         let page_table_addr = frame_alloc
             .allocate_frame()
             .expect("Cannot alloc page table for new process.");
+        frame_alloc.set_owner(page_table_addr, FrameOwner::PAGE_TABLE);
 
         // 2. copy current page table to new page table
         unsafe {
@@ -76,12 +87,164 @@ impl PageTableContext {
         Arc::strong_count(&self.reg)
     }
 
+    /// The page table shared with a thread
+    ///
+    /// Each context holds a reference to the P4 frame, see `release`.
     pub fn fork(&self) -> Self {
+        get_frame_alloc_for_sure().inc_ref(self.reg.addr);
         // forked process shares the page table
         Self {
             reg: self.reg.clone(),
         }
     }
+
+    /// Drop this context's reference to the P4 frame, `true` if it was the last one
+    ///
+    /// The frame is back in the allocator then, the caller frees the user
+    /// space with `free_user_space` before it lets go of `alloc`.
+    pub fn release(&self, alloc: &mut BootInfoFrameAllocator) -> bool {
+        alloc.dec_ref(self.reg.addr) == 0
+    }
+
+    /// Free the user space tables and every frame still mapped there
+    ///
+    /// A forked process also maps the stacks of the other threads of its
+    /// parent, nothing else knows about them.
+    pub fn free_user_space(&self, alloc: &mut BootInfoFrameAllocator) {
+        let table = unsafe { table_mut(self.reg.addr) };
+        for i in 0..USER_P4_ENTRIES {
+            if is_private(i, &table[i]) {
+                free_table(table[i].addr(), 3, alloc);
+                table[i].set_unused();
+            }
+        }
+    }
+
+    /// Copy the user space tables into a new page table, the kernel space is shared
+    ///
+    /// Writable user pages become read-only and copy-on-write in both tables,
+    /// every user frame gets one more reference. `None` if out of frames,
+    /// the tables copied so far are freed then.
+    ///
+    /// The user heap from `sys_allocate` is not copied: the kernel keeps a
+    /// single allocator for it, whose free list lives in the heap itself,
+    /// so its pages stay shared and writable in every process.
+    pub fn clone_cow(&self) -> Option<Self> {
+        let mut frame_alloc = crate::memory::get_frame_alloc_for_sure();
+        let page_table_addr = frame_alloc.allocate_frame()?;
+        frame_alloc.set_owner(page_table_addr, FrameOwner::PAGE_TABLE);
+
+        let (parent, child) = unsafe { (table_mut(self.reg.addr), table_mut(page_table_addr)) };
+        for i in 0..ENTRY_COUNT {
+            child[i] = parent[i].clone();
+            if !is_private(i, &parent[i]) {
+                continue;
+            }
+            match copy_table(parent[i].addr(), 3, &mut frame_alloc) {
+                Some(table) => child[i].set_addr(table, parent[i].flags()),
+                None => {
+                    for (i, copy) in child.iter().enumerate().take(i) {
+                        if is_private(i, copy) {
+                            free_table(copy.addr(), 3, &mut frame_alloc);
+                        }
+                    }
+                    frame_alloc.dec_ref(page_table_addr);
+                    return None;
+                }
+            }
+        }
+
+        // the parent lost write access to its pages
+        x86_64::instructions::tlb::flush_all();
+
+        Some(Self {
+            reg: Arc::new(Cr3RegValue::new(page_table_addr, Cr3Flags::empty())),
+        })
+    }
+}
+
+/// The page table in the frame
+///
+/// # Safety
+///
+/// The frame must hold a page table.
+unsafe fn table_mut(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *(physical_to_virtual(frame.start_address().as_u64()) as *mut PageTable) }
+}
+
+/// A P4 entry copied into a forked process rather than shared
+///
+/// The user heap of the kernel is mapped in every process and stays shared,
+/// data allocated there before a fork is seen by both processes.
+fn is_private(index: usize, entry: &page_table::PageTableEntry) -> bool {
+    let shared = usize::from(VirtAddr::new(USER_HEAP_START as u64).p4_index());
+    index < USER_P4_ENTRIES
+        && index != shared
+        && entry.flags().contains(PageTableFlags::USER_ACCESSIBLE)
+}
+
+/// Copy a P3, P2 or P1 table of the user space, return the address of the copy
+///
+/// User pages in P1 tables are marked copy-on-write, other entries are copied as is.
+/// `None` if out of frames, nothing of the copy is left then.
+fn copy_table(addr: PhysAddr, level: u8, alloc: &mut BootInfoFrameAllocator) -> Option<PhysAddr> {
+    let frame = alloc.allocate_frame()?;
+    alloc.set_owner(frame, FrameOwner::PAGE_TABLE);
+    let (src, dst) = unsafe { (table_mut(PhysFrame::containing_address(addr)), table_mut(frame)) };
+
+    for (i, (entry, copy)) in src.iter_mut().zip(dst.iter_mut()).enumerate() {
+        let flags = entry.flags();
+        *copy = entry.clone();
+        if entry.is_unused() || !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            continue;
+        }
+
+        if level == 1 {
+            if flags.intersects(PageTableFlags::WRITABLE | COW) {
+                let flags = (flags - PageTableFlags::WRITABLE) | COW;
+                entry.set_flags(flags);
+                copy.set_flags(flags);
+            }
+            let frame = PhysFrame::containing_address(entry.addr());
+            alloc.inc_ref(frame);
+            alloc.set_owner(frame, FrameOwner::USER);
+        } else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+            // user space is mapped in 4 KiB pages only
+            match copy_table(entry.addr(), level - 1, alloc) {
+                Some(table) => copy.set_addr(table, flags),
+                None => {
+                    // the entry still points to the parent's table
+                    copy.set_unused();
+                    free_entries(frame, level, i, alloc);
+                    return None;
+                }
+            }
+        }
+    }
+
+    Some(frame.start_address())
+}
+
+/// Free a table made by `copy_table`, dropping the references it holds
+fn free_table(addr: PhysAddr, level: u8, alloc: &mut BootInfoFrameAllocator) {
+    free_entries(PhysFrame::containing_address(addr), level, ENTRY_COUNT, alloc);
+}
+
+/// Free the first `count` entries of a copied table and the table itself
+fn free_entries(frame: PhysFrame, level: u8, count: usize, alloc: &mut BootInfoFrameAllocator) {
+    let table = unsafe { table_mut(frame) };
+    for entry in table.iter().take(count) {
+        let flags = entry.flags();
+        if entry.is_unused() || !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            continue;
+        }
+        if level == 1 {
+            alloc.dec_ref(PhysFrame::containing_address(entry.addr()));
+        } else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+            free_table(entry.addr(), level - 1, alloc);
+        }
+    }
+    alloc.dec_ref(frame);
 }
 
 impl core::fmt::Debug for PageTableContext {
@@ -99,14 +99,15 @@ impl Process {
     //     self.write().vm_mut().init_proc_stack(self.pid)
     // }
 
-    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+    /// `None` if there is no memory for the child
+    pub fn fork(self: &Arc<Self>, mode: ForkMode) -> Option<Arc<Self>> {
         // FIXME: lock inner as write
         let mut inner = self.inner.write();
         // FIXME: inner fork with parent weak ref
         let parent = Arc::downgrade(self);
-        // 先创建新进程child的pid
+        let child_inner = inner.fork(parent, mode)?;
+        // 再创建新进程child的pid
         let child_pid = ProcessId::new();
-        let child_inner = inner.fork(parent);
 
         // FOR DBG: maybe print the child process info
         //          e.g. parent, name, pid, etc.
@@ -125,7 +126,7 @@ impl Process {
         // inner.pause(); // 不是哥们 这里应该要把父进程的状态改成ready吧？
         // child.write().pause(); // 这样？
         child.inner.write().pause(); // 这样？
-        child
+        Some(child)
     }
 }
 
@@ -178,6 +179,11 @@ impl ProcessInner {
         self.proc_vm.as_ref().unwrap()
     }
 
+    /// The vm, `None` once the process is dead
+    pub fn try_vm(&self) -> Option<&ProcessVm> {
+        self.proc_vm.as_ref()
+    }
+
     pub fn vm_mut(&mut self) -> &mut ProcessVm {
         // info!("vm_mut");
         self.proc_vm.as_mut().unwrap()
@@ -305,43 +311,45 @@ impl ProcessInner {
         self.vm_mut().load_elf(elf)
     }
 
-    pub fn fork(&mut self, parent: Weak<Process>) -> ProcessInner {
+    pub fn fork(&mut self, parent: Weak<Process>, mode: ForkMode) -> Option<ProcessInner> {
         // FIXME: fork the process virtual memory struct
-        // 这个应该是要后算的？因为fork里面有个stack_offset_count啊
-        // FIXME: calculate the real stack offset
-        // 以上两个写一起，暂时认为顺序应该是反过来的。
-        // 这里的逻辑应该是，按照文档的图，总子进程个数n，那就是0x400000000000-(n+1)*0x100000000？
-        let child_count = self.children.len() as u64;
-        // let stack_offset_count = (child_count + 1) * 0x100000000;//这不对，应该是页数
-        let stack_offset_count = (child_count + 1) * STACK_MAX_PAGES;
-        let child_vm = self //克隆父进程的虚拟内存空间
-            .proc_vm
-            .as_ref()
-            .unwrap()
-            .fork(stack_offset_count);
-
-        // FIXME: update `rsp` in interrupt stack frame
-        // rsp是中断栈帧的返回地址，先克隆上下文的寄存器状态
+        let vm = self.proc_vm.as_ref().unwrap();
         let mut child_context = self.context.clone();
-        // 然后计算新的栈顶地址，因为在vm的fork（其实是stack的fork）里面我们尝试分配新的栈空间
-        // 高32位用进程新栈基地址的高32位，低32位使用原栈顶地址的低32位（保留栈偏移量）
-        let child_stack_start_addr = child_vm.stack.range.start.start_address().as_u64();
-        let high32 = !0xFFFFFFFF & child_stack_start_addr;
-        let parent_stack_top = self.context.value.stack_frame.stack_pointer.as_u64();
-        let low32 = parent_stack_top & 0xFFFFFFFF;
-        let new_rsp = high32 | low32;
-        let child_stack_top = VirtAddr::new(new_rsp);
-        // 然后设置新的栈顶地址
-        child_context.value.stack_frame.stack_pointer = child_stack_top;
-        // 这样在返回的时候，子进程就可以从新的栈顶地址开始找
+
+        let child_vm = match mode {
+            // the stack is copied at the same address, `rsp` stays
+            ForkMode::Process => vm.fork()?,
+            ForkMode::Thread => {
+                // FIXME: calculate the real stack offset
+                // 这里的逻辑应该是，按照文档的图，总子进程个数n，那就是0x400000000000-(n+1)*0x100000000？
+                let child_count = self.children.len() as u64;
+                // let stack_offset_count = (child_count + 1) * 0x100000000;//这不对，应该是页数
+                let stack_offset_count = (child_count + 1) * STACK_MAX_PAGES;
+                let child_vm = vm.fork_thread(stack_offset_count);
+
+                // FIXME: update `rsp` in interrupt stack frame
+                // 然后计算新的栈顶地址，因为在vm的fork（其实是stack的fork）里面我们尝试分配新的栈空间
+                // 高32位用进程新栈基地址的高32位，低32位使用原栈顶地址的低32位（保留栈偏移量）
+                let child_stack_start_addr = child_vm.stack.range.start.start_address().as_u64();
+                let high32 = !0xFFFFFFFF & child_stack_start_addr;
+                let parent_stack_top = self.context.value.stack_frame.stack_pointer.as_u64();
+                let low32 = parent_stack_top & 0xFFFFFFFF;
+                let new_rsp = high32 | low32;
+                // 这样在返回的时候，子进程就可以从新的栈顶地址开始找
+                child_context.value.stack_frame.stack_pointer = VirtAddr::new(new_rsp);
+                child_vm
+            }
+        };
+
         // FIXME: set the return value 0 for child with `context.set_rax`
         child_context.set_rax(0);
         // FIXME: clone the process data struct
-        let child_proc_data = self
-            .proc_data
-            .as_ref()
-            .unwrap()
-            .clone(); // 这里是深拷贝
+        // 进程复制一份文件描述符表，线程共享同一张表
+        let parent_data = self.proc_data.as_ref().unwrap();
+        let child_proc_data = match mode {
+            ForkMode::Process => parent_data.fork(),
+            ForkMode::Thread => parent_data.clone(),
+        };
         // FIXME: construct the child process inner
         // 我们克隆父进程的名字后加一个child再加上当前序号，也就是children.len() + 1
         let child_name = self.name.clone() + "-child" + &(self.children.len() + 1).to_string();
@@ -358,7 +366,7 @@ impl ProcessInner {
         };
 
         // NOTE: return inner because there's no pid record in inner
-        child_inner
+        Some(child_inner)
     }
 
     pub fn set_rax(&mut self, value: usize) {
@@ -382,8 +390,12 @@ impl ProcessInner {
         self.data_mut().sem_signal(key)
     }
 
-    pub fn open_file(&mut self, path: &str) -> u8 {
-        self.proc_data.as_mut().unwrap().open_file(path)
+    pub fn open_file(
+        &mut self,
+        path: &str,
+        flags: syscall_def::OpenFlags,
+    ) -> syscall_def::SysResult<u8> {
+        self.proc_data.as_mut().unwrap().open_file(path, flags)
     }
     
     pub fn brk(&self,addr: Option<VirtAddr>) -> Option<VirtAddr>{
@@ -0,0 +1,295 @@
+//! Process File System
+//!
+//! Text files generated from kernel state when opened, mounted at `/proc`.
+//!
+//! - `/proc/<pid>/status`: name, pid, ppid, state, ticks and memory
+//! - `/proc/<pid>/maps`: mapped regions of the process
+//! - `/proc/meminfo`: physical frames
+//! - `/proc/mounts`: mounted filesystems
+//! - `/proc/uptime`: seconds since boot
+//! - `/proc/diskstats`: block cache statistics of partitions
+//! - `/proc/drives`: drives found on the ATA buses
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+use storage::*;
+use x86_64::instructions::interrupts;
+
+use super::*;
+use crate::memory::{get_frame_alloc_for_sure, PAGE_SIZE};
+
+const SYSTEM_FILES: [&str; 5] = ["diskstats", "drives", "meminfo", "mounts", "uptime"];
+const PROCESS_FILES: [&str; 2] = ["maps", "status"];
+
+/// A node of the procfs
+enum ProcNode {
+    Root,
+    System(&'static str),
+    ProcessDir(Arc<Process>),
+    ProcessFile(Arc<Process>, &'static str),
+}
+
+/// The procfs, nothing is stored
+#[derive(Debug)]
+pub struct ProcFs;
+
+impl ProcFs {
+    fn find_process(pid: &str) -> FsResult<Arc<Process>> {
+        let pid = pid.parse::<u16>().map_err(|_| FsError::FileNotFound)?;
+        interrupts::without_interrupts(|| {
+            get_process_manager()
+                .get_proc(&ProcessId(pid))
+                .ok_or(FsError::FileNotFound)
+        })
+    }
+
+    fn lookup(path: &str) -> FsResult<ProcNode> {
+        let mut names = path.split('/').filter(|name| !name.is_empty());
+
+        let node = match (names.next(), names.next()) {
+            (None, _) => ProcNode::Root,
+            (Some(name), None) => match SYSTEM_FILES.iter().find(|&&f| f == name) {
+                Some(file) => ProcNode::System(file),
+                None => ProcNode::ProcessDir(Self::find_process(name)?),
+            },
+            (Some(pid), Some(name)) => {
+                let proc = Self::find_process(pid)?;
+                let file = PROCESS_FILES
+                    .iter()
+                    .find(|&&f| f == name)
+                    .ok_or(FsError::FileNotFound)?;
+                ProcNode::ProcessFile(proc, file)
+            }
+        };
+
+        match names.next() {
+            Some(_) => Err(FsError::FileNotFound),
+            None => Ok(node),
+        }
+    }
+
+    fn meta(name: String, entry_type: FileType, len: usize) -> Metadata {
+        let time = now();
+        Metadata::new(name, entry_type, len, Some(time), Some(time), Some(time))
+    }
+
+    fn node_meta(node: &ProcNode) -> Metadata {
+        match node {
+            ProcNode::Root => Self::meta(String::new(), FileType::Directory, 0),
+            ProcNode::System(name) => Self::meta(name.to_string(), FileType::File, 0),
+            ProcNode::ProcessDir(proc) => {
+                Self::meta(proc.pid().to_string(), FileType::Directory, 0)
+            }
+            ProcNode::ProcessFile(_, name) => Self::meta(name.to_string(), FileType::File, 0),
+        }
+    }
+
+    /// Generate the content of a file
+    fn content(node: &ProcNode) -> FsResult<String> {
+        interrupts::without_interrupts(|| match node {
+            ProcNode::System("diskstats") => Ok(diskstats()),
+            ProcNode::System("drives") => Ok(drives()),
+            ProcNode::System("meminfo") => Ok(meminfo()),
+            ProcNode::System("mounts") => Ok(mounts()),
+            ProcNode::System("uptime") => Ok(uptime()),
+            ProcNode::ProcessFile(proc, "status") => Ok(status(proc)),
+            ProcNode::ProcessFile(proc, "maps") => Ok(maps(proc)),
+            _ => Err(FsError::NotAFile),
+        })
+    }
+}
+
+impl FileSystem for ProcFs {
+    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+        let entries: Vec<Metadata> = match Self::lookup(path)? {
+            ProcNode::Root => {
+                let procs = interrupts::without_interrupts(|| get_process_manager().processes());
+                SYSTEM_FILES
+                    .iter()
+                    .map(|name| Self::node_meta(&ProcNode::System(name)))
+                    .chain(
+                        procs
+                            .into_iter()
+                            .map(|proc| Self::node_meta(&ProcNode::ProcessDir(proc))),
+                    )
+                    .collect()
+            }
+            ProcNode::ProcessDir(proc) => PROCESS_FILES
+                .iter()
+                .map(|name| Self::node_meta(&ProcNode::ProcessFile(proc.clone(), name)))
+                .collect(),
+            _ => return Err(FsError::NotADirectory),
+        };
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
+        let node = Self::lookup(path)?;
+        let data = Self::content(&node)?.into_bytes();
+
+        let mut meta = Self::node_meta(&node);
+        meta.len = data.len();
+        Ok(FileHandle::new(meta, Box::new(TextFile { data, offset: 0 })))
+    }
+
+    fn metadata(&self, path: &str) -> FsResult<Metadata> {
+        Ok(Self::node_meta(&Self::lookup(path)?))
+    }
+
+    fn exists(&self, path: &str) -> FsResult<bool> {
+        match Self::lookup(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::FileNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn create_file(&self, _path: &str) -> FsResult<FileHandle> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn append_file(&self, _path: &str) -> FsResult<FileHandle> {
+        Err(FsError::ReadOnly)
+    }
+}
+
+/// A snapshot of the generated text
+struct TextFile {
+    data: Vec<u8>,
+    offset: usize,
+}
+
+impl Read for TextFile {
+    fn read(&mut self, buf: &mut [u8]) -> FsResult<usize> {
+        let data = self.data.get(self.offset..).unwrap_or(&[]);
+        let len = buf.len().min(data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+impl Write for TextFile {
+    fn write(&mut self, _buf: &[u8]) -> FsResult<usize> {
+        Err(FsError::ReadOnly)
+    }
+
+    fn flush(&mut self) -> FsResult {
+        Ok(())
+    }
+}
+
+impl Seek for TextFile {
+    fn seek(&mut self, pos: SeekFrom) -> FsResult<usize> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.data.len().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+        };
+
+        match offset {
+            Some(offset) if offset <= self.data.len() => {
+                self.offset = offset;
+                Ok(offset)
+            }
+            _ => Err(FsError::InvalidOffset),
+        }
+    }
+}
+
+fn status(proc: &Arc<Process>) -> String {
+    let inner = proc.read();
+    let memory = inner.try_vm().map_or(0, |vm| vm.memory_usage());
+
+    let mut output = String::new();
+    let _ = writeln!(output, "Name:\t{}", inner.name());
+    let _ = writeln!(output, "Pid:\t{}", proc.pid());
+    let _ = writeln!(output, "PPid:\t{}", inner.parent().map_or(0, |p| p.pid().0));
+    let _ = writeln!(output, "State:\t{:?}", inner.status());
+    let _ = writeln!(output, "Ticks:\t{}", inner.ticks_passed());
+    let _ = writeln!(output, "Memory:\t{} kB", memory / 1024);
+    output
+}
+
+fn maps(proc: &Arc<Process>) -> String {
+    let inner = proc.read();
+
+    let mut output = String::new();
+    for (start, end, name) in inner.try_vm().map(|vm| vm.regions()).unwrap_or_default() {
+        let _ = writeln!(output, "{start:016x}-{end:016x} {name}");
+    }
+    output
+}
+
+fn meminfo() -> String {
+    let alloc = get_frame_alloc_for_sure();
+    let total = alloc.frames_total();
+    let used = alloc.frames_used() - alloc.frames_recycled();
+    let shared = alloc.frames_shared();
+    drop(alloc);
+
+    let kb = |frames: usize| frames * PAGE_SIZE as usize / 1024;
+
+    let mut output = String::new();
+    let _ = writeln!(output, "MemTotal:\t{} kB", kb(total));
+    let _ = writeln!(output, "MemUsed:\t{} kB", kb(used));
+    let _ = writeln!(output, "MemFree:\t{} kB", kb(total.saturating_sub(used)));
+    let _ = writeln!(output, "FramesTotal:\t{total}");
+    let _ = writeln!(output, "FramesUsed:\t{used}");
+    let _ = writeln!(output, "FramesShared:\t{shared}");
+    output
+}
+
+fn mounts() -> String {
+    let mut output = String::new();
+    for (source, point, fs_type) in crate::filesystem::mounts() {
+        let _ = writeln!(output, "{source} {point} {fs_type}");
+    }
+    output
+}
+
+fn diskstats() -> String {
+    let mut output = String::from("name hits misses evictions writebacks cached dirty\n");
+    for (name, stats) in crate::filesystem::cache_stats() {
+        let _ = writeln!(
+            output,
+            "{} {} {} {} {} {} {}",
+            name,
+            stats.hits,
+            stats.misses,
+            stats.evictions,
+            stats.writebacks,
+            stats.cached,
+            stats.dirty
+        );
+    }
+    output
+}
+
+fn drives() -> String {
+    let mut output = String::from("name bus drive type size mode model serial\n");
+    for device in crate::drivers::ata::devices() {
+        let _ = writeln!(
+            output,
+            "{} {} {} {} {} {} {} {}",
+            device.name(),
+            device.bus(),
+            device.drive(),
+            device.kind(),
+            device.size(),
+            if device.is_read_only() { "ro" } else { "rw" },
+            device.model(),
+            device.serial()
+        );
+    }
+    output
+}
+
+fn uptime() -> String {
+    let millis = crate::interrupt::clock::sys_time().num_milliseconds();
+    format!("{}.{:02}\n", millis / 1000, millis % 1000 / 10)
+}
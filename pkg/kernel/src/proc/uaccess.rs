@@ -0,0 +1,123 @@
+use core::marker::PhantomData;
+use syscall_def::{SysError, SysResult};
+use x86_64::VirtAddr;
+
+use super::manager::get_process_manager;
+
+// 用户态地址空间的上界（canonical 低半区），内核地址都在这之上
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// Check `[addr, addr + len)` is a valid range of the current process
+fn check_user_range(addr: usize, len: usize, write: bool) -> bool {
+    let end = match (addr as u64).checked_add(len as u64) {
+        Some(end) => end,
+        None => return false,
+    };
+
+    // must be canonical and below the user/kernel split
+    if VirtAddr::try_new(addr as u64).is_err() || end > USER_SPACE_END {
+        return false;
+    }
+
+    if len == 0 {
+        return true;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let mut inner = proc.write();
+        let vm = inner.vm_mut();
+        // pages shared since fork get a private copy before the kernel writes them
+        if write {
+            vm.prepare_write(VirtAddr::new(addr as u64), len as u64);
+        }
+        vm.is_user_accessible(VirtAddr::new(addr as u64), len as u64, write)
+    })
+}
+
+/// A byte buffer passed in by a user program (ptr + len)
+#[derive(Debug, Clone, Copy)]
+pub struct UserSlice {
+    addr: usize,
+    len: usize,
+}
+
+impl UserSlice {
+    pub fn new(addr: usize, len: usize) -> Self {
+        Self { addr, len }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the buffer for reading, `BadAddress` if the range is not readable by user
+    pub fn as_slice(&self) -> SysResult<&[u8]> {
+        if !check_user_range(self.addr, self.len, false) {
+            return Err(SysError::BadAddress);
+        }
+        if self.len == 0 {
+            return Ok(&[]);
+        }
+        Ok(unsafe { core::slice::from_raw_parts(self.addr as *const u8, self.len) })
+    }
+
+    /// Get the buffer for writing, `BadAddress` if the range is not writable by user
+    pub fn as_mut_slice(&mut self) -> SysResult<&mut [u8]> {
+        if !check_user_range(self.addr, self.len, true) {
+            return Err(SysError::BadAddress);
+        }
+        if self.len == 0 {
+            return Ok(&mut []);
+        }
+        Ok(unsafe { core::slice::from_raw_parts_mut(self.addr as *mut u8, self.len) })
+    }
+
+    /// Get the buffer as utf-8 str, `InvalidArgument` if not utf-8
+    pub fn as_str(&self) -> SysResult<&str> {
+        core::str::from_utf8(self.as_slice()?).map_err(|_| SysError::InvalidArgument)
+    }
+}
+
+/// A pointer to a single `T` passed in by a user program
+#[derive(Debug, Clone, Copy)]
+pub struct UserPtr<T> {
+    addr: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Copy> UserPtr<T> {
+    pub fn new(addr: usize) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn check(&self, write: bool) -> bool {
+        self.addr != 0
+            && self.addr % core::mem::align_of::<T>() == 0
+            && check_user_range(self.addr, core::mem::size_of::<T>(), write)
+    }
+
+    /// Copy the value from user space
+    pub fn read(&self) -> SysResult<T> {
+        if !self.check(false) {
+            return Err(SysError::BadAddress);
+        }
+        Ok(unsafe { core::ptr::read(self.addr as *const T) })
+    }
+
+    /// Copy the value to user space
+    pub fn write(&self, value: T) -> SysResult<()> {
+        if !self.check(true) {
+            return Err(SysError::BadAddress);
+        }
+        unsafe { core::ptr::write(self.addr as *mut T, value) };
+        Ok(())
+    }
+}
@@ -50,6 +50,14 @@ impl Heap {
         }
     }
 
+    /// The same range with an end of its own, for a copied address space
+    pub fn duplicate(&self) -> Self {
+        Self {
+            base: self.base,
+            end: Arc::new(AtomicU64::new(self.end.load(Ordering::Relaxed))),
+        }
+    }
+
     pub fn brk(
         &self,
         new_end: Option<VirtAddr>,
@@ -131,6 +139,11 @@ impl Heap {
         Ok(())
     }
 
+    /// The current range `[base, end)` of the heap
+    pub fn range(&self) -> (VirtAddr, VirtAddr) {
+        (self.base, VirtAddr::new(self.end.load(Ordering::Relaxed)))
+    }
+
     pub fn memory_usage(&self) -> u64 {
         self.end.load(Ordering::Relaxed) - self.base.as_u64()
     }
@@ -1,7 +1,7 @@
 use alloc::{format, vec::Vec};
 use x86_64::{
     structures::paging::{
-        mapper::{CleanUp, UnmapError},
+        mapper::UnmapError,
         page::*,
         *,
     },
@@ -28,7 +28,7 @@ type MapperRef<'a> = &'a mut OffsetPageTable<'static>;
 type FrameAllocatorRef<'a> = &'a mut BootInfoFrameAllocator;
 
 pub struct ProcessVm {
-    // page table is shared by parent and child
+    // page table is shared by threads, copied for forked processes
     pub(super) page_table: PageTableContext,
 
     // stack is pre-process allocated
@@ -37,8 +37,8 @@ pub struct ProcessVm {
     // heap is allocated by brk syscall
     pub(super) heap: Heap,
 
-    // code is hold by the first process
-    // these fields will be empty for other processes
+    // code is hold by the first process and its forked copies
+    // these fields will be empty for threads
     pub(super) code: Vec<PageRangeInclusive>,
     pub(super) code_usage: u64,
 }
@@ -118,7 +118,24 @@ impl ProcessVm {
         trace!("Code usage: {}", self.code_usage);
     }
 
-    pub fn fork(&self, stack_offset_count: u64) -> Self {
+    /// A private copy of the address space, the pages are copied on write
+    ///
+    /// `None` if there are no frames left for the page tables.
+    pub fn fork(&self) -> Option<Self> {
+        Some(Self {
+            page_table: self.page_table.clone_cow()?,
+            // at the same address as the parent's
+            stack: self.stack.clone(),
+            heap: self.heap.duplicate(),
+
+            // the child holds references to the code frames as well
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+        })
+    }
+
+    /// Share the address space with a thread, which gets a stack of its own
+    pub fn fork_thread(&self, stack_offset_count: u64) -> Self {
         let owned_page_table = self.page_table.fork();
         let mapper = &mut owned_page_table.mapper();
 
@@ -144,6 +161,123 @@ impl ProcessVm {
         self.stack.handle_page_fault(addr, mapper, alloc)
     }
 
+    /// Give a copy-on-write page a private writable frame, `false` if it is not one
+    ///
+    /// The frame is copied unless no other page table refers to it any more.
+    pub fn handle_cow_fault(&mut self, addr: VirtAddr) -> bool {
+        let mapper = &mut self.page_table.mapper();
+        let page = Page::<Size4KiB>::containing_address(addr);
+
+        let (frame, flags) = match mapper.translate(page.start_address()) {
+            mapper::TranslateResult::Mapped {
+                frame: mapper::MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } if flags.contains(super::paging::COW) => (frame, flags),
+            _ => return false,
+        };
+        let flags = (flags - super::paging::COW) | PageTableFlags::WRITABLE;
+        let alloc = &mut *get_frame_alloc_for_sure();
+
+        if alloc.ref_count(frame) == 1 {
+            return match unsafe { mapper.update_flags(page, flags) } {
+                Ok(flush) => {
+                    flush.flush();
+                    true
+                }
+                Err(_) => false,
+            };
+        }
+
+        let Some(copy) = alloc.allocate_frame() else {
+            warn!("No frame to copy the page at {:#x}", addr);
+            return false;
+        };
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                physical_to_virtual(frame.start_address().as_u64()) as *const u8,
+                physical_to_virtual(copy.start_address().as_u64()) as *mut u8,
+                PAGE_SIZE as usize,
+            );
+        }
+
+        // drop this table's reference, the frame stays with the others
+        match mapper.unmap(page) {
+            Ok((_, flush)) => flush.ignore(),
+            Err(_) => return false,
+        }
+        alloc.dec_ref(frame);
+        alloc.set_owner(copy, FrameOwner::USER);
+
+        match unsafe { mapper.map_to(page, copy, flags, alloc) } {
+            Ok(flush) => {
+                flush.flush();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Copy the copy-on-write pages of `[addr, addr + len)` before the kernel writes to them
+    pub fn prepare_write(&mut self, addr: VirtAddr, len: u64) {
+        if len == 0 {
+            return;
+        }
+
+        let start = Page::<Size4KiB>::containing_address(addr);
+        let end = Page::<Size4KiB>::containing_address(addr + (len - 1));
+        for page in Page::range_inclusive(start, end) {
+            self.handle_cow_fault(page.start_address());
+        }
+    }
+
+    /// Check every page of `[addr, addr + len)` is present and user accessible
+    pub fn is_user_accessible(&self, addr: VirtAddr, len: u64, write: bool) -> bool {
+        if len == 0 {
+            return true;
+        }
+
+        let mapper = self.page_table.mapper();
+        let mut flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+        if write {
+            flags |= PageTableFlags::WRITABLE;
+        }
+
+        let start = Page::<Size4KiB>::containing_address(addr);
+        let end = Page::<Size4KiB>::containing_address(addr + (len - 1));
+
+        Page::range_inclusive(start, end).all(|page| {
+            match mapper.translate(page.start_address()) {
+                mapper::TranslateResult::Mapped { flags: f, .. } => f.contains(flags),
+                _ => false,
+            }
+        })
+    }
+
+    /// Mapped regions as `(start, end, name)`, end is exclusive
+    pub fn regions(&self) -> Vec<(VirtAddr, VirtAddr, &'static str)> {
+        let mut regions: Vec<_> = self
+            .code
+            .iter()
+            .map(|r| (r.start.start_address(), r.end.start_address() + r.end.size(), "code"))
+            .collect();
+
+        let (heap_start, heap_end) = self.heap.range();
+        if heap_end > heap_start {
+            regions.push((heap_start, heap_end, "heap"));
+        }
+
+        if self.stack.usage > 0 {
+            regions.push((
+                self.stack.range.start.start_address(),
+                self.stack.range.end.start_address(),
+                "stack",
+            ));
+        }
+
+        regions
+    }
+
     pub(super) fn memory_usage(&self) -> u64 {
         self.stack.memory_usage() + self.heap.memory_usage() + self.code_usage
     }
@@ -159,7 +293,8 @@ impl ProcessVm {
         // FIXME: implement the `clean_up` function for `Stack`
         self.stack.clean_up(mapper, dealloc)?;
 
-        if self.page_table.using_count() == 1 {
+        // the last thread of the address space frees it
+        if self.page_table.release(dealloc) {
             // free heap
             // FIXME: implement the `clean_up` function for `Heap`
             self.heap.clean_up(mapper, dealloc)?;
@@ -183,13 +318,8 @@ impl ProcessVm {
                 }
             }
 
-            unsafe {
-                // free P1-P3
-                mapper.clean_up(dealloc);
-
-                // free P4
-                dealloc.deallocate_frame(self.page_table.reg.addr);
-            }
+            // free P1-P3 and what is still mapped
+            self.page_table.free_user_space(dealloc);
         }
 
         // statistics for logging and debugging
@@ -44,6 +44,7 @@ const KSTACK_INIT_PAGE: Page<Size4KiB> = Page::containing_address(VirtAddr::new(
 const KSTACK_INIT_TOP_PAGE: Page<Size4KiB> =
     Page::containing_address(VirtAddr::new(KSTACK_INIT_TOP));
 
+#[derive(Clone)]
 pub struct Stack {
     pub range: PageRange<Size4KiB>,
     pub usage: u64,
@@ -7,6 +7,7 @@ mod regs;
 
 pub mod func;
 pub mod logger;
+pub mod pipe;
 pub mod resource;
 
 pub use macros::*;
@@ -0,0 +1,154 @@
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::sync::Arc;
+use spin::Mutex;
+use syscall_def::{SysError, SysResult};
+
+use crate::proc::{processor, wake_up_blocked, ProcessId};
+
+/// Capacity of the pipe ring buffer
+pub const PIPE_SIZE: usize = 4096;
+
+#[derive(Debug)]
+struct PipeBuffer {
+    data: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    // 等待数据 / 等待空间的进程
+    read_waiters: BTreeSet<ProcessId>,
+    write_waiters: BTreeSet<ProcessId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipeEnd {
+    Read,
+    Write,
+}
+
+/// One end of an anonymous pipe
+#[derive(Debug)]
+pub struct Pipe {
+    buf: Arc<Mutex<PipeBuffer>>,
+    end: PipeEnd,
+}
+
+impl Pipe {
+    /// Create a new pipe, return (read end, write end)
+    pub fn new() -> (Pipe, Pipe) {
+        let buf = Arc::new(Mutex::new(PipeBuffer {
+            data: VecDeque::with_capacity(PIPE_SIZE),
+            readers: 1,
+            writers: 1,
+            read_waiters: BTreeSet::new(),
+            write_waiters: BTreeSet::new(),
+        }));
+
+        (
+            Pipe {
+                buf: buf.clone(),
+                end: PipeEnd::Read,
+            },
+            Pipe {
+                buf,
+                end: PipeEnd::Write,
+            },
+        )
+    }
+
+    pub fn end(&self) -> PipeEnd {
+        self.end
+    }
+
+    /// Read from the pipe
+    ///
+    /// `WouldBlock` if no data yet, the caller is recorded and woken up on write
+    /// return 0 (EOF) if no data and all writers are closed
+    pub fn read(&self, buf: &mut [u8]) -> SysResult<usize> {
+        if self.end != PipeEnd::Read {
+            return Err(SysError::BadFd);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut inner = self.buf.lock();
+
+        if inner.data.is_empty() {
+            if inner.writers == 0 {
+                return Ok(0);
+            }
+            inner.read_waiters.insert(processor::get_pid());
+            return Err(SysError::WouldBlock);
+        }
+
+        let len = buf.len().min(inner.data.len());
+        for (dst, src) in buf.iter_mut().zip(inner.data.drain(..len)) {
+            *dst = src;
+        }
+
+        let waiters = core::mem::take(&mut inner.write_waiters);
+        drop(inner);
+        wake_up_blocked(waiters);
+
+        Ok(len)
+    }
+
+    /// Write to the pipe
+    ///
+    /// `WouldBlock` if the buffer is full, `BrokenPipe` if all readers are closed
+    pub fn write(&self, buf: &[u8]) -> SysResult<usize> {
+        if self.end != PipeEnd::Write {
+            return Err(SysError::BadFd);
+        }
+
+        let mut inner = self.buf.lock();
+
+        if inner.readers == 0 {
+            return Err(SysError::BrokenPipe);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let space = PIPE_SIZE - inner.data.len();
+        if space == 0 {
+            inner.write_waiters.insert(processor::get_pid());
+            return Err(SysError::WouldBlock);
+        }
+
+        let len = buf.len().min(space);
+        inner.data.extend(&buf[..len]);
+
+        let waiters = core::mem::take(&mut inner.read_waiters);
+        drop(inner);
+        wake_up_blocked(waiters);
+
+        Ok(len)
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut inner = self.buf.lock();
+
+        // readers see EOF / writers see broken pipe once the other side is gone
+        let waiters = match self.end {
+            PipeEnd::Read => {
+                inner.readers -= 1;
+                if inner.readers > 0 {
+                    return;
+                }
+                core::mem::take(&mut inner.write_waiters)
+            }
+            PipeEnd::Write => {
+                inner.writers -= 1;
+                if inner.writers > 0 {
+                    return;
+                }
+                core::mem::take(&mut inner.read_waiters)
+            }
+        };
+
+        drop(inner);
+        wake_up_blocked(waiters);
+    }
+}
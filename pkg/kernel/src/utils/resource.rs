@@ -1,11 +1,15 @@
 use alloc::string::String;
-// use alloc::sync::Arc;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
 use alloc::collections::BTreeMap;
 use spin::Mutex;
 // use spin::RwLock;
 // use x86_64::structures::paging::Page;
 // use x86_64::VirtAddr;
-use storage::FileHandle;
+use storage::{FileHandle, Metadata, SeekFrom};
+use syscall_def::{SysError, SysResult};
+use crate::filesystem::{file_stat, fs_error_to_sys};
+use super::pipe::Pipe;
 
 
 
@@ -16,49 +20,154 @@ pub enum StdIO {
     Stderr,
 }
 
-#[derive(Debug)]
+/// Default max number of open fds per process
+pub const DEFAULT_FD_LIMIT: usize = 64;
+
+/// Cloning gives a new fd table, the fds still share their resources
+#[derive(Debug, Clone)]
 pub struct ResourceSet {
-    pub handles: BTreeMap<u8, Mutex<Resource>>,
+    // dup-ed fds share the same resource
+    pub handles: BTreeMap<u8, Arc<Mutex<Resource>>>,
+    fd_limit: usize,
 }
 
 impl Default for ResourceSet {
     fn default() -> Self {
         let mut res = Self {
             handles: BTreeMap::new(),
+            fd_limit: DEFAULT_FD_LIMIT,
         };
 
-        res.open(Resource::Console(StdIO::Stdin));
-        res.open(Resource::Console(StdIO::Stdout));
-        res.open(Resource::Console(StdIO::Stderr));
+        res.open(Resource::Console(StdIO::Stdin)).unwrap();
+        res.open(Resource::Console(StdIO::Stdout)).unwrap();
+        res.open(Resource::Console(StdIO::Stderr)).unwrap();
 
         res
     }
 }
 
 impl ResourceSet {
-    pub fn open(&mut self, res: Resource) -> u8 {
-        let fd = self.handles.len() as u8;
-        self.handles.insert(fd, Mutex::new(res));
-        fd
+    pub fn fd_limit(&self) -> usize {
+        self.fd_limit
+    }
+
+    /// Set the max number of open fds, fds already opened are kept
+    pub fn set_fd_limit(&mut self, limit: usize) {
+        self.fd_limit = limit.min(u8::MAX as usize + 1);
+    }
+
+    /// Find the lowest fd not in use
+    fn alloc_fd(&self) -> SysResult<u8> {
+        (0..self.fd_limit)
+            .map(|fd| fd as u8)
+            .find(|fd| !self.handles.contains_key(fd))
+            .ok_or(SysError::TooManyFiles)
+    }
+
+    pub fn open(&mut self, res: Resource) -> SysResult<u8> {
+        let fd = self.alloc_fd()?;
+        self.handles.insert(fd, Arc::new(Mutex::new(res)));
+        Ok(fd)
     }
 
     pub fn close(&mut self, fd: u8) -> bool {
         self.handles.remove(&fd).is_some()
     }
 
-    pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().read(buf)) {
-            count as isize
-        } else {
-            -1
+    /// Create a pipe, return (read fd, write fd)
+    pub fn pipe(&mut self) -> SysResult<(u8, u8)> {
+        let (reader, writer) = Pipe::new();
+        let read_fd = self.open(Resource::Pipe(reader))?;
+        match self.open(Resource::Pipe(writer)) {
+            Ok(write_fd) => Ok((read_fd, write_fd)),
+            Err(err) => {
+                self.close(read_fd);
+                Err(err)
+            }
+        }
+    }
+
+    /// Duplicate `fd` to the lowest free fd
+    pub fn dup(&mut self, fd: u8) -> SysResult<u8> {
+        let res = self.handles.get(&fd).ok_or(SysError::BadFd)?.clone();
+        let new_fd = self.alloc_fd()?;
+        self.handles.insert(new_fd, res);
+        Ok(new_fd)
+    }
+
+    /// Duplicate `old_fd` to `new_fd`, close `new_fd` first if it is open
+    pub fn dup2(&mut self, old_fd: u8, new_fd: u8) -> SysResult<u8> {
+        let res = self.handles.get(&old_fd).ok_or(SysError::BadFd)?.clone();
+        if new_fd as usize >= self.fd_limit {
+            return Err(SysError::BadFd);
         }
+        if old_fd != new_fd {
+            self.handles.insert(new_fd, res);
+        }
+        Ok(new_fd)
+    }
+
+    pub fn read(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+        self.handles.get(&fd).ok_or(SysError::BadFd)?.lock().read(buf)
+    }
+
+    pub fn write(&self, fd: u8, buf: &[u8]) -> SysResult<usize> {
+        self.handles.get(&fd).ok_or(SysError::BadFd)?.lock().write(buf)
     }
 
-    pub fn write(&self, fd: u8, buf: &[u8]) -> isize {
-        if let Some(count) = self.handles.get(&fd).and_then(|h| h.lock().write(buf)) {
-            count as isize
-        } else {
-            -1
+    pub fn seek(&self, fd: u8, pos: SeekFrom) -> SysResult<usize> {
+        self.handles.get(&fd).ok_or(SysError::BadFd)?.lock().seek(pos)
+    }
+
+    pub fn read_dir(&self, fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+        match &mut *self.handles.get(&fd).ok_or(SysError::BadFd)?.lock() {
+            Resource::Dir(dir) => dir.read(buf),
+            _ => Err(SysError::NotADirectory),
+        }
+    }
+}
+
+/// An opened directory, entries are read when it is opened
+#[derive(Debug)]
+pub struct DirHandle {
+    entries: Vec<Metadata>,
+    /// Index of the next entry
+    pos: usize,
+}
+
+impl DirHandle {
+    pub fn new(entries: Vec<Metadata>) -> Self {
+        Self { entries, pos: 0 }
+    }
+
+    /// Copy as many whole records as fit in `buf`, 0 at the end
+    pub fn read(&mut self, buf: &mut [u8]) -> SysResult<usize> {
+        let mut len = 0;
+
+        while let Some(meta) = self.entries.get(self.pos) {
+            match file_stat(meta).encode(&meta.name, &mut buf[len..]) {
+                Some(size) => len += size,
+                None => break,
+            }
+            self.pos += 1;
+        }
+
+        // the buffer can not hold even one record
+        if len == 0 && self.pos < self.entries.len() {
+            return Err(SysError::InvalidArgument);
+        }
+
+        Ok(len)
+    }
+
+    /// Move to the `index`th entry
+    pub fn seek(&mut self, pos: SeekFrom) -> SysResult<usize> {
+        match pos {
+            SeekFrom::Start(index) if index <= self.entries.len() => {
+                self.pos = index;
+                Ok(index)
+            }
+            _ => Err(SysError::InvalidSeek),
         }
     }
 }
@@ -67,50 +176,68 @@ impl ResourceSet {
 pub enum Resource {
     Console(StdIO),
     File(FileHandle),
+    Pipe(Pipe),
+    Dir(DirHandle),
     Null,
 }
 
 impl Resource {
-    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+    pub fn read(&mut self, buf: &mut [u8]) -> SysResult<usize> {
         match self {
-            Resource::File(file) => {
-                if let Ok(size) = file.read(buf) {
-                    Some(size)
-                } else {
-                    Some(0)
-                }
-            }
+            // `WouldBlock` until the disk is done, the read syscall is restarted then
+            Resource::File(file) => match file.read(buf) {
+                Ok(size) => Ok(size),
+                Err(storage::FsError::EndOfFile) => Ok(0),
+                Err(err) => Err(fs_error_to_sys(err)),
+            },
             Resource::Console(stdio) => match stdio {
                 StdIO::Stdin => {
                     // FIXME: just read from kernel input buffer
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
                     if let Some(key) = crate::drivers::input::try_pop_key() {
                         buf[0] = key;
-                        Some(1)
+                        Ok(1)
                     } else {
-                        Some(0)
+                        Ok(0)
                     }
                 }
-                _ => None,// 如果是其他就不支持读取
+                _ => Err(SysError::BadFd),// 如果是其他就不支持读取
             },
-            Resource::Null => Some(0),
+            Resource::Pipe(pipe) => pipe.read(buf),
+            Resource::Dir(_) => Err(SysError::IsADirectory),
+            Resource::Null => Ok(0),
         }
     }
 
-    pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
+    pub fn write(&mut self, buf: &[u8]) -> SysResult<usize> {
         match self {
-            Resource::File(_file) => Some(0),
+            Resource::File(file) => {
+                crate::filesystem::atomic(|| file.write(buf)).map_err(fs_error_to_sys)
+            }
             Resource::Console(stdio) => match *stdio {
-                StdIO::Stdin => None,
+                StdIO::Stdin => Err(SysError::BadFd),
                 StdIO::Stdout => {
                     print!("{}", String::from_utf8_lossy(buf));
-                    Some(buf.len())
+                    Ok(buf.len())
                 }
                 StdIO::Stderr => {
                     warn!("{}", String::from_utf8_lossy(buf));
-                    Some(buf.len())
+                    Ok(buf.len())
                 }
             },
-            Resource::Null => Some(buf.len()),
+            Resource::Pipe(pipe) => pipe.write(buf),
+            Resource::Dir(_) => Err(SysError::IsADirectory),
+            Resource::Null => Ok(buf.len()),
+        }
+    }
+
+    pub fn seek(&mut self, pos: SeekFrom) -> SysResult<usize> {
+        match self {
+            Resource::File(file) => file.seek(pos).map_err(fs_error_to_sys),
+            Resource::Dir(dir) => dir.seek(pos),
+            _ => Err(SysError::InvalidSeek),
         }
     }
 }
@@ -5,7 +5,7 @@ unsafe impl alloc::alloc::GlobalAlloc for KernelAllocator {
         crate::sys_allocate(&layout)
     }
     unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
-        crate::sys_deallocate(ptr, &layout);
+        let _ = crate::sys_deallocate(ptr, &layout);
     }
 }
 
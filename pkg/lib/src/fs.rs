@@ -0,0 +1,107 @@
+//! Files and directories on top of the `FileStat` and `ReadDir` syscalls
+
+use crate::*;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use syscall_def::STAT_HEADER_LEN;
+
+/// Records read by one `ReadDir` syscall
+const DIR_BUF_SIZE: usize = 1024;
+
+/// An entry of a directory
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    pub name: String,
+    pub stat: FileStat,
+}
+
+impl DirEntry {
+    pub fn is_dir(&self) -> bool {
+        self.stat.is_dir()
+    }
+}
+
+/// Get the status of `path`
+pub fn stat(path: &str) -> SysResult<DirEntry> {
+    // a record can not be longer than u16::MAX
+    let mut buf = vec![0u8; STAT_HEADER_LEN + 256];
+    loop {
+        match sys_file_stat(path, &mut buf) {
+            Ok(_) => break,
+            Err(SysError::InvalidArgument) if buf.len() <= u16::MAX as usize => {
+                buf.resize(buf.len() * 2, 0);
+            }
+            Err(err) => return Err(err),
+        }
+    }
+
+    let (stat, name, _) = FileStat::decode(&buf).ok_or(SysError::IoError)?;
+    Ok(DirEntry {
+        name: name.to_string(),
+        stat,
+    })
+}
+
+/// Iterate over the entries of the directory `path`
+pub fn read_dir(path: &str) -> SysResult<ReadDir> {
+    let fd = sys_open(path, OpenFlags::DIRECTORY)?;
+    Ok(ReadDir {
+        fd,
+        buf: vec![0u8; DIR_BUF_SIZE],
+        start: 0,
+        end: 0,
+    })
+}
+
+/// Iterator over a directory, the fd is closed when dropped
+pub struct ReadDir {
+    fd: u8,
+    buf: Vec<u8>,
+    /// Unread records are `buf[start..end]`
+    start: usize,
+    end: usize,
+}
+
+impl Iterator for ReadDir {
+    type Item = SysResult<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            let len = loop {
+                match sys_read_dir(self.fd, &mut self.buf) {
+                    // the next record does not fit
+                    Err(SysError::InvalidArgument) if self.buf.len() <= u16::MAX as usize => {
+                        let len = self.buf.len() * 2;
+                        self.buf.resize(len, 0);
+                    }
+                    Ok(0) => return None,
+                    Ok(len) => break len,
+                    Err(err) => return Some(Err(err)),
+                }
+            };
+            self.start = 0;
+            self.end = len;
+        }
+
+        let (stat, name, len) = match FileStat::decode(&self.buf[self.start..self.end]) {
+            Some(record) => record,
+            None => {
+                self.start = self.end;
+                return Some(Err(SysError::IoError));
+            }
+        };
+        let entry = DirEntry {
+            name: name.to_string(),
+            stat,
+        };
+        self.start += len;
+        Some(Ok(entry))
+    }
+}
+
+impl Drop for ReadDir {
+    fn drop(&mut self) {
+        let _ = sys_close_file(self.fd);
+    }
+}
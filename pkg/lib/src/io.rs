@@ -33,7 +33,7 @@ impl Stdin {
             // 当需要更多数据时读取输入
             if input_buffer.is_empty() {
                 let mut temp_buf = [0u8; 256];
-                if let Some(n) = sys_read(0, &mut temp_buf) {
+                if let Ok(n) = sys_read(0, &mut temp_buf) {
                     input_buffer.extend_from_slice(&temp_buf[..n]);
                 } else {
                     continue; // 读取失败，重试
@@ -46,7 +46,7 @@ impl Stdin {
 
                 match byte {
                     b'\n' | b'\r' => { // 回车或换行，结束输入
-                        sys_write(1, b"\n");
+                        let _ = sys_write(1, b"\n");
                         return line;
                     }
                     0x08 | 0x7F => { // 处理退格
@@ -55,7 +55,7 @@ impl Stdin {
                             let backspace_seq = b"\x08 \x08"; // 退格、空格、退格
                             // 根据字符的UTF-8长度回退光标
                             for _ in 0..c.len_utf8() {
-                                sys_write(1, backspace_seq);
+                                let _ = sys_write(1, backspace_seq);
                             }
                         }
                     }
@@ -66,7 +66,7 @@ impl Stdin {
                                 if let Some(c) = s.chars().next() {
                                     // 成功解析字符，添加到行并显示
                                     line.push(c);
-                                    sys_write(1, c.to_string().as_bytes());
+                                    let _ = sys_write(1, c.to_string().as_bytes());
                                     utf8_partial.clear(); // 清空临时缓冲区
                                 }
                             }
@@ -91,7 +91,7 @@ impl Stdout {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(1, s.as_bytes());
+        let _ = sys_write(1, s.as_bytes());
     }
 }
 
@@ -101,7 +101,7 @@ impl Stderr {
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(2, s.as_bytes());
+        let _ = sys_write(2, s.as_bytes());
     }
 }
 
@@ -10,6 +10,7 @@ extern crate syscall_def;
 
 #[macro_use]
 pub mod io;
+pub mod fs;
 pub mod allocator1;
 pub mod allocator;
 pub mod sync;
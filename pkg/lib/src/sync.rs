@@ -54,9 +54,9 @@ impl Semaphore {
     // 这个就是new
     #[inline(always)]
     pub fn init(&self, value: usize) -> bool { // 初始化信号量（对应文档中的sem系统调用）
-        let ret = sys_new_sem(self.key, value); // 系统调用创建信号量（op=0）
+        let ret = sys_new_sem(self.key, value).is_ok(); // 系统调用创建信号量（op=0）
         let s = format!("Semaphore {} initialized with value {}: {}\n", self.key, value, ret);
-        sys_write(1, s.as_bytes()); // 输出初始化结果到标准输出
+        let _ = sys_write(1, s.as_bytes()); // 输出初始化结果到标准输出
         ret
     }
 
@@ -69,17 +69,17 @@ impl Semaphore {
 
     #[inline(always)]
     pub fn remove(&self) -> bool { // 删除信号量（对应文档中的sem系统调用）
-        sys_remove_sem(self.key) // 系统调用删除信号量（op=3）
+        sys_remove_sem(self.key).is_ok() // 系统调用删除信号量（op=1）
     }
 
     #[inline(always)]
     pub fn wait(&self) -> bool {
-        sys_wait_sem(self.key) // P操作（op=1）
+        sys_wait_sem(self.key).is_ok() // P操作（op=3）
     }
 
     #[inline(always)]
     pub fn signal(&self) -> bool { // V操作（op=2）
-        sys_signal_sem(self.key) // 系统调用释放信号量（op=2）
+        sys_signal_sem(self.key).is_ok() // 系统调用释放信号量（op=2）
     }
 }
 
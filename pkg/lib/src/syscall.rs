@@ -1,39 +1,41 @@
 use core::fmt;
 use alloc::format;
 use syscall_def::Syscall;
+pub use syscall_def::{FileKind, FileStat, OpenFlags, SeekWhence, SysError, SysResult};
 use chrono::{DateTime, FixedOffset, NaiveDateTime};
 
 // fmt
 
 
 #[inline(always)]
-pub fn sys_write(fd: u8, buf: &[u8]) -> Option<usize> {
-    let ret = syscall!(
+pub fn sys_write(fd: u8, buf: &[u8]) -> SysResult<usize> {
+    SysError::decode(syscall!(
         Syscall::Write,
         fd as u64,
         buf.as_ptr() as u64,
         buf.len() as u64
-    ) as isize;
-    if ret.is_negative() {
-        None
-    } else {
-        Some(ret as usize)
-    }
+    ))
 }
 
 #[inline(always)]
-pub fn sys_read(fd: u8, buf: &mut [u8]) -> Option<usize> {
-    let ret = syscall!(
+pub fn sys_read(fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+    SysError::decode(syscall!(
         Syscall::Read,
         fd as u64,
         buf.as_ptr() as u64,
         buf.len() as u64
-    ) as isize;
-    if ret.is_negative() {
-        None
-    } else {
-        Some(ret as usize)
-    }
+    ))
+}
+
+/// Move the offset of `fd`, return the new offset from the start
+#[inline(always)]
+pub fn sys_lseek(fd: u8, offset: isize, whence: SeekWhence) -> SysResult<usize> {
+    SysError::decode(syscall!(
+        Syscall::Lseek,
+        fd as u64,
+        offset as u64,
+        whence as u64
+    ))
 }
 
 #[inline(always)]
@@ -51,14 +53,10 @@ pub fn sys_time_beijing() -> DateTime<FixedOffset> {
     DateTime::from_utc(utc_dt, beijing_offset)
 }
 
+/// Wait for a process to exit, returns its exit status (the low 8 bits)
 #[inline(always)]
-pub fn sys_wait_pid(pid: u16) -> isize {
-    // FIXME: try to get the return value for process
-    //        loop until the process is finished
-    let ret = syscall!(Syscall::WaitPid, pid as u64) as isize;
-    // let s =format!("Process {} exited with code {}", pid, ret);
-    // sys_write(1, s.as_bytes());
-    ret
+pub fn sys_wait_pid(pid: u16) -> SysResult<isize> {
+    SysError::decode(syscall!(Syscall::WaitPid, pid as u64)).map(|ret| ret as isize)
 }
 
 #[inline(always)]
@@ -73,17 +71,21 @@ pub fn sys_stat() {
 
 #[inline(always)]
 pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
-    syscall!(Syscall::Allocate, layout as *const _) as *mut u8
+    // GlobalAlloc wants a null pointer on failure
+    SysError::decode(syscall!(Syscall::Allocate, layout.size(), layout.align()))
+        .unwrap_or(0) as *mut u8
 }
 
 #[inline(always)]
-pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> usize {
-    syscall!(Syscall::Deallocate, ptr, layout as *const _)
+pub fn sys_deallocate(ptr: *mut u8, layout: &core::alloc::Layout) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Deallocate, ptr, layout.size(), layout.align()))
+        .map(|_| ())
 }
 
 #[inline(always)]
-pub fn sys_spawn(path: &str) -> u16 {
-    syscall!(Syscall::Spawn, path.as_ptr() as u64, path.len() as u64) as u16
+pub fn sys_spawn(path: &str) -> SysResult<u16> {
+    SysError::decode(syscall!(Syscall::Spawn, path.as_ptr() as u64, path.len() as u64))
+        .map(|pid| pid as u16)
 }
 
 #[inline(always)]
@@ -105,50 +107,155 @@ pub fn sys_get_time() -> usize {
 }
 
 #[inline(always)]
-pub fn sys_brk(addr: Option<usize>) -> Option<usize> {
-    const BRK_FAILED: usize = !0;
-    match syscall!(Syscall::Brk, addr.unwrap_or(0)) {
-        BRK_FAILED => None,
-        ret => Some(ret),
-    }
+pub fn sys_brk(addr: Option<usize>) -> SysResult<usize> {
+    SysError::decode(syscall!(Syscall::Brk, addr.unwrap_or(0)))
+}
+
+/// Fork a process with a copy of the memory, written pages are copied
+#[inline(always)]
+pub fn sys_fork() -> SysResult<u16> {
+    SysError::decode(syscall!(Syscall::Fork)).map(|pid| pid as u16)
+}
+
+/// Fork a thread sharing the memory, it runs on a stack of its own
+#[inline(always)]
+pub fn sys_thread() -> SysResult<u16> {
+    SysError::decode(syscall!(Syscall::Thread)).map(|pid| pid as u16)
+}
+
+#[inline(always)]
+pub fn sys_new_sem(key: u32, value: usize) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Sem, 0, key as u64, value as u64)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_remove_sem(key: u32) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Sem, 1, key as usize)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_signal_sem(key: u32) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Sem, 2, key as usize)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_wait_sem(key: u32) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Sem, 3, key as usize)).map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_list_dir(path: &str) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::ListDir, path.as_ptr() as u64, path.len() as u64))
+        .map(|_| ())
+}
+
+/// Write the status record of `path` into `buf`, return its length
+#[inline(always)]
+pub fn sys_file_stat(path: &str, buf: &mut [u8]) -> SysResult<usize> {
+    SysError::decode(syscall!(
+        Syscall::FileStat,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64
+    ))
+}
+
+/// Read status records of the directory `fd` into `buf`, return 0 at the end
+#[inline(always)]
+pub fn sys_read_dir(fd: u8, buf: &mut [u8]) -> SysResult<usize> {
+    SysError::decode(syscall!(
+        Syscall::ReadDir,
+        fd as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64
+    ))
+}
+
+/// Open the file for reading
+#[inline(always)]
+pub fn sys_open_file(path: &str) -> SysResult<u8> {
+    sys_open(path, OpenFlags::empty())
+}
+
+/// Open the file with `flags`, it is always readable and writable
+#[inline(always)]
+pub fn sys_open(path: &str, flags: OpenFlags) -> SysResult<u8> {
+    SysError::decode(syscall!(
+        Syscall::OpenFile,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        flags.bits() as u64
+    ))
+    .map(|fd| fd as u8)
+}
+
+#[inline(always)]
+pub fn sys_remove_file(path: &str) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::RemoveFile, path.as_ptr() as u64, path.len() as u64))
+        .map(|_| ())
+}
+
+#[inline(always)]
+pub fn sys_create_dir(path: &str) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::CreateDir, path.as_ptr() as u64, path.len() as u64))
+        .map(|_| ())
 }
 
+/// Remove an empty directory
 #[inline(always)]
-pub fn sys_fork() -> u16 {
-    syscall!(Syscall::Fork) as u16
+pub fn sys_remove_dir(path: &str) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::RemoveDir, path.as_ptr() as u64, path.len() as u64))
+        .map(|_| ())
 }
 
+/// Mount the partition `source` (e.g. `hda2`) at `target`
 #[inline(always)]
-pub fn sys_new_sem(key: u32, value: usize) -> bool {
-    syscall!(Syscall::Sem, 0, key as u64, value as u64) != 0
+pub fn sys_mount(source: &str, target: &str) -> SysResult<()> {
+    SysError::decode(syscall!(
+        Syscall::Mount,
+        source.as_ptr() as u64,
+        source.len() as u64,
+        target.as_ptr() as u64,
+        target.len() as u64
+    ))
+    .map(|_| ())
 }
 
+/// Detach the filesystem mounted at `target`
 #[inline(always)]
-pub fn sys_remove_sem(key: u32) -> bool {
-    syscall!(Syscall::Sem, 1, key as usize) != 0
+pub fn sys_umount(target: &str) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::Umount, target.as_ptr() as u64, target.len() as u64))
+        .map(|_| ())
 }
 
+/// Create a pipe, return (read fd, write fd)
 #[inline(always)]
-pub fn sys_signal_sem(key: u32) -> bool {
-    syscall!(Syscall::Sem, 2, key as usize) != 0
+pub fn sys_pipe() -> SysResult<(u8, u8)> {
+    let mut fds = [0u8; 2];
+    SysError::decode(syscall!(Syscall::Pipe, fds.as_mut_ptr() as u64))?;
+    Ok((fds[0], fds[1]))
 }
 
 #[inline(always)]
-pub fn sys_wait_sem(key: u32) -> bool {
-    syscall!(Syscall::Sem, 3, key as usize) != 0
+pub fn sys_dup(fd: u8) -> SysResult<u8> {
+    SysError::decode(syscall!(Syscall::Dup, fd as u64)).map(|fd| fd as u8)
 }
 
 #[inline(always)]
-pub fn sys_list_dir(path: &str) {
-    syscall!(Syscall::ListDir, path.as_ptr() as u64, path.len() as u64);
+pub fn sys_dup2(old_fd: u8, new_fd: u8) -> SysResult<u8> {
+    SysError::decode(syscall!(Syscall::Dup2, old_fd as u64, new_fd as u64)).map(|fd| fd as u8)
 }
 
+/// Set the max number of open fds if `limit` is given, return the old one
+///
+/// Fds already open are kept, forked and spawned processes get the limit too.
 #[inline(always)]
-pub fn sys_open_file(path: &str) -> u8 {
-    syscall!(Syscall::OpenFile, path.as_ptr() as u64, path.len() as u64) as u8
+pub fn sys_fd_limit(limit: Option<usize>) -> SysResult<usize> {
+    SysError::decode(syscall!(Syscall::FdLimit, limit.unwrap_or(0)))
 }
 
 #[inline(always)]
-pub fn sys_close_file(fd: u8) -> bool {
-    syscall!(Syscall::CloseFile, fd as u64) == 0
+pub fn sys_close_file(fd: u8) -> SysResult<()> {
+    SysError::decode(syscall!(Syscall::CloseFile, fd as u64)).map(|_| ())
 }
\ No newline at end of file
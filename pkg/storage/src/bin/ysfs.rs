@@ -0,0 +1,222 @@
+//! ysfs: build and inspect FAT16 disk images on the host
+//!
+//! ```text
+//! cargo run -p ysos_storage --features std --bin ysfs -- <command> <image> [args...]
+//! ```
+//!
+//! Images made by `create` hold an MBR with one active partition, commands
+//! work on the first partition unless `-p <n>` is given, `-p 0` means the
+//! whole image is a bare FAT16 volume.
+
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use ysos_storage::fat16::Fat16;
+use ysos_storage::fat16::check::check;
+use ysos_storage::fat16::format::format;
+use ysos_storage::mbr::MbrTable;
+use ysos_storage::*;
+
+const USAGE: &str = "\
+usage: ysfs [-p <partition>] <command> <image> [args...]
+
+commands:
+  create <image> <size-MiB> [label]  create an MBR image with one FAT16 partition
+  format <image> [label]             format the partition as FAT16
+  ls     <image> [path]              list a directory
+  tree   <image> [path]              list a directory recursively
+  put    <image> <host-file> <path>  copy a host file into the image
+  get    <image> <path> <host-file>  copy a file out of the image
+  mkdir  <image> <path>              create a directory
+  rm     <image> <path>              remove a file or an empty directory
+  fsck   [--repair] <image>          check the volume, repair problems if asked";
+
+/// First LBA of the partition made by `create`, aligned to 1 MiB
+const PARTITION_START: usize = 2048;
+
+/// Partition type of FAT16 with LBA addressing
+const FAT16_LBA_TYPE: u8 = 0x0E;
+
+type Result<T = ()> = core::result::Result<T, String>;
+
+fn fs_err(context: &str) -> impl Fn(FsError) -> String + '_ {
+    move |err| format!("{}: {:?}", context, err)
+}
+
+/// The block device of the volume, `partition` counts from 1
+fn open_device(image: &str, partition: usize) -> Result<Arc<dyn BlockDevice<Block512>>> {
+    let disk = FileDisk::open(image).map_err(|err| format!("{}: {}", image, err))?;
+    if partition == 0 {
+        return Ok(Arc::new(disk));
+    }
+
+    let parts = MbrTable::parse(disk)
+        .and_then(|mbr| mbr.partitions())
+        .map_err(fs_err(image))?;
+    let part = parts
+        .into_iter()
+        .nth(partition - 1)
+        .ok_or_else(|| format!("{}: no partition {}", image, partition))?;
+    Ok(Arc::new(part))
+}
+
+fn open_fs(image: &str, partition: usize) -> Result<Fat16> {
+    let device = open_device(image, partition)?;
+    match FatType::detect(&device).map_err(fs_err(image))? {
+        FatType::Fat16 => Ok(Fat16::new(device)),
+        other => Err(format!("{}: not a FAT16 volume: {:?}", image, other)),
+    }
+}
+
+/// Write an MBR with one active partition covering the rest of the disk
+fn write_mbr(disk: &FileDisk) -> Result {
+    let total = disk.block_count().map_err(fs_err("mbr"))?;
+    let size = u32::try_from(total - PARTITION_START).map_err(|_| "image too large")?;
+
+    let mut block = Block512::default();
+    let entry = &mut block[0x1BE..0x1CE];
+    entry[0x00] = 0x80;
+    // CHS is not used, mark it as out of range
+    entry[0x01..0x04].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[0x04] = FAT16_LBA_TYPE;
+    entry[0x05..0x08].copy_from_slice(&[0xFE, 0xFF, 0xFF]);
+    entry[0x08..0x0C].copy_from_slice(&(PARTITION_START as u32).to_le_bytes());
+    entry[0x0C..0x10].copy_from_slice(&size.to_le_bytes());
+    block[0x1FE..0x200].copy_from_slice(&[0x55, 0xAA]);
+
+    disk.write_block(0, &block).map_err(fs_err("mbr"))
+}
+
+fn create(image: &str, size: &str, label: &str) -> Result {
+    let mib: usize = size
+        .parse()
+        .map_err(|_| format!("invalid size: {}", size))?;
+    let disk = FileDisk::create(image, mib * 2048).map_err(|err| format!("{}: {}", image, err))?;
+    if mib * 2048 <= PARTITION_START {
+        return Err(format!("{}: image is too small", image));
+    }
+
+    write_mbr(&disk)?;
+    let part = Partition::new(disk, PARTITION_START, mib * 2048 - PARTITION_START);
+    format(&part, label).map_err(fs_err(image))
+}
+
+fn ls(fs: &Fat16, path: &str) -> Result {
+    for meta in fs.read_dir(path).map_err(fs_err(path))? {
+        let modified = meta
+            .modified
+            .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let suffix = if meta.is_dir() { "/" } else { "" };
+        println!("{:>10}  {:<19}  {}{}", meta.len, modified, meta.name, suffix);
+    }
+    Ok(())
+}
+
+fn tree(fs: &Fat16, path: &str, depth: usize) -> Result {
+    for meta in fs.read_dir(path).map_err(fs_err(path))? {
+        if meta.name == "." || meta.name == ".." {
+            continue;
+        }
+        println!("{:indent$}{}", "", meta.name, indent = depth * 2);
+        if meta.is_dir() {
+            let child = format!("{}/{}", path.trim_end_matches('/'), meta.name);
+            tree(fs, &child, depth + 1)?;
+        }
+    }
+    Ok(())
+}
+
+fn put(fs: &Fat16, host: &str, path: &str) -> Result {
+    let data = std::fs::read(host).map_err(|err| format!("{}: {}", host, err))?;
+    let mut file = fs.create_file(path).map_err(fs_err(path))?;
+    file.write_all(&data).map_err(fs_err(path))
+}
+
+fn get(fs: &Fat16, path: &str, host: &str) -> Result {
+    let mut data = Vec::new();
+    let mut file = fs.open_file(path).map_err(fs_err(path))?;
+    file.read_all(&mut data).map_err(fs_err(path))?;
+    std::fs::write(host, data).map_err(|err| format!("{}: {}", host, err))
+}
+
+fn rm(fs: &Fat16, path: &str) -> Result {
+    let meta = fs.metadata(path).map_err(fs_err(path))?;
+    match meta.is_dir() {
+        true => fs.remove_dir(path),
+        false => fs.remove_file(path),
+    }
+    .map_err(fs_err(path))
+}
+
+/// Check the volume, print and optionally repair the problems
+fn fsck(fs: &Fat16, image: &str, repair: bool) -> Result {
+    let problems = check(fs, repair).map_err(fs_err(image))?;
+    for problem in problems.iter() {
+        println!("{}", problem);
+    }
+
+    match (problems.len(), repair) {
+        (0, _) => Ok(()),
+        (n, true) => {
+            println!("{}: {} problems repaired", image, n);
+            Ok(())
+        }
+        (n, false) => Err(format!("{}: {} problems found", image, n)),
+    }
+}
+
+fn run(args: &[String]) -> Result {
+    let mut args = args;
+    let mut partition = 1;
+    if args.first().map(String::as_str) == Some("-p") {
+        let n = args.get(1).ok_or(USAGE)?;
+        partition = n.parse().map_err(|_| format!("invalid partition: {}", n))?;
+        args = &args[2..];
+    }
+
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args[..] {
+        ["create", image, size] => create(image, size, "YSOS"),
+        ["create", image, size, label] => create(image, size, label),
+        ["format", image] | ["format", image, _] => {
+            let label = args.get(2).copied().unwrap_or("YSOS");
+            format(&open_device(image, partition)?, label).map_err(fs_err(image))
+        }
+        ["ls", image] => ls(&open_fs(image, partition)?, "/"),
+        ["ls", image, path] => ls(&open_fs(image, partition)?, path),
+        ["tree", image] => tree(&open_fs(image, partition)?, "/", 0),
+        ["tree", image, path] => tree(&open_fs(image, partition)?, path, 0),
+        ["put", image, host, path] => put(&open_fs(image, partition)?, host, path),
+        ["get", image, path, host] => get(&open_fs(image, partition)?, path, host),
+        ["mkdir", image, path] => open_fs(image, partition)?
+            .create_dir(path)
+            .map_err(fs_err(path)),
+        ["rm", image, path] => rm(&open_fs(image, partition)?, path),
+        ["fsck", image] => fsck(&open_fs(image, partition)?, image, false),
+        ["fsck", "--repair", image] => fsck(&open_fs(image, partition)?, image, true),
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// Timestamps of new entries come from the host clock
+fn host_time() -> FsTime {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    FsTime::from_timestamp(since_epoch.as_secs() as i64, since_epoch.subsec_nanos())
+        .unwrap_or_default()
+}
+
+fn main() -> ExitCode {
+    set_clock(host_time);
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("ysfs: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
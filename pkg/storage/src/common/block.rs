@@ -1,5 +1,5 @@
 use crate::*;
-use core::ops::Deref;
+use core::ops::{Deref, DerefMut};
 
 pub trait BlockTrait =
     AsMut<[u8]> + AsRef<[u8]> + SizedBlock + Default + Send + Sync + Clone + 'static;
@@ -39,6 +39,12 @@ impl<const SIZE: usize> Deref for Block<SIZE> {
     }
 }
 
+impl<const SIZE: usize> DerefMut for Block<SIZE> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.contents
+    }
+}
+
 impl<const SIZE: usize> AsRef<[u8]> for Block<SIZE> {
     fn as_ref(&self) -> &[u8] {
         &self.contents
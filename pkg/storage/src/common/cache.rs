@@ -0,0 +1,395 @@
+//! Block cache
+//!
+//! Keeps recently used blocks of a device in memory. Writes only go to the
+//! cache and are written back when the block is evicted or on `sync`.
+//!
+//! Writes can be kept aside in a transaction, an operation which fails
+//! halfway then leaves no trace and can be run again from the start.
+
+use super::*;
+use alloc::collections::BTreeMap;
+use core::marker::PhantomData;
+use core::num::NonZeroUsize;
+use lru::LruCache;
+use spin::Mutex;
+
+/// Statistics of a block cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Reads and writes served by the cache
+    pub hits: usize,
+    /// Reads that had to load the block from the device
+    pub misses: usize,
+    /// Blocks dropped to make room for others
+    pub evictions: usize,
+    /// Dirty blocks written to the device
+    pub writebacks: usize,
+    /// Blocks in the cache
+    pub cached: usize,
+    /// Blocks not yet written to the device
+    pub dirty: usize,
+}
+
+struct CachedBlock<B> {
+    block: B,
+    dirty: bool,
+}
+
+struct CacheInner<B> {
+    blocks: LruCache<usize, CachedBlock<B>>,
+    stats: CacheStats,
+    /// Writes of the running transaction, not in `blocks` yet
+    pending: Option<BTreeMap<usize, B>>,
+}
+
+impl<B> CacheInner<B> {
+    /// The newest data of the block, marked as recently used
+    fn get(&mut self, offset: usize) -> Option<&B> {
+        if self.pending.as_ref().is_some_and(|p| p.contains_key(&offset)) {
+            return self.pending.as_ref().and_then(|p| p.get(&offset));
+        }
+        self.blocks.get(&offset).map(|cached| &cached.block)
+    }
+
+    fn contains(&self, offset: usize) -> bool {
+        self.pending.as_ref().is_some_and(|p| p.contains_key(&offset))
+            || self.blocks.contains(&offset)
+    }
+}
+
+/// A block device with an LRU write-back cache
+pub struct CachedBlockDevice<T, B>
+where
+    T: BlockDevice<B>,
+    B: BlockTrait,
+{
+    inner: T,
+    cache: Mutex<CacheInner<B>>,
+    _block: PhantomData<B>,
+}
+
+impl<T, B> CachedBlockDevice<T, B>
+where
+    T: BlockDevice<B>,
+    B: BlockTrait,
+{
+    /// Cache at most `capacity` blocks of `inner`
+    pub fn new(inner: T, capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner,
+            cache: Mutex::new(CacheInner {
+                blocks: LruCache::new(capacity),
+                stats: CacheStats::default(),
+                pending: None,
+            }),
+            _block: PhantomData,
+        }
+    }
+
+    /// The device under the cache, reads and writes on it bypass the cache
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let cache = self.cache.lock();
+        CacheStats {
+            cached: cache.blocks.len(),
+            dirty: cache.blocks.iter().filter(|(_, b)| b.dirty).count(),
+            ..cache.stats
+        }
+    }
+
+    /// Write all dirty blocks to the device
+    pub fn sync(&self) -> FsResult {
+        let mut cache = self.cache.lock();
+        let CacheInner { blocks, stats, .. } = &mut *cache;
+
+        for (&offset, cached) in blocks.iter_mut().filter(|(_, b)| b.dirty) {
+            self.inner.write_block(offset, &cached.block)?;
+            cached.dirty = false;
+            stats.writebacks += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Keep writes aside until `commit`, `rollback` drops them
+    pub fn begin(&self) {
+        self.cache.lock().pending.get_or_insert_with(BTreeMap::new);
+    }
+
+    /// Apply the writes kept since `begin`
+    pub fn commit(&self) -> FsResult {
+        let mut cache = self.cache.lock();
+        for (offset, block) in cache.pending.take().into_iter().flatten() {
+            self.write_cached(&mut cache, offset, &block)?;
+        }
+        Ok(())
+    }
+
+    /// Drop the writes kept since `begin`
+    pub fn rollback(&self) {
+        self.cache.lock().pending = None;
+    }
+
+    /// Write back dirty blocks and drop everything in the cache
+    pub fn invalidate(&self) -> FsResult {
+        self.sync()?;
+        self.cache.lock().blocks.clear();
+        Ok(())
+    }
+
+    /// Add a block which is not in the cache, evict the least recently used one if full
+    fn insert(&self, cache: &mut CacheInner<B>, offset: usize, cached: CachedBlock<B>) -> FsResult {
+        if cache.blocks.len() == cache.blocks.cap().get() {
+            if let Some((&lru, old)) = cache.blocks.peek_lru() {
+                // keep the dirty block if it can not be written back
+                if old.dirty {
+                    self.inner.write_block(lru, &old.block)?;
+                    cache.stats.writebacks += 1;
+                }
+            }
+            cache.blocks.pop_lru();
+            cache.stats.evictions += 1;
+        }
+
+        cache.blocks.put(offset, cached);
+        Ok(())
+    }
+
+    /// Update the block in the cache, it is written back later
+    fn write_cached(&self, cache: &mut CacheInner<B>, offset: usize, block: &B) -> FsResult {
+        if let Some(cached) = cache.blocks.get_mut(&offset) {
+            cached.block.as_mut().copy_from_slice(block.as_ref());
+            cached.dirty = true;
+            cache.stats.hits += 1;
+            return Ok(());
+        }
+
+        // the whole block is overwritten, no need to read it first
+        let cached = CachedBlock {
+            block: block.clone(),
+            dirty: true,
+        };
+        self.insert(cache, offset, cached)
+    }
+}
+
+impl<T, B> BlockDevice<B> for CachedBlockDevice<T, B>
+where
+    T: BlockDevice<B>,
+    B: BlockTrait,
+{
+    fn block_count(&self) -> FsResult<usize> {
+        self.inner.block_count()
+    }
+
+    fn read_block(&self, offset: usize, block: &mut B) -> FsResult {
+        let mut cache = self.cache.lock();
+
+        if let Some(cached) = cache.get(offset) {
+            block.as_mut().copy_from_slice(cached.as_ref());
+            cache.stats.hits += 1;
+            return Ok(());
+        }
+
+        cache.stats.misses += 1;
+        self.inner.read_block(offset, block)?;
+        let cached = CachedBlock {
+            block: block.clone(),
+            dirty: false,
+        };
+        self.insert(&mut cache, offset, cached)
+    }
+
+    fn read_blocks(&self, offset: usize, blocks: &mut [B]) -> FsResult {
+        let mut cache = self.cache.lock();
+
+        // cached blocks may be newer than the device, only runs of misses are read
+        let mut i = 0;
+        while i < blocks.len() {
+            if let Some(cached) = cache.get(offset + i) {
+                blocks[i].as_mut().copy_from_slice(cached.as_ref());
+                cache.stats.hits += 1;
+                i += 1;
+                continue;
+            }
+
+            let start = i;
+            while i < blocks.len() && !cache.contains(offset + i) {
+                i += 1;
+            }
+            let run = &mut blocks[start..i];
+            cache.stats.misses += run.len();
+            self.inner.read_blocks(offset + start, run)?;
+
+            for (j, block) in run.iter().enumerate() {
+                let cached = CachedBlock {
+                    block: block.clone(),
+                    dirty: false,
+                };
+                self.insert(&mut cache, offset + start + j, cached)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_block(&self, offset: usize, block: &B) -> FsResult {
+        if offset >= self.inner.block_count()? {
+            return Err(FsError::InvalidOffset);
+        }
+
+        let mut cache = self.cache.lock();
+        if let Some(pending) = cache.pending.as_mut() {
+            pending.insert(offset, block.clone());
+            return Ok(());
+        }
+        self.write_cached(&mut cache, offset, block)
+    }
+}
+
+impl<T, B> Drop for CachedBlockDevice<T, B>
+where
+    T: BlockDevice<B>,
+    B: BlockTrait,
+{
+    fn drop(&mut self) {
+        if let Err(err) = self.sync() {
+            warn!("Failed to sync block cache: {err:?}");
+        }
+    }
+}
+
+impl<T, B> core::fmt::Debug for CachedBlockDevice<T, B>
+where
+    T: BlockDevice<B>,
+    B: BlockTrait,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CachedBlockDevice")
+            .field("capacity", &self.cache.lock().blocks.cap())
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::MemDisk;
+
+    fn disk(blocks: usize) -> MemDisk {
+        let data = (0..blocks * 512).map(|i| (i / 512) as u8).collect();
+        MemDisk(Arc::new(Mutex::new(data)))
+    }
+
+    #[test]
+    fn test_cache_read() {
+        let cache = CachedBlockDevice::new(disk(8), 2);
+        let mut block = Block512::default();
+
+        cache.read_block(1, &mut block).unwrap();
+        assert_eq!(block[0], 1);
+        cache.read_block(1, &mut block).unwrap();
+        cache.read_block(2, &mut block).unwrap();
+        assert_eq!(block[511], 2);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.cached), (1, 2, 2));
+
+        // block 1 is used more recently than block 2
+        cache.read_block(1, &mut block).unwrap();
+        cache.read_block(3, &mut block).unwrap();
+        cache.read_block(1, &mut block).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (3, 3, 1));
+        assert!(cache.read_block(8, &mut block).is_err());
+    }
+
+    #[test]
+    fn test_cache_read_blocks() {
+        let disk = disk(8);
+        let cache = CachedBlockDevice::new(disk.clone(), 4);
+        let mut blocks = vec![Block512::default(); 4];
+
+        // a dirty block in the middle splits the read into two runs
+        cache.write_block(2, &Block512::new(&[0xAA; 512])).unwrap();
+        cache.read_blocks(1, &mut blocks).unwrap();
+        assert_eq!(blocks.iter().map(|b| b[0]).collect::<Vec<_>>(), [1, 0xAA, 3, 4]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.cached), (1, 3, 4));
+        assert_eq!(disk.0.lock()[2 * 512], 2);
+        assert!(cache.read_blocks(6, &mut blocks).is_err());
+    }
+
+    #[test]
+    fn test_cache_write_back() {
+        let disk = disk(8);
+        let cache = CachedBlockDevice::new(disk.clone(), 2);
+        let block = Block512::new(&[0xAA; 512]);
+        let mut buf = Block512::default();
+
+        cache.write_block(0, &block).unwrap();
+        cache.write_block(1, &block).unwrap();
+        assert_eq!(disk.0.lock()[0], 0);
+        assert_eq!(cache.stats().dirty, 2);
+
+        cache.read_block(0, &mut buf).unwrap();
+        assert_eq!(buf[0], 0xAA);
+
+        // evict block 1, it is written back
+        cache.read_block(2, &mut buf).unwrap();
+        assert_eq!(disk.0.lock()[512], 0xAA);
+        assert_eq!(disk.0.lock()[0], 0);
+
+        cache.sync().unwrap();
+        assert_eq!(disk.0.lock()[0], 0xAA);
+
+        let stats = cache.stats();
+        assert_eq!((stats.dirty, stats.writebacks), (0, 2));
+        assert!(cache.write_block(8, &block).is_err());
+
+        // dropping the cache writes dirty blocks
+        cache.write_block(3, &block).unwrap();
+        drop(cache);
+        assert_eq!(disk.0.lock()[3 * 512], 0xAA);
+    }
+
+    #[test]
+    fn test_cache_transaction() {
+        let disk = disk(8);
+        let cache = CachedBlockDevice::new(disk.clone(), 4);
+        let block = Block512::new(&[0xAA; 512]);
+        let mut buf = Block512::default();
+
+        // kept writes are seen by reads, nothing is dirty yet
+        cache.begin();
+        cache.write_block(1, &block).unwrap();
+        cache.read_block(1, &mut buf).unwrap();
+        assert_eq!(buf[0], 0xAA);
+        let mut blocks = vec![Block512::default(); 3];
+        cache.read_blocks(0, &mut blocks).unwrap();
+        assert_eq!(blocks.iter().map(|b| b[0]).collect::<Vec<_>>(), [0, 0xAA, 2]);
+        assert_eq!(cache.stats().dirty, 0);
+
+        cache.rollback();
+        cache.read_block(1, &mut buf).unwrap();
+        assert_eq!(buf[0], 1);
+
+        cache.begin();
+        cache.write_block(2, &block).unwrap();
+        cache.commit().unwrap();
+        cache.read_block(2, &mut buf).unwrap();
+        assert_eq!(buf[0], 0xAA);
+        assert_eq!(cache.stats().dirty, 1);
+
+        cache.sync().unwrap();
+        assert_eq!(disk.0.lock()[2 * 512], 0xAA);
+        assert_eq!(disk.0.lock()[512], 1);
+    }
+}
@@ -11,6 +11,15 @@ where
     /// Reads a block from the device into the provided buffer
     fn read_block(&self, offset: usize, block: &mut B) -> FsResult;
 
+    /// Reads consecutive blocks starting at `offset`,
+    /// devices able to transfer them in one request should override it
+    fn read_blocks(&self, offset: usize, blocks: &mut [B]) -> FsResult {
+        for (i, block) in blocks.iter_mut().enumerate() {
+            self.read_block(offset + i, block)?;
+        }
+        Ok(())
+    }
+
     /// Writes a block to the device from the provided buffer
     fn write_block(&self, offset: usize, block: &B) -> FsResult;
 
@@ -19,3 +28,26 @@ where
         B::size()
     }
 }
+
+/// A shared block device, e.g. a cache used by both a filesystem and devfs
+impl<T, B> BlockDevice<B> for Arc<T>
+where
+    T: BlockDevice<B> + ?Sized,
+    B: BlockTrait,
+{
+    fn block_count(&self) -> FsResult<usize> {
+        (**self).block_count()
+    }
+
+    fn read_block(&self, offset: usize, block: &mut B) -> FsResult {
+        (**self).read_block(offset, block)
+    }
+
+    fn read_blocks(&self, offset: usize, blocks: &mut [B]) -> FsResult {
+        (**self).read_blocks(offset, blocks)
+    }
+
+    fn write_block(&self, offset: usize, block: &B) -> FsResult {
+        (**self).write_block(offset, block)
+    }
+}
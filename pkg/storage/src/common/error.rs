@@ -27,6 +27,12 @@ pub enum FsError {
     BadCluster,
     /// Invalid offset.
     InvalidOffset,
+    /// The partition table is missing or corrupted.
+    InvalidPartitionTable,
+    /// The file or directory already exists.
+    AlreadyExists,
+    /// The directory is not empty.
+    DirectoryNotEmpty,
     /// The file name is invalid.
     FileNameError(FilenameError),
     /// Encountered an error while reading from the device.
@@ -39,6 +45,8 @@ pub enum FsError {
 pub enum DeviceError {
     /// The device is busy.
     Busy,
+    /// The request is queued, try again once it is finished.
+    WouldBlock,
     /// Unknown device.
     UnknownDevice,
     /// Unknown error.
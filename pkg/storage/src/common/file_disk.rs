@@ -0,0 +1,107 @@
+//! Disk image in a host file, only with the `std` feature
+
+use super::*;
+use spin::Mutex;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read as _, Seek as _, Write as _};
+use std::path::Path;
+
+/// A block device backed by a disk image on the host
+#[derive(Debug, Clone)]
+pub struct FileDisk {
+    file: Arc<Mutex<File>>,
+    blocks: usize,
+}
+
+impl FileDisk {
+    /// Open an existing image, its size is rounded down to whole blocks
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let blocks = file.metadata()?.len() as usize / Block512::size();
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            blocks,
+        })
+    }
+
+    /// Create a zeroed image of `blocks` blocks, truncate it if it exists
+    pub fn create(path: impl AsRef<Path>, blocks: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((blocks * Block512::size()) as u64)?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            blocks,
+        })
+    }
+
+    fn seek_to(file: &mut File, offset: usize) -> FsResult {
+        file.seek(io::SeekFrom::Start((offset * Block512::size()) as u64))
+            .map_err(|_| DeviceError::InvalidOperation)?;
+        Ok(())
+    }
+}
+
+impl BlockDevice<Block512> for FileDisk {
+    fn block_count(&self) -> FsResult<usize> {
+        Ok(self.blocks)
+    }
+
+    fn read_block(&self, offset: usize, block: &mut Block512) -> FsResult {
+        if offset >= self.blocks {
+            return Err(FsError::InvalidOffset);
+        }
+
+        let mut file = self.file.lock();
+        Self::seek_to(&mut file, offset)?;
+        file.read_exact(block.as_mut())
+            .map_err(|_| DeviceError::ReadError)?;
+        Ok(())
+    }
+
+    fn write_block(&self, offset: usize, block: &Block512) -> FsResult {
+        if offset >= self.blocks {
+            return Err(FsError::InvalidOffset);
+        }
+
+        let mut file = self.file.lock();
+        Self::seek_to(&mut file, offset)?;
+        file.write_all(block.as_ref())
+            .map_err(|_| DeviceError::WriteError)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat16::{format::format, Fat16};
+
+    #[test]
+    fn test_file_disk() {
+        let path = std::env::temp_dir().join(format!("ysos-file-disk-{}.img", std::process::id()));
+        let disk = FileDisk::create(&path, 8192).unwrap();
+        assert_eq!(disk.block_count(), Ok(8192));
+
+        let mut block = Block512::default();
+        assert!(disk.read_block(8192, &mut block).is_err());
+
+        format(&disk, "test").unwrap();
+        let fs = Fat16::new(disk);
+        let mut file = fs.create_file("/data.bin").unwrap();
+        file.write_all(&[0x5A; 3000]).unwrap();
+        drop((file, fs));
+
+        // reopen the image
+        let fs = Fat16::new(FileDisk::open(&path).unwrap());
+        let mut data = Vec::new();
+        fs.open_file("/data.bin").unwrap().read_all(&mut data).unwrap();
+        assert_eq!(data, [0x5A; 3000]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
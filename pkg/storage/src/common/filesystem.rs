@@ -21,7 +21,7 @@ pub trait FileSystem: Debug + Sync + Send {
     // NOTE: following functions are not implemented (optional)
     // ----------------------------------------------------
 
-    /// Creates a file at this path for writing
+    /// Creates a file at this path for writing, truncate it if it exists
     fn create_file(&self, _path: &str) -> FsResult<FileHandle> {
         Err(FsError::NotSupported)
     }
@@ -32,12 +32,17 @@ pub trait FileSystem: Debug + Sync + Send {
     }
 
     /// Removes the file at this path
-    fn remove_file(&self, _path: &str) -> FsResult<FileHandle> {
+    fn remove_file(&self, _path: &str) -> FsResult {
         Err(FsError::NotSupported)
     }
 
-    /// Removes the directory at this path
-    fn remove_dir(&self, _path: &str) -> FsResult<FileHandle> {
+    /// Creates a directory at this path
+    fn create_dir(&self, _path: &str) -> FsResult {
+        Err(FsError::NotSupported)
+    }
+
+    /// Removes the empty directory at this path
+    fn remove_dir(&self, _path: &str) -> FsResult {
         Err(FsError::NotSupported)
     }
 
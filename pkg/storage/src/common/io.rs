@@ -8,13 +8,20 @@ pub trait Read {
 
     /// Read all bytes until EOF in this source, placing them into `buf`.
     fn read_all(&mut self, buf: &mut Vec<u8>) -> FsResult<usize> {
-        let mut start_len = buf.len();
+        let start_len = buf.len();
+        let mut chunk = [0u8; 512];
         loop {
             // FIXME: read data into the buffer
             //      - extend the buffer if it's not big enough
             //      - break if the read returns 0 or Err
             //      - update the length of the buffer if data was read
+            match self.read(&mut chunk) {
+                Ok(0) | Err(FsError::EndOfFile) => break,
+                Ok(n) => buf.extend_from_slice(&chunk[..n]),
+                Err(err) => return Err(err),
+            }
         }
+        Ok(buf.len() - start_len)
     }
 }
 
@@ -31,8 +38,13 @@ pub trait Write {
 
     /// Attempts to write an entire buffer into this writer.
     fn write_all(&mut self, mut buf: &[u8]) -> FsResult {
-        // not required for lab
-        todo!()
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(FsError::WriteZero),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
     }
 }
 
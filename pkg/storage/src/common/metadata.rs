@@ -3,6 +3,21 @@ use chrono::{DateTime, Utc};
 
 pub type FsTime = DateTime<Utc>;
 
+static CLOCK: spin::Once<fn() -> FsTime> = spin::Once::new();
+
+/// Register the clock used to stamp created / modified entries
+pub fn set_clock(clock: fn() -> FsTime) {
+    CLOCK.call_once(|| clock);
+}
+
+/// Current time from the registered clock, unix epoch if none
+pub fn now() -> FsTime {
+    match CLOCK.get() {
+        Some(clock) => clock(),
+        None => DateTime::from_timestamp_millis(0).unwrap(),
+    }
+}
+
 /// Type of file entry
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum FileType {
@@ -2,8 +2,11 @@
 mod macros;
 
 mod block;
+mod cache;
 mod device;
 mod error;
+#[cfg(any(test, feature = "std"))]
+mod file_disk;
 mod filehandle;
 mod filesystem;
 mod io;
@@ -13,8 +16,11 @@ mod mount;
 use super::*;
 
 pub use block::*;
+pub use cache::*;
 pub use device::*;
 pub use error::*;
+#[cfg(any(test, feature = "std"))]
+pub use file_disk::*;
 pub use filehandle::*;
 pub use filesystem::*;
 pub use io::*;
@@ -22,3 +28,9 @@ pub use metadata::*;
 pub use mount::*;
 
 pub const PATH_SEPARATOR: char = '/';
+
+/// Split the path into the parent directory and the last component
+pub fn split_path(path: &str) -> (&str, &str) {
+    let path = path.trim_end_matches(PATH_SEPARATOR);
+    path.rsplit_once(PATH_SEPARATOR).unwrap_or(("", path))
+}
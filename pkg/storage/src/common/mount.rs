@@ -14,9 +14,25 @@ impl Mount {
         Self { fs, mount_point }
     }
 
+    /// Path relative to the mount point, `None` if not under it
+    ///
+    /// matches whole components only, `/data` does not cover `/database`
+    pub fn relative<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let rest = match self.mount_point.as_ref() {
+            "/" => path.strip_prefix(PATH_SEPARATOR)?,
+            point => path.strip_prefix(point)?,
+        };
+
+        if rest.is_empty() || rest.starts_with(PATH_SEPARATOR) || self.mount_point.as_ref() == "/" {
+            Some(rest.trim_start_matches(PATH_SEPARATOR))
+        } else {
+            None
+        }
+    }
+
     #[inline]
     fn trim_mount_point<'a>(&self, path: &'a str) -> &'a str {
-        path.trim_start_matches(self.mount_point.as_ref())
+        self.relative(path).unwrap_or(path)
     }
 }
 
@@ -40,6 +56,31 @@ impl FileSystem for Mount {
     fn exists(&self, path: &str) -> FsResult<bool> {
         self.fs.exists(self.trim_mount_point(path))
     }
+
+    #[inline]
+    fn create_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.fs.create_file(self.trim_mount_point(path))
+    }
+
+    #[inline]
+    fn append_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.fs.append_file(self.trim_mount_point(path))
+    }
+
+    #[inline]
+    fn remove_file(&self, path: &str) -> FsResult {
+        self.fs.remove_file(self.trim_mount_point(path))
+    }
+
+    #[inline]
+    fn create_dir(&self, path: &str) -> FsResult {
+        self.fs.create_dir(self.trim_mount_point(path))
+    }
+
+    #[inline]
+    fn remove_dir(&self, path: &str) -> FsResult {
+        self.fs.remove_dir(self.trim_mount_point(path))
+    }
 }
 
 impl core::fmt::Debug for Mount {
@@ -50,3 +91,202 @@ impl core::fmt::Debug for Mount {
             .finish()
     }
 }
+
+/// The mount table of the VFS
+///
+/// paths are resolved to the mount with the longest matching mount point
+pub struct MountTable {
+    // sorted by the length of mount points, longest first
+    mounts: spin::RwLock<Vec<Arc<Mount>>>,
+}
+
+impl MountTable {
+    pub const fn new() -> Self {
+        Self {
+            mounts: spin::RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Normalize the mount point: absolute, no trailing separator
+    pub fn normalize(point: &str) -> FsResult<Box<str>> {
+        if !point.starts_with(PATH_SEPARATOR) {
+            return Err(FsError::InvalidPath(point.into()));
+        }
+
+        match point.trim_end_matches(PATH_SEPARATOR) {
+            "" => Ok("/".into()),
+            point => Ok(point.into()),
+        }
+    }
+
+    /// Mount `fs` at `point`
+    pub fn mount(&self, fs: Box<dyn FileSystem>, point: &str) -> FsResult {
+        let point = Self::normalize(point)?;
+        let mut mounts = self.mounts.write();
+
+        if mounts.iter().any(|m| m.mount_point == point) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        mounts.push(Arc::new(Mount::new(fs, point)));
+        mounts.sort_by(|a, b| b.mount_point.len().cmp(&a.mount_point.len()));
+        Ok(())
+    }
+
+    /// Detach the filesystem mounted at `point`
+    ///
+    /// `Busy` if other filesystems are mounted under it
+    pub fn umount(&self, point: &str) -> FsResult<Arc<Mount>> {
+        let point = Self::normalize(point)?;
+        let mut mounts = self.mounts.write();
+
+        let idx = mounts
+            .iter()
+            .position(|m| m.mount_point == point)
+            .ok_or(FsError::FileNotFound)?;
+
+        let target = &mounts[idx];
+        let nested = mounts
+            .iter()
+            .any(|m| m.mount_point != point && target.relative(&m.mount_point).is_some());
+        if nested {
+            return Err(DeviceError::Busy.into());
+        }
+
+        Ok(mounts.remove(idx))
+    }
+
+    /// Find the mount which the path belongs to
+    pub fn resolve(&self, path: &str) -> FsResult<Arc<Mount>> {
+        self.mounts
+            .read()
+            .iter()
+            .find(|m| m.relative(path).is_some())
+            .cloned()
+            .ok_or(FsError::FileNotFound)
+    }
+
+    /// Mount points and the filesystems, longest mount point first
+    pub fn mounts(&self) -> Vec<Arc<Mount>> {
+        self.mounts.read().clone()
+    }
+}
+
+impl Default for MountTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for MountTable {
+    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+        self.resolve(path)?.read_dir(path)
+    }
+
+    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.resolve(path)?.open_file(path)
+    }
+
+    fn metadata(&self, path: &str) -> FsResult<Metadata> {
+        self.resolve(path)?.metadata(path)
+    }
+
+    fn exists(&self, path: &str) -> FsResult<bool> {
+        match self.resolve(path) {
+            Ok(mount) => mount.exists(path),
+            Err(FsError::FileNotFound) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn create_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.resolve(path)?.create_file(path)
+    }
+
+    fn append_file(&self, path: &str) -> FsResult<FileHandle> {
+        self.resolve(path)?.append_file(path)
+    }
+
+    fn remove_file(&self, path: &str) -> FsResult {
+        self.resolve(path)?.remove_file(path)
+    }
+
+    fn create_dir(&self, path: &str) -> FsResult {
+        self.resolve(path)?.create_dir(path)
+    }
+
+    fn remove_dir(&self, path: &str) -> FsResult {
+        self.resolve(path)?.remove_dir(path)
+    }
+}
+
+impl core::fmt::Debug for MountTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.mounts.read().iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A filesystem that only records the paths it is asked for
+    #[derive(Debug)]
+    struct Echo(&'static str);
+
+    impl FileSystem for Echo {
+        fn read_dir(&self, _path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+            Err(FsError::NotSupported)
+        }
+
+        fn open_file(&self, _path: &str) -> FsResult<FileHandle> {
+            Err(FsError::NotSupported)
+        }
+
+        fn metadata(&self, path: &str) -> FsResult<Metadata> {
+            Err(FsError::InvalidPath(format!("{}:{}", self.0, path)))
+        }
+
+        fn exists(&self, _path: &str) -> FsResult<bool> {
+            Ok(true)
+        }
+    }
+
+    fn which(table: &MountTable, path: &str) -> String {
+        match table.metadata(path) {
+            Err(FsError::InvalidPath(res)) => res,
+            other => panic!("unexpected {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_mount_table() {
+        let table = MountTable::new();
+        table.mount(Box::new(Echo("root")), "/").unwrap();
+        table.mount(Box::new(Echo("data")), "/data/").unwrap();
+        table.mount(Box::new(Echo("logs")), "/data/logs").unwrap();
+
+        assert_eq!(
+            table.mount(Box::new(Echo("dup")), "/data").err(),
+            Some(FsError::AlreadyExists)
+        );
+        assert!(table.mount(Box::new(Echo("rel")), "data").is_err());
+
+        assert_eq!(which(&table, "/"), "root:");
+        assert_eq!(which(&table, "/APP/SH"), "root:APP/SH");
+        assert_eq!(which(&table, "/database"), "root:database");
+        assert_eq!(which(&table, "/data"), "data:");
+        assert_eq!(which(&table, "/data/a.txt"), "data:a.txt");
+        assert_eq!(which(&table, "/data/logs/1/2"), "logs:1/2");
+
+        assert_eq!(
+            table.umount("/data").err(),
+            Some(FsError::DeviceError(DeviceError::Busy))
+        );
+        table.umount("/data/logs").unwrap();
+        assert_eq!(which(&table, "/data/logs/1"), "data:logs/1");
+        table.umount("/data").unwrap();
+        assert_eq!(which(&table, "/data/logs"), "root:data/logs");
+        assert_eq!(table.umount("/data").err(), Some(FsError::FileNotFound));
+    }
+}
@@ -0,0 +1,450 @@
+//! Fat16 Consistency Check
+//!
+//! Walk every directory from the root and compare the cluster chains with
+//! the FAT, like `fsck.fat`. Problems can optionally be repaired:
+//!
+//! - FAT copies are overwritten by the first FAT
+//! - broken chains are terminated where they go wrong
+//! - cross-linked chains are cut before the shared cluster
+//! - file sizes are fitted to the chain, extra clusters are freed
+//! - lost clusters are freed
+
+use super::*;
+
+/// FAT value of a bad cluster
+const FAT_BAD: u16 = 0xFFF7;
+/// FAT values from here mark the end of a chain
+const FAT_EOC: u16 = 0xFFF8;
+
+/// A problem found on the volume
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The FAT copy `copy` differs from the first FAT in `sectors` sectors
+    FatMismatch { copy: usize, sectors: usize },
+    /// The first cluster of the entry is outside the data region
+    InvalidStart { path: String, cluster: Cluster },
+    /// The chain of the entry links `cluster` to a free, bad or invalid cluster
+    BrokenChain { path: String, cluster: Cluster, next: u16 },
+    /// `cluster` is already used by `other`, or earlier in the same chain
+    CrossLinked {
+        path: String,
+        other: String,
+        cluster: Cluster,
+    },
+    /// The size of the file does not match the length of its chain
+    SizeMismatch {
+        path: String,
+        size: u32,
+        clusters: usize,
+    },
+    /// Clusters allocated in the FAT but not used by any entry
+    LostClusters { count: usize },
+}
+
+impl core::fmt::Display for Problem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Problem::FatMismatch { copy, sectors } => {
+                write!(f, "FAT copy {copy} differs in {sectors} sectors")
+            }
+            Problem::InvalidStart { path, cluster } => {
+                write!(f, "{path}: invalid start cluster {cluster}")
+            }
+            Problem::BrokenChain {
+                path,
+                cluster,
+                next,
+            } => write!(f, "{path}: cluster {cluster} links to {next:#06x}"),
+            Problem::CrossLinked {
+                path,
+                other,
+                cluster,
+            } => write!(f, "{path}: cluster {cluster} is also used by {other}"),
+            Problem::SizeMismatch {
+                path,
+                size,
+                clusters,
+            } => write!(f, "{path}: size {size} with {clusters} clusters"),
+            Problem::LostClusters { count } => write!(f, "{count} lost clusters"),
+        }
+    }
+}
+
+/// State of a check run
+struct Checker<'a> {
+    volume: &'a Fat16Impl,
+    repair: bool,
+    /// In memory copy of the first FAT
+    fat: Vec<u16>,
+    /// Index into `paths` of the entry using each cluster
+    owners: Vec<Option<usize>>,
+    paths: Vec<String>,
+    problems: Vec<Problem>,
+}
+
+impl Checker<'_> {
+    fn is_data(&self, value: u32) -> bool {
+        (2..self.fat.len() as u32).contains(&value)
+    }
+
+    fn set_next(&mut self, cluster: u32, value: u16) -> FsResult {
+        self.fat[cluster as usize] = value;
+        let next = match value {
+            FAT_BAD => Cluster::BAD,
+            FAT_EOC.. => Cluster::END_OF_FILE,
+            value => Cluster(value as u32),
+        };
+        self.volume.set_next_cluster(&Cluster(cluster), &next)
+    }
+
+    /// Read the first FAT and compare the other copies with it
+    fn load_fats(&mut self) -> FsResult {
+        let volume = self.volume;
+        let sectors = volume.bpb.sectors_per_fat() as usize;
+        let count = volume.cluster_count() as usize;
+
+        let mut first = Vec::with_capacity(sectors);
+        let mut block = Block512::default();
+        for sector in 0..sectors {
+            volume.inner.read_block(volume.fat_start + sector, &mut block)?;
+            first.push(block.clone());
+        }
+
+        self.fat = first
+            .iter()
+            .flat_map(|block| block.chunks_exact(2))
+            .map(|b| u16::from_le_bytes([b[0], b[1]]))
+            .take(count)
+            .collect();
+
+        for copy in 1..volume.bpb.fat_count() as usize {
+            let start = volume.fat_start + copy * sectors;
+            let mut differ = Vec::new();
+            for (sector, expected) in first.iter().enumerate() {
+                volume.inner.read_block(start + sector, &mut block)?;
+                if block.as_ref() != expected.as_ref() {
+                    differ.push(sector);
+                }
+            }
+
+            if differ.is_empty() {
+                continue;
+            }
+            self.problems.push(Problem::FatMismatch {
+                copy,
+                sectors: differ.len(),
+            });
+            if self.repair {
+                for sector in differ {
+                    volume.inner.write_block(start + sector, &first[sector])?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Follow the chain of an entry, return its length in clusters
+    fn walk_chain(&mut self, path: usize, entry: &mut DirEntry) -> FsResult<usize> {
+        let mut prev: Option<u32> = None;
+        let mut current = entry.cluster.0;
+        let mut count = 0;
+
+        loop {
+            if let Some(other) = self.owners[current as usize] {
+                self.problems.push(Problem::CrossLinked {
+                    path: self.paths[path].clone(),
+                    other: self.paths[other].clone(),
+                    cluster: Cluster(current),
+                });
+                if self.repair {
+                    match prev {
+                        Some(prev) => self.set_next(prev, 0xFFFF)?,
+                        None => entry.cluster = Cluster::EMPTY,
+                    }
+                }
+                return Ok(count);
+            }
+
+            self.owners[current as usize] = Some(path);
+            count += 1;
+
+            let next = self.fat[current as usize];
+            if next >= FAT_EOC {
+                return Ok(count);
+            }
+            // the next cluster must be allocated too
+            if !self.is_data(next as u32) || next == FAT_BAD || self.fat[next as usize] == 0 {
+                self.problems.push(Problem::BrokenChain {
+                    path: self.paths[path].clone(),
+                    cluster: Cluster(current),
+                    next,
+                });
+                if self.repair {
+                    self.set_next(current, 0xFFFF)?;
+                }
+                return Ok(count);
+            }
+
+            prev = Some(current);
+            current = next as u32;
+        }
+    }
+
+    /// Fit the file size to the chain, or free clusters past the size
+    fn fix_size(&mut self, entry: &mut DirEntry, clusters: usize) -> FsResult {
+        let cluster_size = self.volume.cluster_size();
+        let needed = (entry.size as usize).div_ceil(cluster_size);
+
+        if clusters < needed {
+            entry.size = (clusters * cluster_size) as u32;
+            return Ok(());
+        }
+
+        // keep `needed` clusters, free the rest
+        let mut current = entry.cluster.0;
+        for _ in 1..needed {
+            current = self.fat[current as usize] as u32;
+        }
+        let mut rest = match needed {
+            0 => {
+                entry.cluster = Cluster::EMPTY;
+                current
+            }
+            _ => {
+                let rest = self.fat[current as usize] as u32;
+                self.set_next(current, 0xFFFF)?;
+                rest
+            }
+        };
+
+        for _ in needed..clusters {
+            let next = self.fat[rest as usize] as u32;
+            self.owners[rest as usize] = None;
+            self.set_next(rest, 0)?;
+            rest = next;
+        }
+        Ok(())
+    }
+
+    /// Check the entries of `dir` and the directories below it
+    fn check_dir(&mut self, dir: &Directory, path: &str) -> FsResult {
+        let mut entries = Vec::new();
+        self.volume.scan_dir(dir, |entry, pos, _| {
+            entries.push((entry.clone(), pos));
+            None::<()>
+        })?;
+
+        for (mut entry, pos) in entries {
+            if entry.is_dot() || entry.attributes.contains(Attributes::VOLUME_ID) {
+                continue;
+            }
+
+            let entry_path = format!("{}/{}", path, entry.filename());
+            let original = entry.clone();
+            let index = self.paths.len();
+            self.paths.push(entry_path.clone());
+
+            let clusters = if entry.cluster == Cluster::EMPTY && !entry.is_directory() {
+                0
+            } else if !self.is_data(entry.cluster.0) {
+                self.problems.push(Problem::InvalidStart {
+                    path: entry_path.clone(),
+                    cluster: entry.cluster,
+                });
+                entry.cluster = Cluster::EMPTY;
+                0
+            } else {
+                self.walk_chain(index, &mut entry)?
+            };
+
+            if entry.is_directory() {
+                // a directory sharing its first cluster is not walked twice
+                if clusters > 0 && entry.cluster == original.cluster {
+                    self.check_dir(&Directory::from_entry(entry.clone()), &entry_path)?;
+                }
+            } else {
+                let needed = (entry.size as usize).div_ceil(self.volume.cluster_size());
+                if clusters != needed {
+                    self.problems.push(Problem::SizeMismatch {
+                        path: entry_path,
+                        size: entry.size,
+                        clusters,
+                    });
+                    if self.repair {
+                        self.fix_size(&mut entry, clusters)?;
+                    }
+                }
+            }
+
+            // a directory without clusters can not be repaired, leave it
+            if self.repair && entry != original && !(entry.is_directory() && clusters == 0) {
+                self.volume.write_entry(&pos, &entry)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Count clusters in use but not owned by any entry
+    fn check_lost(&mut self) -> FsResult {
+        let lost: Vec<u32> = (2..self.fat.len() as u32)
+            .filter(|&c| {
+                let value = self.fat[c as usize];
+                value != 0 && value != FAT_BAD && self.owners[c as usize].is_none()
+            })
+            .collect();
+
+        if lost.is_empty() {
+            return Ok(());
+        }
+
+        self.problems.push(Problem::LostClusters { count: lost.len() });
+        if self.repair {
+            for cluster in lost {
+                self.set_next(cluster, 0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the volume, repair the problems found if `repair` is set
+///
+/// The problems returned are the ones found before repairing.
+pub fn check(fs: &Fat16, repair: bool) -> FsResult<Vec<Problem>> {
+    let volume = fs.handle.as_ref();
+    let mut checker = Checker {
+        volume,
+        repair,
+        fat: Vec::new(),
+        owners: vec![None; volume.cluster_count() as usize],
+        paths: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    checker.load_fats()?;
+    checker.check_dir(&Directory::root(), "")?;
+    checker.check_lost()?;
+
+    Ok(checker.problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat16::format::format;
+    use crate::fs::tests::MemDisk;
+    use spin::Mutex;
+
+    const SECTORS: usize = 8192;
+    /// First FAT sector and FAT size given by `format` for `SECTORS`
+    const FAT_START: usize = 1;
+    const FAT_SECTORS: usize = 32;
+
+    fn mem_fat16() -> (Fat16, Arc<Mutex<Vec<u8>>>) {
+        let data = Arc::new(Mutex::new(vec![0u8; SECTORS * BLOCK_SIZE]));
+        format(&MemDisk(data.clone()), "check").unwrap();
+        (Fat16::new(MemDisk(data.clone())), data)
+    }
+
+    fn write_file(fs: &Fat16, path: &str, len: usize) {
+        let mut file = fs.create_file(path).unwrap();
+        file.write_all(&vec![0x42; len]).unwrap();
+    }
+
+    fn set_fat(disk: &Arc<Mutex<Vec<u8>>>, copy: usize, cluster: usize, value: u16) {
+        let offset = (FAT_START + copy * FAT_SECTORS) * BLOCK_SIZE + cluster * 2;
+        disk.lock()[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    #[test]
+    fn test_check_clean() {
+        let (fs, _) = mem_fat16();
+        fs.create_dir("/dir").unwrap();
+        write_file(&fs, "/dir/long file name.txt", 1500);
+        write_file(&fs, "/EMPTY.TXT", 0);
+
+        assert_eq!(check(&fs, false).unwrap(), []);
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let (fs, disk) = mem_fat16();
+        // clusters: A.TXT 2-4, B.TXT 5-6
+        write_file(&fs, "/A.TXT", 1500);
+        write_file(&fs, "/B.TXT", 1000);
+
+        // FAT copies differ
+        set_fat(&disk, 1, 100, 0x1234);
+        // B.TXT links into A.TXT
+        set_fat(&disk, 0, 5, 3);
+        set_fat(&disk, 1, 5, 3);
+        // a lost chain
+        set_fat(&disk, 0, 50, 51);
+        set_fat(&disk, 0, 51, 0xFFFF);
+        set_fat(&disk, 1, 50, 51);
+        set_fat(&disk, 1, 51, 0xFFFF);
+
+        let problems = check(&fs, true).unwrap();
+        assert_eq!(
+            problems,
+            [
+                Problem::FatMismatch {
+                    copy: 1,
+                    sectors: 1
+                },
+                Problem::CrossLinked {
+                    path: "/B.TXT".into(),
+                    other: "/A.TXT".into(),
+                    cluster: Cluster(3),
+                },
+                Problem::SizeMismatch {
+                    path: "/B.TXT".into(),
+                    size: 1000,
+                    clusters: 1
+                },
+                // the old second cluster of B.TXT and the lost chain
+                Problem::LostClusters { count: 3 },
+            ]
+        );
+
+        assert_eq!(check(&fs, false).unwrap(), []);
+        assert_eq!(fs.metadata("/B.TXT").unwrap().len, 512);
+        assert_eq!(fs.metadata("/A.TXT").unwrap().len, 1500);
+    }
+
+    #[test]
+    fn test_check_broken_chain() {
+        let (fs, disk) = mem_fat16();
+        write_file(&fs, "/A.TXT", 1500);
+        write_file(&fs, "/B.TXT", 100);
+
+        // A.TXT points to a free cluster, B.TXT ends early
+        for copy in 0..2 {
+            set_fat(&disk, copy, 3, 200);
+        }
+
+        let problems = check(&fs, true).unwrap();
+        assert_eq!(
+            problems,
+            [
+                Problem::BrokenChain {
+                    path: "/A.TXT".into(),
+                    cluster: Cluster(3),
+                    next: 200
+                },
+                Problem::SizeMismatch {
+                    path: "/A.TXT".into(),
+                    size: 1500,
+                    clusters: 2
+                },
+                Problem::LostClusters { count: 1 },
+            ]
+        );
+
+        assert_eq!(check(&fs, false).unwrap(), []);
+        assert_eq!(fs.metadata("/A.TXT").unwrap().len, 1024);
+        assert_eq!(fs.metadata("/B.TXT").unwrap().len, 100);
+    }
+}
@@ -29,8 +29,14 @@ impl Directory {
     }
 
     pub fn from_entry(entry: DirEntry) -> Self {
+        // `..` of a top level directory points to cluster 0
+        let cluster = match entry.cluster {
+            Cluster::EMPTY => Cluster::ROOT_DIR,
+            cluster => cluster,
+        };
+
         Directory {
-            cluster: entry.cluster,
+            cluster,
             entry: Some(entry),
         }
     }
@@ -5,13 +5,15 @@
 use crate::*;
 use bitflags::bitflags;
 use chrono::LocalResult::Single;
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Datelike, TimeZone, Timelike, Utc};
 use core::fmt::{Debug, Display};
 use core::ops::*;
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct DirEntry {
     pub filename: ShortFileName,
+    /// Long file name assembled from the preceding LFN entries
+    pub long_name: Option<String>,
     pub modified_time: FsTime,
     pub created_time: FsTime,
     pub accessed_time: FsTime,
@@ -23,6 +25,15 @@ pub struct DirEntry {
 #[derive(Copy, Clone, PartialEq, Eq)]
 pub struct Cluster(pub u32);
 
+/// Location of a directory entry on the disk
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct EntryPos {
+    /// The sector holding the entry
+    pub sector: usize,
+    /// Byte offset of the entry in the sector
+    pub offset: usize,
+}
+
 bitflags! {
     /// File Attributes
     #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -33,16 +44,32 @@ bitflags! {
         const VOLUME_ID = 0x08;
         const DIRECTORY = 0x10;
         const ARCHIVE   = 0x20;
-        const LFN       = 0x0f; // Long File Name, Not Implemented
+        const LFN       = 0x0f; // Long File Name
     }
 }
 
 impl DirEntry {
     pub const LEN: usize = 0x20;
 
+    /// Create a new entry stamped with the current time
+    pub fn new(filename: ShortFileName, attributes: Attributes, cluster: Cluster) -> Self {
+        let time = now();
+        DirEntry {
+            filename,
+            long_name: None,
+            modified_time: time,
+            created_time: time,
+            accessed_time: time,
+            cluster,
+            attributes,
+            size: 0,
+        }
+    }
+
     pub fn filename(&self) -> String {
-        // NOTE: ignore the long file name in FAT16 for lab
-        if self.is_valid() && !self.is_long_name() {
+        if let Some(name) = &self.long_name {
+            name.clone()
+        } else if self.is_valid() && !self.is_long_name() {
             format!("{}", self.filename)
         } else {
             String::from("unknown")
@@ -67,6 +94,7 @@ impl DirEntry {
 
         Ok(DirEntry {
             filename,
+            long_name: None,
             modified_time,
             created_time,
             accessed_time,
@@ -76,6 +104,25 @@ impl DirEntry {
         })
     }
 
+    /// Serialize into the 8.3 on-disk format, reverse of `parse`
+    pub fn as_bytes(&self) -> [u8; DirEntry::LEN] {
+        let mut data = [0u8; DirEntry::LEN];
+        let created_time = format_datetime(&self.created_time);
+        let accessed_date = (format_datetime(&self.accessed_time) >> 16) as u16;
+        let modified_time = format_datetime(&self.modified_time);
+
+        data[..8].copy_from_slice(&self.filename.name);
+        data[8..11].copy_from_slice(&self.filename.ext);
+        data[11] = self.attributes.bits();
+        data[14..18].copy_from_slice(&created_time.to_le_bytes());
+        data[18..20].copy_from_slice(&accessed_date.to_le_bytes());
+        data[20..22].copy_from_slice(&((self.cluster.0 >> 16) as u16).to_le_bytes());
+        data[22..26].copy_from_slice(&modified_time.to_le_bytes());
+        data[26..28].copy_from_slice(&(self.cluster.0 as u16).to_le_bytes());
+        data[28..32].copy_from_slice(&self.size.to_le_bytes());
+        data
+    }
+
     pub fn as_meta(&self) -> Metadata {
         self.into()
     }
@@ -88,6 +135,10 @@ impl DirEntry {
     pub fn is_directory(&self)->bool{
         self.attributes.contains(Attributes::DIRECTORY)
     }
+    /// `.` or `..` of a directory
+    pub fn is_dot(&self) -> bool {
+        self.filename.matches(&ShortFileName::DOT) || self.filename.matches(&ShortFileName::DOT_DOT)
+    }
 }
 
 fn prase_datetime(time: u32) -> FsTime {
@@ -124,6 +175,19 @@ fn prase_datetime(time: u32) -> FsTime {
     }
 }
 
+/// Encode the time as FAT date (high 16 bits) and time (low 16 bits)
+fn format_datetime(time: &FsTime) -> u32 {
+    // FAT 只能表示 1980 ~ 2107 年，超出范围时使用 1980-01-01 00:00:00
+    if !(1980..=2107).contains(&time.year()) {
+        return ((1 << 5) | 1) << 16;
+    }
+
+    let date = ((time.year() as u32 - 1980) << 9) | (time.month() << 5) | time.day();
+    let time = (time.hour() << 11) | (time.minute() << 5) | (time.second() / 2);
+
+    (date << 16) | time
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub struct ShortFileName {
     pub name: [u8; 8],
@@ -131,6 +195,19 @@ pub struct ShortFileName {
 }
 
 impl ShortFileName {
+    /// First byte of a deleted entry
+    pub const DELETED: u8 = 0xE5;
+    /// The `.` entry of a directory
+    pub const DOT: ShortFileName = ShortFileName {
+        name: *b".       ",
+        ext: *b"   ",
+    };
+    /// The `..` entry of a directory
+    pub const DOT_DOT: ShortFileName = ShortFileName {
+        name: *b"..      ",
+        ext: *b"   ",
+    };
+
     pub fn new(buf: &[u8]) -> Self {
         Self {
             name: buf[..8].try_into().unwrap(),
@@ -151,13 +228,21 @@ impl ShortFileName {
     }
 
     pub fn is_unused(&self) -> bool {
-        self.name[0] == 0xE5
+        self.name[0] == Self::DELETED
     }
 
     pub fn matches(&self, sfn: &ShortFileName) -> bool {
         self.name == sfn.name && self.ext == sfn.ext
     }
 
+    /// Checksum stored in the LFN entries of this short name
+    pub fn checksum(&self) -> u8 {
+        self.name
+            .iter()
+            .chain(self.ext.iter())
+            .fold(0u8, |sum, &c| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(c))
+    }
+
     /// Parse a short file name from a string
     pub fn parse(name: &str) -> FsResult<ShortFileName> {
         // FIXME: implement the parse function
@@ -180,6 +265,9 @@ impl ShortFileName {
             return Err(FilenameError::MisplacedPeriod.into());
         }
         let vec_name:Vec<_> = name.split(".").collect();
+        if vec_name.len() > 2 {
+            return Err(FilenameError::MisplacedPeriod.into());
+        }
         let name = vec_name[0];
         let extension = vec_name.get(1).map(|s| s.as_ref()).unwrap_or("");
         if name.len() > 8 || extension.len() > 3 {
@@ -328,4 +416,30 @@ mod tests {
 
         println!("{:#?}", res);
     }
+
+    #[test]
+    fn test_dir_entry_as_bytes() {
+        let data = hex_literal::hex!(
+            "4b 45 52 4e 45 4c 20 20 45 4c 46 20 00 00 0f be
+             d0 50 d0 50 00 00 0f be d0 50 02 00 f0 e4 0e 00"
+        );
+
+        let res = DirEntry::parse(&data).unwrap();
+        assert_eq!(res.as_bytes(), data);
+
+        let mut entry = DirEntry::new(
+            ShortFileName::parse("test.txt").unwrap(),
+            Attributes::ARCHIVE,
+            Cluster(0x1234),
+        );
+        entry.size = 42;
+        entry.modified_time = Utc.with_ymd_and_hms(2024, 5, 1, 12, 30, 10).unwrap();
+
+        let parsed = DirEntry::parse(&entry.as_bytes()).unwrap();
+        assert_eq!(&parsed.filename.name, b"TEST    ");
+        assert_eq!(&parsed.filename.ext, b"TXT");
+        assert_eq!(parsed.cluster, Cluster(0x1234));
+        assert_eq!(parsed.size, 42);
+        assert_eq!(parsed.modified_time, entry.modified_time);
+    }
 }
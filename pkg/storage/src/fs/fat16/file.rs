@@ -5,24 +5,43 @@
 use super::*;
 use core::cmp::min;
 
-#[derive(Debug, Clone)]
-pub struct File {
+/// Most bytes read from the device in one request
+const MAX_RUN: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub struct File<V: FatVolume> {
     /// The current offset in the file
     offset: usize,
     /// The current cluster of this file
     current_cluster: Cluster,
     /// DirEntry of this file
     entry: DirEntry,
+    /// Location of the DirEntry, updated on write
+    pos: EntryPos,
     /// The file system handle that contains this file
-    handle: Fat16Handle,
+    handle: Arc<V>,
+}
+
+// the volume is shared, `V` itself need not be `Clone`
+impl<V: FatVolume> Clone for File<V> {
+    fn clone(&self) -> Self {
+        Self {
+            offset: self.offset,
+            current_cluster: self.current_cluster,
+            entry: self.entry.clone(),
+            pos: self.pos.clone(),
+            handle: self.handle.clone(),
+        }
+    }
 }
 
-impl File {
-    pub fn new(handle: Fat16Handle, entry: DirEntry) -> Self {
+impl<V: FatVolume> File<V> {
+    pub fn new(handle: Arc<V>, entry: DirEntry, pos: EntryPos) -> Self {
         Self {
             offset: 0,
             current_cluster: entry.cluster,
             entry,
+            pos,
             handle,
         }
     }
@@ -30,9 +49,44 @@ impl File {
     pub fn length(&self) -> usize {
         self.entry.size as usize
     }
+
+    /// Bytes from `offset` on, at most `len`, in clusters following each
+    /// other on disk, and the cluster holding the position after them
+    fn contiguous_run(&self, len: usize) -> FsResult<(usize, Cluster)> {
+        let cluster_size = self.handle.cluster_size();
+        let mut bytes = min(len, cluster_size - self.offset % cluster_size);
+        let mut last = self.current_cluster;
+
+        loop {
+            // the run ends inside `last`
+            if (self.offset + bytes) % cluster_size != 0 {
+                return Ok((bytes, last));
+            }
+            let next = self.handle.get_next_cluster(&last)?;
+            if bytes == len || next.0 != last.0 + 1 {
+                return Ok((bytes, next));
+            }
+            last = next;
+            bytes += min(len - bytes, cluster_size);
+        }
+    }
+
+    /// Make sure `current_cluster` is allocated before writing at `offset`
+    fn alloc_current_cluster(&mut self) -> FsResult {
+        if self.entry.cluster == Cluster::EMPTY {
+            // 空文件还没有分配簇
+            self.entry.cluster = self.handle.alloc_cluster()?;
+            self.current_cluster = self.entry.cluster;
+        } else if !self.handle.is_data_cluster(&self.current_cluster) {
+            // offset 在簇链末尾，需要扩展簇链
+            let last = self.handle.last_cluster(&self.entry.cluster)?;
+            self.current_cluster = self.handle.extend_chain(&last)?;
+        }
+        Ok(())
+    }
 }
 
-impl Read for File {
+impl<V: FatVolume> Read for File<V> {
     fn read(&mut self, buf: &mut [u8]) -> FsResult<usize> {
         // FIXME: read file content from disk
         //      CAUTION: file length / buffer size / offset
@@ -43,52 +97,145 @@ impl Read for File {
         //      - use `self.handle.cluster_to_sector` to convert cluster to sector
         //      - update `self.offset` after reading
         //      - update `self.cluster` with FAT if necessary
-        let bytes_per_sec = self.handle.bpb.bytes_per_sector();
-        let sec_per_clus = self.handle.bpb.sectors_per_cluster();
-        let mut block = Block::default();
-        let mut sector_num = self.offset / bytes_per_sec as usize; 
-        let mut bytes_num = self.offset % bytes_per_sec as usize;
-        let mut current_offset = self.handle.cluster_to_sector(&self.current_cluster) + sector_num;
-        let mut space = buf.len();
-        let mut read:usize = 0;
-        while  space > 0 || self.offset == self.entry.size as usize {
-            self.handle.inner.read_block(current_offset,&mut block)?;
-            let need_to_read = min(space,
-                                        min(bytes_per_sec as usize - bytes_num, 
-                                                self.entry.size as usize - self.offset)
-                                            );
-            space -= need_to_read;
-            self.offset += need_to_read;
-            buf[read .. read+need_to_read].copy_from_slice(&block[bytes_num .. bytes_num+need_to_read]);
-            read += need_to_read;
-            bytes_num = 0;
-            sector_num = match sector_num+1 {
-                sec_per_clus=> {
-                    self.current_cluster = self.handle.get_next_cluster(&self.current_cluster)?;
-                    0
-                }
-                _ => sector_num + 1,
+        //      - `self.current_cluster` is always the cluster holding `self.offset`
+        let cluster_size = self.handle.cluster_size();
+
+        if self.offset >= self.length() {
+            return Ok(0);
+        }
+        let to_read = min(buf.len(), self.length() - self.offset);
+        let mut read: usize = 0;
+
+        while read < to_read {
+            let cluster_offset = self.offset % cluster_size;
+            let sector = self.handle.cluster_to_sector(&self.current_cluster)
+                + cluster_offset / BLOCK_SIZE;
+            let sector_offset = cluster_offset % BLOCK_SIZE;
+
+            // one request for the clusters following each other on disk,
+            // the position is only updated once all reads succeed
+            let run = self.contiguous_run(min(to_read - read, MAX_RUN)).and_then(|(len, next)| {
+                let mut blocks = vec![Block::default(); (sector_offset + len).div_ceil(BLOCK_SIZE)];
+                self.handle.device().read_blocks(sector, &mut blocks)?;
+                Ok((len, next, blocks))
+            });
+            let (len, next, blocks) = match run {
+                Ok(run) => run,
+                // return what has been read, the error shows up on the next read
+                Err(_) if read > 0 => break,
+                Err(err) => return Err(err),
+            };
+
+            let (end, mut start) = (read + len, sector_offset);
+            for block in blocks.iter() {
+                let count = min(end - read, BLOCK_SIZE - start);
+                buf[read..read + count].copy_from_slice(&block[start..start + count]);
+                read += count;
+                start = 0;
             }
-            
+            self.offset += len;
+            self.current_cluster = next;
         }
-        return Ok(read);
+        Ok(read)
     }
 }
 
-// NOTE: `Seek` trait is not required for this lab
-impl Seek for File {
+impl<V: FatVolume> Seek for File<V> {
     fn seek(&mut self, pos: SeekFrom) -> FsResult<usize> {
-        unimplemented!()
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length().checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+        };
+
+        // can not seek before the start or past the end of file
+        let offset = match offset {
+            Some(offset) if offset <= self.length() => offset,
+            _ => return Err(FsError::InvalidOffset),
+        };
+
+        // walk the cluster chain, from current cluster if seeking forward
+        let cluster_size = self.handle.cluster_size();
+        let target = offset / cluster_size;
+        let (mut cluster, mut index) = if target >= self.offset / cluster_size {
+            (self.current_cluster, self.offset / cluster_size)
+        } else {
+            (self.entry.cluster, 0)
+        };
+
+        while index < target {
+            cluster = self.handle.get_next_cluster(&cluster)?;
+            if cluster == Cluster::END_OF_FILE || cluster == Cluster::INVALID {
+                // only when seeking to the end of a cluster-aligned file
+                if offset != self.length() || index + 1 != target {
+                    return Err(FsError::BadCluster);
+                }
+            }
+            index += 1;
+        }
+
+        self.offset = offset;
+        self.current_cluster = cluster;
+        Ok(offset)
     }
 }
 
-// NOTE: `Write` trait is not required for this lab
-impl Write for File {
-    fn write(&mut self, _buf: &[u8]) -> FsResult<usize> {
-        unimplemented!()
+impl<V: FatVolume> Write for File<V> {
+    fn write(&mut self, buf: &[u8]) -> FsResult<usize> {
+        // work on a copy, a failed write leaves the position and the entry
+        // as they were so it can be tried again
+        let mut file = self.clone();
+        let written = file.write_sectors(buf)?;
+        *self = file;
+        Ok(written)
     }
 
     fn flush(&mut self) -> FsResult {
-        unimplemented!()
+        // data goes to the device directly, only the entry needs to be updated
+        self.handle.write_entry(&self.pos, &self.entry)
+    }
+}
+
+impl<V: FatVolume> File<V> {
+    fn write_sectors(&mut self, buf: &[u8]) -> FsResult<usize> {
+        let bytes_per_sec = BLOCK_SIZE;
+        let cluster_size = self.handle.cluster_size();
+        let mut block = Block::default();
+
+        // file size is limited to u32 in the DirEntry
+        let to_write = min(buf.len(), u32::MAX as usize - self.offset);
+        let mut written: usize = 0;
+
+        while written < to_write {
+            self.alloc_current_cluster()?;
+
+            let cluster_offset = self.offset % cluster_size;
+            let sector = self.handle.cluster_to_sector(&self.current_cluster)
+                + cluster_offset / bytes_per_sec;
+            let sector_offset = cluster_offset % bytes_per_sec;
+            let len = min(to_write - written, bytes_per_sec - sector_offset);
+
+            // no need to read the sector if it is overwritten entirely
+            if len < bytes_per_sec {
+                self.handle.device().read_block(sector, &mut block)?;
+            }
+            block[sector_offset..sector_offset + len].copy_from_slice(&buf[written..written + len]);
+            self.handle.device().write_block(sector, &block)?;
+            written += len;
+            self.offset += len;
+
+            // may be the end of chain, allocated on the next write
+            if self.offset % cluster_size == 0 {
+                self.current_cluster = self.handle.get_next_cluster(&self.current_cluster)?;
+            }
+        }
+
+        if written > 0 {
+            self.entry.size = self.entry.size.max(self.offset as u32);
+            self.entry.modified_time = now();
+            self.handle.write_entry(&self.pos, &self.entry)?;
+        }
+
+        Ok(written)
     }
 }
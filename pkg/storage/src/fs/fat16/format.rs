@@ -0,0 +1,143 @@
+//! Fat16 Formatter
+//!
+//! Write an empty Fat16 volume: boot sector, two FATs and the root directory.
+//!
+//! reference: Microsoft FAT Specification, 3.5 FAT Type Determination
+
+use super::*;
+
+const RESERVED_SECTORS: usize = 1;
+const FAT_COUNT: usize = 2;
+const ROOT_ENTRIES: usize = 512;
+const MEDIA_FIXED: u8 = 0xF8;
+
+/// Sizes of a Fat16 layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Layout {
+    sectors_per_cluster: usize,
+    sectors_per_fat: usize,
+}
+
+/// Pick the smallest cluster size giving a cluster count valid for Fat16
+fn layout(total_sectors: usize) -> FsResult<Layout> {
+    let root_sectors = ROOT_ENTRIES * DirEntry::LEN / BLOCK_SIZE;
+
+    for sectors_per_cluster in [1, 2, 4, 8, 16, 32, 64, 128] {
+        let meta = RESERVED_SECTORS + root_sectors;
+        let Some(data) = total_sectors.checked_sub(meta) else {
+            break;
+        };
+
+        // FAT entries also cover the two reserved clusters
+        let clusters = data / sectors_per_cluster;
+        let sectors_per_fat = ((clusters + 2) * 2).div_ceil(BLOCK_SIZE);
+
+        let Some(data) = data.checked_sub(FAT_COUNT * sectors_per_fat) else {
+            break;
+        };
+        match data / sectors_per_cluster {
+            4085..65525 => {
+                return Ok(Layout {
+                    sectors_per_cluster,
+                    sectors_per_fat,
+                });
+            }
+            ..4085 => break,
+            _ => continue,
+        }
+    }
+
+    Err(FsError::NotSupported)
+}
+
+/// Format the whole device as an empty Fat16 volume
+///
+/// The device must be between about 2 MiB and 4 GiB.
+pub fn format(device: &impl BlockDevice<Block512>, label: &str) -> FsResult {
+    let total_sectors = device.block_count()?;
+    let layout = layout(total_sectors)?;
+    let root_sectors = ROOT_ENTRIES * DirEntry::LEN / BLOCK_SIZE;
+
+    let mut volume_label = [b' '; 11];
+    for (dst, src) in volume_label.iter_mut().zip(label.bytes()) {
+        *dst = src.to_ascii_uppercase();
+    }
+
+    let mut block = Block512::default();
+    block[0x00..0x03].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    block[0x03..0x0B].copy_from_slice(b"YSOS    ");
+    block[0x0B..0x0D].copy_from_slice(&(BLOCK_SIZE as u16).to_le_bytes());
+    block[0x0D] = layout.sectors_per_cluster as u8;
+    block[0x0E..0x10].copy_from_slice(&(RESERVED_SECTORS as u16).to_le_bytes());
+    block[0x10] = FAT_COUNT as u8;
+    block[0x11..0x13].copy_from_slice(&(ROOT_ENTRIES as u16).to_le_bytes());
+    match u16::try_from(total_sectors) {
+        Ok(total) => block[0x13..0x15].copy_from_slice(&total.to_le_bytes()),
+        Err(_) => block[0x20..0x24].copy_from_slice(&(total_sectors as u32).to_le_bytes()),
+    }
+    block[0x15] = MEDIA_FIXED;
+    block[0x16..0x18].copy_from_slice(&(layout.sectors_per_fat as u16).to_le_bytes());
+    block[0x18..0x1A].copy_from_slice(&63u16.to_le_bytes());
+    block[0x1A..0x1C].copy_from_slice(&255u16.to_le_bytes());
+    block[0x24] = 0x80;
+    block[0x26] = 0x29;
+    let volume_id = now().timestamp() as u32;
+    block[0x27..0x2B].copy_from_slice(&volume_id.to_le_bytes());
+    block[0x2B..0x36].copy_from_slice(&volume_label);
+    block[0x36..0x3E].copy_from_slice(b"FAT16   ");
+    block[0x1FE..0x200].copy_from_slice(&[0x55, 0xAA]);
+    device.write_block(0, &block)?;
+
+    // clear the FATs and the root directory
+    let zero = Block512::default();
+    let meta_end = RESERVED_SECTORS + FAT_COUNT * layout.sectors_per_fat + root_sectors;
+    for sector in RESERVED_SECTORS..meta_end {
+        device.write_block(sector, &zero)?;
+    }
+
+    // the two reserved FAT entries: media descriptor and end of chain
+    let mut fat = Block512::default();
+    fat[0..4].copy_from_slice(&[MEDIA_FIXED, 0xFF, 0xFF, 0xFF]);
+    for i in 0..FAT_COUNT {
+        device.write_block(RESERVED_SECTORS + i * layout.sectors_per_fat, &fat)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::MemDisk;
+    use spin::Mutex;
+
+    #[test]
+    fn test_fat16_layout() {
+        let small = layout(8192).unwrap();
+        assert_eq!(small.sectors_per_cluster, 1);
+        assert_eq!(small.sectors_per_fat, 32);
+
+        // 64 MiB needs 2 sectors per cluster
+        assert_eq!(layout(131072).unwrap().sectors_per_cluster, 2);
+        assert_eq!(layout(1024), Err(FsError::NotSupported));
+    }
+
+    #[test]
+    fn test_fat16_format() {
+        let disk = MemDisk(Arc::new(Mutex::new(vec![0xCC; 8192 * BLOCK_SIZE])));
+        format(&disk, "ysos").unwrap();
+        assert_eq!(FatType::detect(&disk), Ok(FatType::Fat16));
+
+        let fs = Fat16::new(disk.clone());
+        assert_eq!(fs.handle.bpb.volume_label(), b"YSOS       ");
+        assert_eq!(fs.read_dir("/").unwrap().count(), 0);
+
+        let mut file = fs.create_file("/hello.txt").unwrap();
+        file.write_all(b"hello, world").unwrap();
+
+        let mut buf = Vec::new();
+        let mut file = fs.open_file("/hello.txt").unwrap();
+        assert_eq!(file.read_all(&mut buf).unwrap(), 12);
+        assert_eq!(buf, b"hello, world");
+    }
+}
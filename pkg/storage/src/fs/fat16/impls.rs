@@ -1,5 +1,4 @@
 use super::*;
-use core::f32::consts::E;
 use common::FsResult;
 
 impl Fat16Impl {
@@ -30,8 +29,28 @@ impl Fat16Impl {
             first_root_dir_sector,
         }
     }
+}
+
+impl FatVolume for Fat16Impl {
+    fn device(&self) -> &dyn BlockDevice<Block512> {
+        self.inner.as_ref()
+    }
+
+    fn sectors_per_cluster(&self) -> usize {
+        self.bpb.sectors_per_cluster() as usize
+    }
+
+    fn root_dir_sectors(&self) -> Option<usize> {
+        Some(self.first_data_sector - self.first_root_dir_sector)
+    }
+
+    fn cluster_count(&self) -> u32 {
+        let data_sectors = self.bpb.total_sectors() as usize - self.first_data_sector;
+        let count = data_sectors / self.bpb.sectors_per_cluster() as usize + 2;
+        count.min(0xFFF6) as u32
+    }
 
-    pub fn cluster_to_sector(&self, cluster: &Cluster) -> usize {
+    fn cluster_to_sector(&self, cluster: &Cluster) -> usize {
         match *cluster {
             Cluster::ROOT_DIR => self.first_root_dir_sector,
             Cluster(c) => {
@@ -44,7 +63,7 @@ impl Fat16Impl {
 
     // FIXME: YOU NEED TO IMPLEMENT THE FILE SYSTEM OPERATIONS HERE
     //      - read the FAT and get next cluster
-    pub fn get_next_cluster(&self, cluster: &Cluster) -> Result<Cluster> {
+    fn get_next_cluster(&self, cluster: &Cluster) -> Result<Cluster> {
         if *cluster == Cluster::ROOT_DIR {
             Ok(Cluster::END_OF_FILE)
         } else {
@@ -66,7 +85,7 @@ impl Fat16Impl {
                 Cluster(c) => {
                     if (0x0000_0002..0x0000_FFF6).contains(&c) {
                         Ok(Cluster(c))
-                    } else if c >= 0xFFFF_FFF8 {
+                    } else if c >= 0x0000_FFF8 {
                         Ok(Cluster::END_OF_FILE)
                     } else {
                         Ok(Cluster::INVALID)
@@ -75,201 +94,282 @@ impl Fat16Impl {
             }
         }
     }
-    //      - traverse the cluster chain and read the data
-    pub fn traverse_cluster_chain(&self, dir: &Directory) -> Result<Vec<Metadata>> {
-        let mut entries = Vec::new();
-        let mut current_cluster = dir.cluster;
-        let mut block: Block<512> = Block::default(); 
-        let entries_per_sector = BLOCK_SIZE / DirEntry::LEN;
 
-        loop {
+    // 写入所有 FAT 副本
+    fn set_next_cluster(&self, cluster: &Cluster, next: &Cluster) -> Result {
+        let value: u16 = match *next {
+            Cluster::END_OF_FILE => 0xFFFF,
+            Cluster::BAD => 0xFFF7,
+            Cluster(c) => c as u16,
+        };
 
-            let sectors_to_read_in_cluster = match current_cluster {
-                Cluster::ROOT_DIR => {
-                    // 根目录区域有固定的大小
-                    // BPB 中的 root_dir_entries 指定了32字节条目的数量
-                    let root_dir_bytes = self.bpb.root_entries_count() as usize * DirEntry::LEN;
+        let fat_offset = (cluster.0 * 2) as usize;
+        let offset_in_sector = fat_offset % BLOCK_SIZE;
+        let mut block: Block<512> = Block::default();
 
-                    (root_dir_bytes + BLOCK_SIZE - 1) / BLOCK_SIZE
-                }
-                _ => {
+        for fat in 0..self.bpb.fat_count() as usize {
+            let sector = self.fat_start
+                + fat * self.bpb.sectors_per_fat() as usize
+                + fat_offset / BLOCK_SIZE;
+            self.inner.read_block(sector, &mut block)?;
+            block[offset_in_sector..offset_in_sector + 2].copy_from_slice(&value.to_le_bytes());
+            self.inner.write_block(sector, &block)?;
+        }
 
-                    self.bpb.sectors_per_cluster() as usize
-                }
-            };
+        Ok(())
+    }
 
-            let first_sector_of_this_cluster = self.cluster_to_sector(&current_cluster);
+    fn alloc_cluster(&self) -> Result<Cluster> {
+        let count = self.cluster_count();
+        let entries_per_sector = (BLOCK_SIZE / 2) as u32;
+        let mut block: Block<512> = Block::default();
 
-            for sector_idx_in_cluster in 0..sectors_to_read_in_cluster {
-                let current_sector_lba = first_sector_of_this_cluster + sector_idx_in_cluster;
-                self.inner.read_block(current_sector_lba, &mut block)?;
+        for fat_sector in 0..self.bpb.sectors_per_fat() as u32 {
+            let first = fat_sector * entries_per_sector;
+            if first >= count {
+                break;
+            }
 
-                for entry_idx_in_sector in 0..entries_per_sector {
-                    let entry_offset_in_block = entry_idx_in_sector * DirEntry::LEN;
+            self.inner.read_block(self.fat_start + fat_sector as usize, &mut block)?;
 
-                    if entry_offset_in_block + DirEntry::LEN > block.len() {
+            for idx in 0..entries_per_sector {
+                let cluster = Cluster(first + idx);
+                if cluster.0 < 2 {
+                    continue;
+                }
+                if cluster.0 >= count {
+                    break;
+                }
 
-                        break; 
-                    }
-                    let entry_slice = &block[entry_offset_in_block .. entry_offset_in_block + DirEntry::LEN];
+                let offset = idx as usize * 2;
+                if block[offset] == 0 && block[offset + 1] == 0 {
+                    self.set_next_cluster(&cluster, &Cluster::END_OF_FILE)?;
+                    self.zero_cluster(&cluster)?;
+                    return Ok(cluster);
+                }
+            }
+        }
 
-                    match DirEntry::parse(entry_slice) {
-                        Ok(dir_entry) => {
-                            if dir_entry.filename.is_eod() {
+        Err(FsError::WriteZero)
+    }
+}
 
-                                return Ok(entries);
-                            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::MemDisk;
+    use spin::Mutex;
+
+    const SECTORS: usize = 8192;
+
+    /// 4 MiB volume: 1 reserved, 2 FATs of 32 sectors, 512 root entries
+    fn mem_fat16() -> (Fat16, Arc<Mutex<Vec<u8>>>) {
+        let mut data = vec![0u8; SECTORS * BLOCK_SIZE];
+        data[0..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+        data[3..11].copy_from_slice(b"MSWIN4.1");
+        data[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+        data[0x0d] = 1;
+        data[0x0e..0x10].copy_from_slice(&1u16.to_le_bytes());
+        data[0x10] = 2;
+        data[0x11..0x13].copy_from_slice(&512u16.to_le_bytes());
+        data[0x13..0x15].copy_from_slice(&(SECTORS as u16).to_le_bytes());
+        data[0x15] = 0xF8;
+        data[0x16..0x18].copy_from_slice(&32u16.to_le_bytes());
+        data[0x1fe..0x200].copy_from_slice(&[0x55, 0xAA]);
+        for fat in 0..2 {
+            let start = (1 + fat * 32) * BLOCK_SIZE;
+            data[start..start + 4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]);
+        }
 
+        let data = Arc::new(Mutex::new(data));
+        (Fat16::new(MemDisk(data.clone())), data)
+    }
 
+    #[test]
+    fn test_fat16_write() {
+        let (fs, disk) = mem_fat16();
+        assert_eq!(FatType::detect(&MemDisk(disk.clone())), Ok(FatType::Fat16));
 
-                            if !dir_entry.is_long_name()  {entries.push(dir_entry.as_meta());}
-                        }
-                        Err(_parse_err) => {
+        // two clusters and a bit
+        let content: Vec<u8> = (0..1100u32).map(|i| i as u8).collect();
+        let mut file = fs.create_file("/HELLO.TXT").unwrap();
+        file.write_all(&content).unwrap();
 
-                            continue;
-                        }
-                    }
-                }
-            }
+        let mut file = fs.append_file("/HELLO.TXT").unwrap();
+        file.write_all(b"tail").unwrap();
 
-            if current_cluster == Cluster::ROOT_DIR {
-                break; 
+        let mut file = fs.open_file("/hello.txt").unwrap();
+        let mut buf = vec![0u8; 2048];
+        let mut len = 0;
+        loop {
+            match file.read(&mut buf[len..]).unwrap() {
+                0 => break,
+                n => len += n,
             }
+        }
+        assert_eq!(len, 1104);
+        assert_eq!(&buf[..1100], &content[..]);
+        assert_eq!(&buf[1100..1104], b"tail");
+        assert_eq!(fs.metadata("/HELLO.TXT").unwrap().len, 1104);
+
+        // both FAT copies are updated
+        {
+            let data = disk.lock();
+            let fat1 = &data[BLOCK_SIZE..33 * BLOCK_SIZE];
+            let fat2 = &data[33 * BLOCK_SIZE..65 * BLOCK_SIZE];
+            assert_eq!(fat1, fat2);
+            assert_eq!(&fat1[4..10], &[3, 0, 4, 0, 0xFF, 0xFF]);
+        }
 
-            match self.get_next_cluster(&current_cluster) {
-                Ok(next_cluster) => {
-                    if next_cluster == Cluster::END_OF_FILE { 
-                        break; 
-                    }
-                    current_cluster = next_cluster;
-                }
-                Err(e) => {
+        // truncate
+        let file = fs.create_file("/HELLO.TXT").unwrap();
+        assert_eq!(file.meta.len, 0);
+        assert_eq!(fs.handle.get_next_cluster(&Cluster(3)).unwrap(), Cluster::EMPTY);
 
-                    warn!("Error getting next cluster: {:?}", e);
-                    return Err(e.into());
-                }
-            }
-        }
-        Ok(entries)
+        fs.remove_file("/HELLO.TXT").unwrap();
+        assert!(!fs.exists("/HELLO.TXT").unwrap());
+        assert_eq!(fs.read_dir("/").unwrap().count(), 0);
     }
-    //      - parse the path
-    pub fn match_name_entry(&self,name:&ShortFileName,sector:usize)->Result<DirEntry>{
-        let mut block: Block<512> = Block::default();
-        self.inner.read_block(sector, &mut block);
-        for entry in 0..(BLOCK_SIZE / DirEntry::LEN) {
-            let dir_entry = DirEntry::parse(&block[entry * DirEntry::LEN..(entry + 1) * DirEntry::LEN])
-                                        .map_err(|_| FsError::InvalidOperation)?;
-            if dir_entry.filename.is_eod() {
-                return Err(FsError::FileNotFound);
-            }
-            if dir_entry.filename.matches(name) {
-                return Ok(dir_entry);
-            }
+
+    /// Counts the multi-block reads
+    struct RunCounter(MemDisk, Arc<Mutex<Vec<(usize, usize)>>>);
+
+    impl BlockDevice<Block512> for RunCounter {
+        fn block_count(&self) -> FsResult<usize> {
+            self.0.block_count()
         }
-        Err(FsError::NotInSector)
-    }
-    //      - open the root directory
-    pub fn open_directory(&self,dir:&Directory,name:&str) -> Result<DirEntry>{
-        let match_name =ShortFileName::parse(&name)?;
-        let mut current_cluster = dir.cluster;
-        let mut block: Block<512> = Block::default();
-        let entries_per_sector = BLOCK_SIZE / DirEntry::LEN;
-        let  sector_size = match dir.cluster {
-            Cluster::ROOT_DIR => self.first_data_sector - self.first_root_dir_sector,
-            _ => self.bpb.sectors_per_cluster() as usize,
-        };
-        let mut sector_offset = self.cluster_to_sector(&current_cluster);
-        loop{
-            for sector in sector_offset..sector_offset + sector_size {
-                match self.match_name_entry(&match_name, sector) {
-                    Err(FsError::NotInSector) => continue,
-                    x => return x,
-                }
-            }
-            current_cluster = if current_cluster != Cluster::ROOT_DIR {
-                match self.get_next_cluster(&current_cluster) {
-                    Ok(n) => {
-                        sector_offset = self.cluster_to_sector(&n);
-                        n
-                    }
-                    _ => break,
-                }
-            } else {
-                break
-            }
+
+        fn read_block(&self, offset: usize, block: &mut Block512) -> FsResult {
+            self.0.read_block(offset, block)
         }
-        Err(FsError::FileNotFound)
 
+        fn read_blocks(&self, offset: usize, blocks: &mut [Block512]) -> FsResult {
+            self.1.lock().push((offset, blocks.len()));
+            self.0.read_blocks(offset, blocks)
+        }
 
+        fn write_block(&self, offset: usize, block: &Block512) -> FsResult {
+            self.0.write_block(offset, block)
+        }
     }
-    //      - ...
-    pub fn get_dir_from_name(&self,path: &str)->Result<Directory>{
-        let mut path = path.split(PATH_SEPARATOR);
-         let mut current = Directory::root();
- 
-         while let Some(dir) = path.next() {
-             if dir.is_empty() {
-                 continue;
-             }
- 
-             let entry = self.open_directory(&current, dir)?;
- 
-             if entry.is_directory() {
-                 current = Directory::from_entry(entry);
-             } else if path.next().is_some() {
-                 return Err(FsError::NotADirectory);
-             } else {
-                 break;
-             }
-         }
- 
-         Ok(current)
-     }
-    //      - finally, implement the FileSystem trait for Fat16 with `self.handle`
-}
 
-impl FileSystem for Fat16 {
-    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
-        // FIXME: read dir and return an iterator for all entries
-        // FIXME: read dir and return an iterator for all entries
-        let dir = self.handle.get_dir_from_name(path)?;
-
-        let  entries = self.handle.traverse_cluster_chain(&dir)?;
+    #[test]
+    fn test_fat16_read_runs() {
+        let (fs, disk) = mem_fat16();
+
+        // clusters 2..=4 and 6..=7, another file takes cluster 5
+        let content: Vec<u8> = (0..2500u32).map(|i| (i % 251) as u8).collect();
+        fs.create_file("/a").unwrap().write_all(&content[..1500]).unwrap();
+        fs.create_file("/b").unwrap().write_all(b"b").unwrap();
+        fs.append_file("/a").unwrap().write_all(&content[1500..]).unwrap();
+
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let fs = Fat16::new(RunCounter(MemDisk(disk), runs.clone()));
+        let mut file = fs.open_file("/a").unwrap();
+        let mut buf = vec![0u8; 4096];
+        runs.lock().clear();
+
+        assert_eq!(file.read(&mut buf).unwrap(), 2500);
+        assert_eq!(&buf[..2500], &content[..]);
+        let data_start = 1 + 2 * 32 + 32;
+        assert_eq!(*runs.lock(), [(data_start, 3), (data_start + 4, 2)]);
+
+        // starting inside a sector, ending in the middle of a run
+        file.seek(SeekFrom::Start(700)).unwrap();
+        assert_eq!(file.read(&mut buf[..1000]).unwrap(), 1000);
+        assert_eq!(&buf[..1000], &content[700..1700]);
+        assert_eq!(file.read(&mut buf).unwrap(), 800);
+        assert_eq!(&buf[..800], &content[1700..]);
+    }
 
-        Ok(Box::new(entries.into_iter()))
+    #[test]
+    fn test_fat16_seek() {
+        let (fs, _) = mem_fat16();
+
+        // clusters 2..=3 and 5, another file takes cluster 4
+        let content: Vec<u8> = (0..1300u32).map(|i| (i % 251) as u8).collect();
+        fs.create_file("/a").unwrap().write_all(&content[..1024]).unwrap();
+        fs.create_file("/b").unwrap().write_all(b"b").unwrap();
+        fs.append_file("/a").unwrap().write_all(&content[1024..]).unwrap();
+
+        let mut file = fs.open_file("/a").unwrap();
+        let mut buf = [0u8; 8];
+
+        // across the boundary of two adjacent clusters
+        assert_eq!(file.seek(SeekFrom::Start(510)), Ok(510));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[510..518]);
+
+        // backwards
+        assert_eq!(file.seek(SeekFrom::Current(-8)), Ok(510));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[510..518]);
+
+        // forward to the end of the first run, the read follows the chain
+        assert_eq!(file.seek(SeekFrom::Current(500)), Ok(1018));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[1018..1026]);
+
+        // backwards from the last cluster into the first one
+        assert_eq!(file.seek(SeekFrom::Current(-1020)), Ok(6));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[6..14]);
+
+        // straight into the last cluster
+        assert_eq!(file.seek(SeekFrom::Start(1100)), Ok(1100));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[1100..1108]);
+
+        assert_eq!(file.seek(SeekFrom::End(-4)), Ok(1296));
+        assert_eq!(file.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], &content[1296..]);
+        assert_eq!(file.seek(SeekFrom::End(0)), Ok(1300));
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+
+        // before the start and past the end, the offset is kept
+        assert_eq!(file.seek(SeekFrom::Start(8)), Ok(8));
+        assert_eq!(file.seek(SeekFrom::Current(-9)), Err(FsError::InvalidOffset));
+        assert_eq!(file.seek(SeekFrom::End(-1301)), Err(FsError::InvalidOffset));
+        assert_eq!(file.seek(SeekFrom::End(1)), Err(FsError::InvalidOffset));
+        assert_eq!(file.seek(SeekFrom::Start(1301)), Err(FsError::InvalidOffset));
+        assert_eq!(file.seek(SeekFrom::Current(1293)), Err(FsError::InvalidOffset));
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert_eq!(&buf, &content[8..16]);
+
+        // the end of a cluster-aligned file is past its last cluster
+        let mut file = fs.create_file("/c").unwrap();
+        file.write_all(&content[..1024]).unwrap();
+        let mut file = fs.open_file("/c").unwrap();
+        assert_eq!(file.seek(SeekFrom::End(0)), Ok(1024));
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+        assert_eq!(file.seek(SeekFrom::Start(1020)), Ok(1020));
+        assert_eq!(file.read(&mut buf).unwrap(), 4);
+        assert_eq!(&buf[..4], &content[1020..1024]);
     }
 
-    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
-        // FIXME: open file and return a file handle
-        let parent = self.handle.get_dir_from_name(path)?;
-        let name = path.rsplit(PATH_SEPARATOR).next().unwrap_or("");
-        let entry = self.handle.open_directory(&parent, name)?;
-        if entry.is_directory() {
-            return Err(FsError::NotAFile);
-        }
+    #[test]
+    fn test_fat16_dir_and_lfn() {
+        let (fs, _) = mem_fat16();
 
-        let handle = self.handle.clone();
-        let meta = entry.as_meta();
-        let file = Box::new(File::new(handle, entry));
+        fs.create_dir("/output").unwrap();
+        assert_eq!(fs.create_dir("/OUTPUT"), Err(FsError::AlreadyExists));
 
-        let file_handle = FileHandle::new(meta, file);
+        let mut file = fs.create_file("/output/config.toml.bak").unwrap();
+        file.write_all(b"key = 1").unwrap();
+        fs.create_file("/output/config.toml.old").unwrap();
 
-        Ok(file_handle)
-    }
+        let names: Vec<String> = fs.read_dir("/output").unwrap().map(|m| m.name).collect();
+        assert_eq!(names, [".", "..", "config.toml.bak", "config.toml.old"]);
 
-    fn metadata(&self, path: &str) -> FsResult<Metadata> {
-        // FIXME: read metadata of the file / dir
-        let parent = self.handle.get_dir_from_name(path)?;
-        let name = path.rsplit(PATH_SEPARATOR).next().unwrap_or("");
-        let entry = self.handle.open_directory(&parent, name)?;
-        Ok(entry.as_meta())
-    }
+        // lookup by long name and by the generated alias
+        assert_eq!(fs.metadata("/output/CONFIG.TOML.BAK").unwrap().len, 7);
+        assert_eq!(fs.metadata("/output/CONFIG~1.BAK").unwrap().len, 7);
+        assert!(fs.exists("/output/CONFIG~1.OLD").unwrap());
 
-    fn exists(&self, path: &str) -> FsResult<bool> {
-        // FIXME: check if the file / dir exists
-        let parent = self.handle.get_dir_from_name(path)?;
-        let name = path.rsplit(PATH_SEPARATOR).next().unwrap_or("");
-        let entry = self.handle.open_directory(&parent, name);
-        Ok(entry.is_ok())
+        assert_eq!(fs.remove_dir("/output"), Err(FsError::DirectoryNotEmpty));
+        fs.remove_file("/output/config.toml.bak").unwrap();
+        fs.remove_file("/output/config.toml.old").unwrap();
+        fs.remove_dir("/output").unwrap();
+        assert_eq!(fs.read_dir("/").unwrap().count(), 0);
     }
 }
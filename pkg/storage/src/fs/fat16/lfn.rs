@@ -0,0 +1,279 @@
+//! VFAT Long File Name
+//!
+//! reference: <https://wiki.osdev.org/FAT#Long_File_Names>
+
+use super::*;
+
+/// Max length of a long file name in UTF-16 units
+pub const LFN_MAX_LEN: usize = 255;
+/// UTF-16 units stored in one LFN entry
+pub const LFN_CHARS_PER_ENTRY: usize = 13;
+
+/// Flag of the order byte, marks the last (first on disk) LFN entry
+const LFN_LAST: u8 = 0x40;
+
+/// Byte offsets of the 13 UTF-16 units in an LFN entry
+const LFN_CHAR_OFFSETS: [usize; LFN_CHARS_PER_ENTRY] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// A Long File Name entry
+///
+/// [ order ] [ name 1-5 ] [ 0x0F ] [ 0 ] [ checksum ] [ name 6-11 ] [ 0 ] [ name 12-13 ]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LfnEntry {
+    /// Sequence number, 1 for the entry right before the short entry
+    pub order: u8,
+    /// Whether this is the last entry of the sequence
+    pub last: bool,
+    /// Checksum of the short file name
+    pub checksum: u8,
+    pub chars: [u16; LFN_CHARS_PER_ENTRY],
+}
+
+impl LfnEntry {
+    pub fn parse(data: &[u8]) -> LfnEntry {
+        let mut chars = [0u16; LFN_CHARS_PER_ENTRY];
+        for (c, &offset) in chars.iter_mut().zip(LFN_CHAR_OFFSETS.iter()) {
+            *c = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        }
+
+        LfnEntry {
+            order: data[0] & !LFN_LAST,
+            last: data[0] & LFN_LAST != 0,
+            checksum: data[13],
+            chars,
+        }
+    }
+
+    pub fn as_bytes(&self) -> [u8; DirEntry::LEN] {
+        let mut data = [0u8; DirEntry::LEN];
+        data[0] = if self.last {
+            self.order | LFN_LAST
+        } else {
+            self.order
+        };
+        data[11] = Attributes::LFN.bits();
+        data[13] = self.checksum;
+        for (c, &offset) in self.chars.iter().zip(LFN_CHAR_OFFSETS.iter()) {
+            data[offset..offset + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        data
+    }
+
+    /// Build the LFN entries of `name`, in on-disk order (last entry first)
+    pub fn from_name(name: &str, checksum: u8) -> Vec<LfnEntry> {
+        let mut units: Vec<u16> = name.encode_utf16().collect();
+        let count = units.len().div_ceil(LFN_CHARS_PER_ENTRY);
+
+        // NUL terminated if there is room, then padded with 0xFFFF
+        if units.len() % LFN_CHARS_PER_ENTRY != 0 {
+            units.push(0x0000);
+        }
+        units.resize(count * LFN_CHARS_PER_ENTRY, 0xFFFF);
+
+        (1..=count)
+            .rev()
+            .map(|order| {
+                let start = (order - 1) * LFN_CHARS_PER_ENTRY;
+                LfnEntry {
+                    order: order as u8,
+                    last: order == count,
+                    checksum,
+                    chars: units[start..start + LFN_CHARS_PER_ENTRY].try_into().unwrap(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Assemble the LFN sequence while scanning a directory
+#[derive(Debug, Default)]
+pub struct LfnCollector {
+    units: Vec<u16>,
+    checksum: u8,
+    /// Order of the next expected entry, 0 if no sequence in progress
+    next_order: u8,
+    /// Positions of the collected LFN entries
+    slots: Vec<EntryPos>,
+}
+
+impl LfnCollector {
+    pub fn reset(&mut self) {
+        self.units.clear();
+        self.slots.clear();
+        self.next_order = 0;
+    }
+
+    /// Feed an LFN entry, broken sequences are dropped
+    pub fn push(&mut self, lfn: &LfnEntry, pos: EntryPos) {
+        if lfn.last {
+            self.reset();
+            self.units = vec![0xFFFF; lfn.order as usize * LFN_CHARS_PER_ENTRY];
+            self.checksum = lfn.checksum;
+            self.next_order = lfn.order;
+        }
+
+        if lfn.order == 0 || lfn.order != self.next_order || lfn.checksum != self.checksum {
+            self.reset();
+            return;
+        }
+
+        let start = (lfn.order as usize - 1) * LFN_CHARS_PER_ENTRY;
+        self.units[start..start + LFN_CHARS_PER_ENTRY].copy_from_slice(&lfn.chars);
+        self.slots.push(pos);
+        self.next_order -= 1;
+    }
+
+    /// Finish the sequence with its short entry
+    ///
+    /// return the long name and the positions of LFN entries if the sequence
+    /// is complete and its checksum matches the short name
+    pub fn finish(&mut self, sfn: &ShortFileName) -> Option<(String, Vec<EntryPos>)> {
+        let complete = !self.slots.is_empty() && self.next_order == 0;
+        let matched = complete && self.checksum == sfn.checksum();
+
+        let res = if matched {
+            let len = self
+                .units
+                .iter()
+                .position(|&c| c == 0x0000)
+                .unwrap_or(self.units.len());
+            String::from_utf16(&self.units[..len])
+                .ok()
+                .map(|name| (name, core::mem::take(&mut self.slots)))
+        } else {
+            None
+        };
+
+        self.reset();
+        res
+    }
+}
+
+/// Check if the name can be used as a long file name
+pub fn validate_long_name(name: &str) -> FsResult {
+    if name.is_empty() {
+        return Err(FilenameError::FilenameEmpty.into());
+    }
+    if name.encode_utf16().count() > LFN_MAX_LEN {
+        return Err(FilenameError::NameTooLong.into());
+    }
+    if name
+        .chars()
+        .any(|c| c < '\x20' || "\"*/:<>?\\|".contains(c))
+    {
+        return Err(FilenameError::InvalidCharacter.into());
+    }
+    if name.trim_end_matches(['.', ' ']).is_empty() {
+        return Err(FilenameError::MisplacedPeriod.into());
+    }
+    Ok(())
+}
+
+/// Whether the name is stored as is by an 8.3 entry, no LFN needed
+pub fn is_short_name(name: &str) -> bool {
+    match ShortFileName::parse(name) {
+        Ok(sfn) => format!("{sfn}") == name,
+        Err(_) => false,
+    }
+}
+
+/// Generate the basis of the short alias: `(name, ext)`
+///
+/// e.g. `config.toml.bak` -> (`CONFIGTO`, `BAK`)
+pub fn short_alias_basis(name: &str) -> (String, String) {
+    let convert = |s: &str, max: usize| -> String {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let c = c.to_ascii_uppercase();
+                if c.is_ascii_alphanumeric() || "!#$%&'()-@^_`{}~".contains(c) {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(max)
+            .collect()
+    };
+
+    let name = name.trim_start_matches('.');
+    match name.rsplit_once('.') {
+        Some((base, ext)) => (convert(base, 8), convert(ext, 3)),
+        None => (convert(name, 8), String::new()),
+    }
+}
+
+/// Build the short alias `BASIS~N.EXT`
+pub fn short_alias(basis: &(String, String), n: usize) -> ShortFileName {
+    let tail = format!("~{n}");
+    let keep = basis.0.len().min(8 - tail.len());
+
+    let mut sfn = ShortFileName {
+        name: *b"        ",
+        ext: *b"   ",
+    };
+    let name = format!("{}{}", &basis.0[..keep], tail);
+    sfn.name[..name.len()].copy_from_slice(name.as_bytes());
+    sfn.ext[..basis.1.len()].copy_from_slice(basis.1.as_bytes());
+    sfn
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lfn_checksum() {
+        assert_eq!(ShortFileName::new(b"KERNEL  ELF").checksum(), 0x95);
+        assert_eq!(ShortFileName::new(b"CONFIG~1BAK").checksum(), 0x28);
+    }
+
+    #[test]
+    fn test_short_alias() {
+        let basis = short_alias_basis("config.toml.bak");
+        assert_eq!(basis, (String::from("CONFIGTO"), String::from("BAK")));
+
+        let sfn = short_alias(&basis, 1);
+        assert_eq!(&sfn.name, b"CONFIG~1");
+        assert_eq!(&sfn.ext, b"BAK");
+
+        let sfn = short_alias(&short_alias_basis("a long name"), 12);
+        assert_eq!(&sfn.name, b"ALONG~12");
+        assert_eq!(&sfn.ext, b"   ");
+
+        assert!(is_short_name("KERNEL.ELF"));
+        assert!(!is_short_name("kernel.elf"));
+        assert!(!is_short_name("config.toml.bak"));
+    }
+
+    #[test]
+    fn test_lfn_roundtrip() {
+        let sfn = ShortFileName::new(b"CONFIG~1BAK");
+        let entries = LfnEntry::from_name("config.toml.bak", sfn.checksum());
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].last);
+        assert_eq!(entries[0].order, 2);
+        assert_eq!(entries[1].order, 1);
+
+        let mut collector = LfnCollector::default();
+        for (idx, entry) in entries.iter().enumerate() {
+            let data = entry.as_bytes();
+            assert_eq!(data[11], 0x0f);
+            let pos = EntryPos {
+                sector: 0,
+                offset: idx * DirEntry::LEN,
+            };
+            collector.push(&LfnEntry::parse(&data), pos);
+        }
+
+        let (name, slots) = collector.finish(&sfn).unwrap();
+        assert_eq!(name, "config.toml.bak");
+        assert_eq!(slots.len(), 2);
+
+        // checksum mismatch
+        for entry in entries.iter() {
+            collector.push(entry, EntryPos { sector: 0, offset: 0 });
+        }
+        assert!(collector.finish(&ShortFileName::new(b"CONFIG~2BAK")).is_none());
+    }
+}
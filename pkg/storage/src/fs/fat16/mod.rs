@@ -1,22 +1,26 @@
 pub mod bpb;
+pub mod check;
 pub mod directory;
 pub mod direntry;
 pub mod file;
+pub mod format;
 pub mod impls;
+pub mod lfn;
+pub mod volume;
 
 use crate::*;
 use directory::Directory;
 use direntry::*;
 use file::File;
+use lfn::*;
+use volume::*;
 
 use bpb::Fat16Bpb;
 
 const BLOCK_SIZE: usize = 512;
 
 /// Identifies a Fat16 filesystem on the disk.
-pub struct Fat16 {
-    handle: Fat16Handle,
-}
+pub type Fat16 = FatFs<Fat16Impl>;
 
 impl Fat16 {
     pub fn new(inner: impl BlockDevice<Block512>) -> Self {
@@ -26,8 +30,6 @@ impl Fat16 {
     }
 }
 
-type Fat16Handle = Arc<Fat16Impl>;
-
 /// The Fat16 filesystem.
 ///
 /// The partition is a collection of clusters.
@@ -43,14 +45,6 @@ pub struct Fat16Impl {
     pub first_root_dir_sector: usize,
 }
 
-impl core::fmt::Debug for Fat16 {
-    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("Fat16")
-            .field("bpb", &self.handle.bpb)
-            .finish()
-    }
-}
-
 impl core::fmt::Debug for Fat16Impl {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Fat16Impl").field("bpb", &self.bpb).finish()
@@ -0,0 +1,497 @@
+//! FAT Volume
+//!
+//! Directory and cluster chain operations shared by FAT16 and FAT32,
+//! a volume only needs to provide access to its FAT and data region.
+
+use super::*;
+
+/// A FAT formatted volume
+pub trait FatVolume: core::fmt::Debug + Send + Sync + 'static {
+    /// The underlying block device
+    fn device(&self) -> &dyn BlockDevice<Block512>;
+
+    fn sectors_per_cluster(&self) -> usize;
+
+    /// Number of FAT entries, including the two reserved ones
+    fn cluster_count(&self) -> u32;
+
+    /// First sector of the cluster, `Cluster::ROOT_DIR` for the root directory
+    fn cluster_to_sector(&self, cluster: &Cluster) -> usize;
+
+    /// Read the FAT and get next cluster
+    fn get_next_cluster(&self, cluster: &Cluster) -> Result<Cluster>;
+
+    /// Set the FAT entry of `cluster`
+    fn set_next_cluster(&self, cluster: &Cluster, next: &Cluster) -> Result;
+
+    /// Allocate a free cluster, mark it as the end of chain and zero its data
+    fn alloc_cluster(&self) -> Result<Cluster>;
+
+    /// Size of the fixed root directory region in sectors,
+    /// `None` if the root directory is a cluster chain
+    fn root_dir_sectors(&self) -> Option<usize> {
+        None
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster() * BLOCK_SIZE
+    }
+
+    /// Whether the cluster points into the data region
+    fn is_data_cluster(&self, cluster: &Cluster) -> bool {
+        (2..self.cluster_count()).contains(&cluster.0)
+    }
+
+    fn zero_cluster(&self, cluster: &Cluster) -> Result {
+        let block: Block<512> = Block::default();
+        let first_sector = self.cluster_to_sector(cluster);
+        for sector in first_sector..first_sector + self.sectors_per_cluster() {
+            self.device().write_block(sector, &block)?;
+        }
+        Ok(())
+    }
+
+    /// Allocate a new cluster and link it after `last`
+    fn extend_chain(&self, last: &Cluster) -> Result<Cluster> {
+        let cluster = self.alloc_cluster()?;
+        self.set_next_cluster(last, &cluster)?;
+        Ok(cluster)
+    }
+
+    /// Get the last cluster of the chain starting at `start`
+    fn last_cluster(&self, start: &Cluster) -> Result<Cluster> {
+        let mut current = *start;
+        loop {
+            let next = self.get_next_cluster(&current)?;
+            if !self.is_data_cluster(&next) {
+                return Ok(current);
+            }
+            current = next;
+        }
+    }
+
+    /// Free the whole chain starting at `start`
+    fn free_chain(&self, start: &Cluster) -> Result {
+        let mut current = *start;
+        while self.is_data_cluster(&current) {
+            let next = self.get_next_cluster(&current)?;
+            self.set_next_cluster(&current, &Cluster::EMPTY)?;
+            current = next;
+        }
+        Ok(())
+    }
+
+    /// Visit the sectors of a directory until `f` returns `Some`
+    fn find_in_dir<T>(
+        &self,
+        dir: &Directory,
+        mut f: impl FnMut(usize, &Block512) -> Option<T>,
+    ) -> Result<Option<T>> {
+        let mut current_cluster = dir.cluster;
+        let mut block: Block<512> = Block::default();
+
+        loop {
+            let fixed_root = match current_cluster {
+                Cluster::ROOT_DIR => self.root_dir_sectors(),
+                _ => None,
+            };
+            let sectors = fixed_root.unwrap_or(self.sectors_per_cluster());
+            let first_sector = self.cluster_to_sector(&current_cluster);
+
+            for sector in first_sector..first_sector + sectors {
+                self.device().read_block(sector, &mut block)?;
+                if let Some(res) = f(sector, &block) {
+                    return Ok(Some(res));
+                }
+            }
+
+            // 根目录区域大小固定，没有簇链
+            if fixed_root.is_some() {
+                return Ok(None);
+            }
+
+            current_cluster = self.get_next_cluster(&current_cluster)?;
+            if !self.is_data_cluster(&current_cluster) {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Visit the entries of a directory, with long names assembled
+    ///
+    /// `f` gets the entry, its location and the locations of its LFN entries
+    fn scan_dir<T>(
+        &self,
+        dir: &Directory,
+        mut f: impl FnMut(&DirEntry, EntryPos, &[EntryPos]) -> Option<T>,
+    ) -> Result<Option<T>> {
+        let mut lfn = LfnCollector::default();
+
+        let res = self.find_in_dir(dir, |sector, block| {
+            for offset in (0..BLOCK_SIZE).step_by(DirEntry::LEN) {
+                let data = &block[offset..offset + DirEntry::LEN];
+                let pos = EntryPos { sector, offset };
+                let mut entry = match DirEntry::parse(data) {
+                    Ok(entry) => entry,
+                    Err(_) => continue,
+                };
+
+                if entry.filename.is_eod() {
+                    // stop scanning
+                    return Some(None);
+                }
+                if !entry.is_valid() {
+                    lfn.reset();
+                    continue;
+                }
+                if entry.is_long_name() {
+                    lfn.push(&LfnEntry::parse(data), pos);
+                    continue;
+                }
+
+                let slots = match lfn.finish(&entry.filename) {
+                    Some((name, slots)) => {
+                        entry.long_name = Some(name);
+                        slots
+                    }
+                    None => Vec::new(),
+                };
+
+                if let Some(res) = f(&entry, pos, &slots) {
+                    return Some(Some(res));
+                }
+            }
+            None
+        })?;
+
+        Ok(res.flatten())
+    }
+
+    /// Read all entries of the directory
+    fn traverse_cluster_chain(&self, dir: &Directory) -> Result<Vec<Metadata>> {
+        let mut entries = Vec::new();
+
+        self.scan_dir(dir, |entry, _, _| {
+            entries.push(entry.as_meta());
+            None::<()>
+        })?;
+
+        Ok(entries)
+    }
+
+    /// Find the entry and its location by long or short name
+    fn find_entry(&self, dir: &Directory, name: &str) -> Result<(DirEntry, EntryPos)> {
+        let short_name = match name {
+            "." => Some(ShortFileName::DOT),
+            ".." => Some(ShortFileName::DOT_DOT),
+            _ => ShortFileName::parse(name).ok(),
+        };
+
+        self.scan_dir(dir, |entry, pos, _| {
+            let long_matched = entry
+                .long_name
+                .as_ref()
+                .is_some_and(|long| long.to_lowercase() == name.to_lowercase());
+            let short_matched = short_name
+                .as_ref()
+                .is_some_and(|short| entry.filename.matches(short));
+
+            (long_matched || short_matched).then(|| (entry.clone(), pos))
+        })?
+        .ok_or(FsError::FileNotFound)
+    }
+
+    fn open_directory(&self, dir: &Directory, name: &str) -> Result<DirEntry> {
+        Ok(self.find_entry(dir, name)?.0)
+    }
+
+    /// Walk the path from the root directory
+    fn get_dir_from_name(&self, path: &str) -> Result<Directory> {
+        let mut current = Directory::root();
+
+        for dir in path.split(PATH_SEPARATOR) {
+            if dir.is_empty() {
+                continue;
+            }
+
+            let entry = self.open_directory(&current, dir)?;
+
+            if !entry.is_directory() {
+                return Err(FsError::NotADirectory);
+            }
+            current = Directory::from_entry(entry);
+        }
+
+        Ok(current)
+    }
+
+    /// Find the entry and its location by full path
+    fn lookup(&self, path: &str) -> Result<(DirEntry, EntryPos)> {
+        let (parent, name) = split_path(path);
+        let dir = self.get_dir_from_name(parent)?;
+        self.find_entry(&dir, name)
+    }
+
+    /// Write the entry back to its location
+    fn write_entry(&self, pos: &EntryPos, entry: &DirEntry) -> Result {
+        let mut block: Block<512> = Block::default();
+        self.device().read_block(pos.sector, &mut block)?;
+        block[pos.offset..pos.offset + DirEntry::LEN].copy_from_slice(&entry.as_bytes());
+        self.device().write_block(pos.sector, &block)
+    }
+
+    /// Mark the entry at `pos` and its LFN entries as deleted
+    fn remove_entry(&self, dir: &Directory, pos: &EntryPos) -> Result {
+        let slots = self
+            .scan_dir(dir, |_, entry_pos, slots| {
+                (entry_pos == *pos).then(|| slots.to_vec())
+            })?
+            .ok_or(FsError::FileNotFound)?;
+
+        let mut block: Block<512> = Block::default();
+        for slot in slots.iter().chain(core::iter::once(pos)) {
+            self.device().read_block(slot.sector, &mut block)?;
+            block[slot.offset] = ShortFileName::DELETED;
+            self.device().write_block(slot.sector, &block)?;
+        }
+        Ok(())
+    }
+
+    /// Find `count` consecutive unused entries, extend the directory if there is no room
+    fn find_free_entries(&self, dir: &Directory, count: usize) -> Result<Vec<EntryPos>> {
+        let mut run = Vec::with_capacity(count);
+
+        let found = self.find_in_dir(dir, |sector, block| {
+            for offset in (0..BLOCK_SIZE).step_by(DirEntry::LEN) {
+                if block[offset] == 0x00 || block[offset] == ShortFileName::DELETED {
+                    run.push(EntryPos { sector, offset });
+                    if run.len() == count {
+                        return Some(());
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+            None
+        })?;
+
+        if found.is_some() {
+            return Ok(run);
+        }
+
+        // 根目录区域无法扩展
+        if dir.cluster == Cluster::ROOT_DIR && self.root_dir_sectors().is_some() {
+            return Err(FsError::WriteZero);
+        }
+
+        // unused entries at the end of the directory are kept in the run
+        let mut last = self.last_cluster(&dir.cluster)?;
+        while run.len() < count {
+            last = self.extend_chain(&last)?;
+            let first_sector = self.cluster_to_sector(&last);
+            for sector in first_sector..first_sector + self.sectors_per_cluster() {
+                for offset in (0..BLOCK_SIZE).step_by(DirEntry::LEN) {
+                    run.push(EntryPos { sector, offset });
+                }
+            }
+        }
+
+        run.truncate(count);
+        Ok(run)
+    }
+
+    /// Create a new entry in the directory, with LFN entries if needed
+    fn add_entry(
+        &self,
+        dir: &Directory,
+        name: &str,
+        attributes: Attributes,
+        cluster: Cluster,
+    ) -> Result<(DirEntry, EntryPos)> {
+        if is_short_name(name) {
+            let entry = DirEntry::new(ShortFileName::parse(name)?, attributes, cluster);
+            let pos = self.find_free_entries(dir, 1)?[0];
+            self.write_entry(&pos, &entry)?;
+            return Ok((entry, pos));
+        }
+
+        validate_long_name(name)?;
+
+        // pick an unused alias `BASIS~N.EXT`
+        let mut short_names = Vec::new();
+        self.scan_dir(dir, |entry, _, _| {
+            short_names.push(entry.filename.clone());
+            None::<()>
+        })?;
+        let basis = short_alias_basis(name);
+        let filename = (1..1_000_000)
+            .map(|n| short_alias(&basis, n))
+            .find(|sfn| !short_names.iter().any(|used| used.matches(sfn)))
+            .ok_or(FsError::AlreadyExists)?;
+
+        let lfn_entries = LfnEntry::from_name(name, filename.checksum());
+        let slots = self.find_free_entries(dir, lfn_entries.len() + 1)?;
+
+        let mut block: Block<512> = Block::default();
+        for (lfn, slot) in lfn_entries.iter().zip(slots.iter()) {
+            self.device().read_block(slot.sector, &mut block)?;
+            block[slot.offset..slot.offset + DirEntry::LEN].copy_from_slice(&lfn.as_bytes());
+            self.device().write_block(slot.sector, &block)?;
+        }
+
+        let mut entry = DirEntry::new(filename, attributes, cluster);
+        let pos = slots[lfn_entries.len()];
+        self.write_entry(&pos, &entry)?;
+
+        entry.long_name = Some(String::from(name));
+        Ok((entry, pos))
+    }
+
+    /// Whether the directory has no entries other than `.` and `..`
+    fn is_empty_dir(&self, dir: &Directory) -> Result<bool> {
+        let found = self.scan_dir(dir, |entry, _, _| (!entry.is_dot()).then_some(()))?;
+        Ok(found.is_none())
+    }
+}
+
+/// A FAT filesystem, `Fat16` or `Fat32`
+pub struct FatFs<V: FatVolume> {
+    pub(crate) handle: Arc<V>,
+}
+
+impl<V: FatVolume> FatFs<V> {
+    fn file_handle(&self, entry: DirEntry, pos: EntryPos) -> FileHandle {
+        let meta = entry.as_meta();
+        let file = Box::new(File::new(self.handle.clone(), entry, pos));
+        FileHandle::new(meta, file)
+    }
+}
+
+impl<V: FatVolume> core::fmt::Debug for FatFs<V> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.handle, f)
+    }
+}
+
+impl<V: FatVolume> FileSystem for FatFs<V> {
+    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+        let dir = self.handle.get_dir_from_name(path)?;
+
+        let entries = self.handle.traverse_cluster_chain(&dir)?;
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
+        let (entry, pos) = self.handle.lookup(path)?;
+        if entry.is_directory() {
+            return Err(FsError::NotAFile);
+        }
+
+        Ok(self.file_handle(entry, pos))
+    }
+
+    fn metadata(&self, path: &str) -> FsResult<Metadata> {
+        let (entry, _) = self.handle.lookup(path)?;
+        Ok(entry.as_meta())
+    }
+
+    fn exists(&self, path: &str) -> FsResult<bool> {
+        match self.handle.lookup(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::FileNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn create_file(&self, path: &str) -> FsResult<FileHandle> {
+        let (parent, name) = split_path(path);
+        let dir = self.handle.get_dir_from_name(parent)?;
+
+        let (entry, pos) = match self.handle.find_entry(&dir, name) {
+            Ok((mut entry, pos)) => {
+                if entry.is_directory() {
+                    return Err(FsError::NotAFile);
+                }
+                // truncate the existing file
+                self.handle.free_chain(&entry.cluster)?;
+                entry.cluster = Cluster::EMPTY;
+                entry.size = 0;
+                entry.modified_time = now();
+                self.handle.write_entry(&pos, &entry)?;
+                (entry, pos)
+            }
+            Err(FsError::FileNotFound) => {
+                self.handle
+                    .add_entry(&dir, name, Attributes::ARCHIVE, Cluster::EMPTY)?
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(self.file_handle(entry, pos))
+    }
+
+    fn append_file(&self, path: &str) -> FsResult<FileHandle> {
+        let mut handle = self.open_file(path)?;
+        handle.seek(SeekFrom::End(0))?;
+        Ok(handle)
+    }
+
+    fn remove_file(&self, path: &str) -> FsResult {
+        let (parent, name) = split_path(path);
+        let dir = self.handle.get_dir_from_name(parent)?;
+        let (entry, pos) = self.handle.find_entry(&dir, name)?;
+        if entry.is_directory() {
+            return Err(FsError::NotAFile);
+        }
+
+        self.handle.free_chain(&entry.cluster)?;
+        self.handle.remove_entry(&dir, &pos)
+    }
+
+    fn create_dir(&self, path: &str) -> FsResult {
+        let (parent, name) = split_path(path);
+        let dir = self.handle.get_dir_from_name(parent)?;
+
+        match self.handle.find_entry(&dir, name) {
+            Ok(_) => return Err(FsError::AlreadyExists),
+            Err(FsError::FileNotFound) => {}
+            Err(e) => return Err(e),
+        }
+
+        let cluster = self.handle.alloc_cluster()?;
+
+        // `.` 指向自身，`..` 指向父目录（根目录为 0）
+        let parent_cluster = match dir.cluster {
+            Cluster::ROOT_DIR => Cluster::EMPTY,
+            cluster => cluster,
+        };
+        let dot = DirEntry::new(ShortFileName::DOT, Attributes::DIRECTORY, cluster);
+        let dot_dot = DirEntry::new(ShortFileName::DOT_DOT, Attributes::DIRECTORY, parent_cluster);
+
+        let sector = self.handle.cluster_to_sector(&cluster);
+        self.handle.write_entry(&EntryPos { sector, offset: 0 }, &dot)?;
+        self.handle.write_entry(&EntryPos { sector, offset: DirEntry::LEN }, &dot_dot)?;
+
+        if let Err(e) = self.handle.add_entry(&dir, name, Attributes::DIRECTORY, cluster) {
+            self.handle.free_chain(&cluster)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> FsResult {
+        let (parent, name) = split_path(path);
+        let dir = self.handle.get_dir_from_name(parent)?;
+        let (entry, pos) = self.handle.find_entry(&dir, name)?;
+        if !entry.is_directory() {
+            return Err(FsError::NotADirectory);
+        }
+        if entry.is_dot() || !self.handle.is_empty_dir(&Directory::from_entry(entry.clone()))? {
+            return Err(FsError::DirectoryNotEmpty);
+        }
+
+        self.handle.free_chain(&entry.cluster)?;
+        self.handle.remove_entry(&dir, &pos)
+    }
+}
@@ -0,0 +1,157 @@
+//! Fat32 BIOS Parameter Block
+//!
+//! reference:
+//! - <https://en.wikipedia.org/wiki/Design_of_the_FAT_file_system#FAT32_Extended_BIOS_Parameter_Block>
+//! - <https://wiki.osdev.org/FAT#FAT_32>
+
+use crate::*;
+
+/// Represents a FAT32 Boot Parameter Block.
+///
+/// The common BPB is the same as FAT16, followed by the FAT32 extended BPB.
+pub struct Fat32Bpb {
+    data: [u8; 512],
+}
+
+impl Fat32Bpb {
+    /// Attempt to parse a Boot Parameter Block from a 512 byte sector.
+    pub fn new(data: &[u8]) -> FsResult<Fat32Bpb> {
+        let data = data.try_into().map_err(|_| FsError::InvalidOperation)?;
+        let bpb = Fat32Bpb { data };
+
+        if bpb.trail() != 0xAA55 || bpb.bytes_per_sector() != 512 || bpb.sectors_per_cluster() == 0 {
+            return Err(FsError::InvalidOperation);
+        }
+
+        Ok(bpb)
+    }
+
+    pub fn total_sectors(&self) -> u32 {
+        if self.total_sectors_16() == 0 {
+            self.total_sectors_32()
+        } else {
+            self.total_sectors_16() as u32
+        }
+    }
+
+    /// Size of one FAT in sectors
+    pub fn fat_size(&self) -> u32 {
+        if self.sectors_per_fat_16() == 0 {
+            self.sectors_per_fat_32()
+        } else {
+            self.sectors_per_fat_16() as u32
+        }
+    }
+
+    /// FAT mirroring is disabled, only the active FAT is used
+    pub fn mirroring_disabled(&self) -> bool {
+        self.ext_flags() & 0x80 != 0
+    }
+
+    /// Index of the active FAT when mirroring is disabled
+    pub fn active_fat(&self) -> u8 {
+        (self.ext_flags() & 0x0f) as u8
+    }
+
+    define_field!([u8; 8], 0x03, oem_name);
+    define_field!(u16, 0x0b, bytes_per_sector);
+    define_field!(u8, 0x0d, sectors_per_cluster);
+    define_field!(u16, 0x0e, reserved_sector_count);
+    define_field!(u8, 0x10, fat_count);
+    define_field!(u16, 0x11, root_entries_count);
+    define_field!(u16, 0x13, total_sectors_16);
+    define_field!(u8, 0x15, media_descriptor);
+    define_field!(u16, 0x16, sectors_per_fat_16);
+    define_field!(u16, 0x18, sectors_per_track);
+    define_field!(u16, 0x1a, track_count);
+    define_field!(u32, 0x1c, hidden_sectors);
+    define_field!(u32, 0x20, total_sectors_32);
+
+    // FAT32 Extended BPB
+    define_field!(u32, 0x24, sectors_per_fat_32);
+    define_field!(u16, 0x28, ext_flags);
+    define_field!(u16, 0x2a, fs_version);
+    define_field!(u32, 0x2c, root_cluster);
+    define_field!(u16, 0x30, fs_info_sector);
+    define_field!(u16, 0x32, backup_boot_sector);
+    define_field!(u8, 0x40, drive_number);
+    define_field!(u8, 0x42, boot_signature);
+    define_field!(u32, 0x43, volume_id);
+    define_field!([u8; 11], 0x47, volume_label);
+    define_field!([u8; 8], 0x52, system_identifier);
+    define_field!(u16, 0x1fe, trail);
+}
+
+impl core::fmt::Debug for Fat32Bpb {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Fat32 BPB")
+            .field("OEM Name", &self.oem_name_str())
+            .field("Bytes per Sector", &self.bytes_per_sector())
+            .field("Sectors per Cluster", &self.sectors_per_cluster())
+            .field("Reserved Sector Count", &self.reserved_sector_count())
+            .field("FAT Count", &self.fat_count())
+            .field("Total Sectors", &self.total_sectors())
+            .field("Media Descriptor", &self.media_descriptor())
+            .field("Sectors per FAT", &self.fat_size())
+            .field("Hidden Sectors", &self.hidden_sectors())
+            .field("Ext Flags", &self.ext_flags())
+            .field("FS Version", &self.fs_version())
+            .field("Root Cluster", &self.root_cluster())
+            .field("FSInfo Sector", &self.fs_info_sector())
+            .field("Backup Boot Sector", &self.backup_boot_sector())
+            .field("Drive Number", &self.drive_number())
+            .field("Boot Signature", &self.boot_signature())
+            .field("Volume ID", &self.volume_id())
+            .field("Volume Label", &self.volume_label_str())
+            .field("System Identifier", &self.system_identifier_str())
+            .field("Trail", &self.trail())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Boot sector of a 1 GiB FAT32 volume
+    pub(crate) fn fat32_bpb_data() -> Vec<u8> {
+        const DATA: [u8; 96] = hex_literal::hex!(
+            "EB 58 90 6D 6B 66 73 2E 66 61 74 00 02 08 20 00
+        02 00 00 00 00 F8 00 00 20 00 40 00 00 00 00 00
+        00 00 20 00 F8 07 00 00 00 00 00 00 02 00 00 00
+        01 00 06 00 00 00 00 00 00 00 00 00 00 00 00 00
+        80 00 29 78 56 34 12 4E 4F 20 4E 41 4D 45 20 20
+        20 20 46 41 54 33 32 20 20 20 0E 1F BE 77 7C AC"
+        );
+
+        let mut bpb_data = Vec::with_capacity(512);
+        bpb_data.extend_from_slice(&DATA);
+        bpb_data.resize(510, 0u8);
+        bpb_data.extend_from_slice(&[0x55, 0xAA]);
+        bpb_data
+    }
+
+    #[test]
+    fn test_fat32_bpb() {
+        let bpb = Fat32Bpb::new(&fat32_bpb_data()).unwrap();
+
+        assert_eq!(bpb.oem_name(), b"mkfs.fat");
+        assert_eq!(bpb.bytes_per_sector(), 512);
+        assert_eq!(bpb.sectors_per_cluster(), 8);
+        assert_eq!(bpb.reserved_sector_count(), 32);
+        assert_eq!(bpb.fat_count(), 2);
+        assert_eq!(bpb.root_entries_count(), 0);
+        assert_eq!(bpb.total_sectors(), 0x200000);
+        assert_eq!(bpb.fat_size(), 0x7f8);
+        assert!(!bpb.mirroring_disabled());
+        assert_eq!(bpb.root_cluster(), 2);
+        assert_eq!(bpb.fs_info_sector(), 1);
+        assert_eq!(bpb.backup_boot_sector(), 6);
+        assert_eq!(bpb.boot_signature(), 0x29);
+        assert_eq!(bpb.volume_id(), 0x12345678);
+        assert_eq!(bpb.volume_label(), b"NO NAME    ");
+        assert_eq!(bpb.system_identifier(), b"FAT32   ");
+
+        println!("{bpb:#?}");
+    }
+}
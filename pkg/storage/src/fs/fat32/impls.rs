@@ -0,0 +1,327 @@
+use super::*;
+
+/// Only the low 28 bits of a FAT32 entry are used
+const FAT32_MASK: u32 = 0x0FFF_FFFF;
+const FAT32_BAD: u32 = 0x0FFF_FFF7;
+const FAT32_EOC: u32 = 0x0FFF_FFFF;
+
+const FSINFO_LEAD_SIG: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIG: u32 = 0x6141_7272;
+
+impl FsInfo {
+    pub const UNKNOWN: u32 = 0xFFFF_FFFF;
+
+    fn unknown() -> FsInfo {
+        FsInfo {
+            sector: None,
+            free_count: Self::UNKNOWN,
+            next_free: Self::UNKNOWN,
+        }
+    }
+
+    fn parse(sector: usize, data: &[u8]) -> FsInfo {
+        let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+        if read_u32(0) != FSINFO_LEAD_SIG || read_u32(0x1e4) != FSINFO_STRUCT_SIG {
+            return FsInfo::unknown();
+        }
+
+        FsInfo {
+            sector: Some(sector),
+            free_count: read_u32(0x1e8),
+            next_free: read_u32(0x1ec),
+        }
+    }
+}
+
+impl Fat32Impl {
+    pub fn new(inner: impl BlockDevice<Block512>) -> Self {
+        let mut block = Block::default();
+
+        inner.read_block(0, &mut block).unwrap();
+        let bpb = Fat32Bpb::new(block.as_ref()).unwrap();
+
+        trace!("Loading Fat32 Volume: {bpb:#?}");
+
+        // FirstDataSector = BPB_ResvdSecCnt + (BPB_NumFATs * FATSz), no root dir region
+        let fat_start = bpb.reserved_sector_count() as usize;
+        let first_data_sector = fat_start + bpb.fat_count() as usize * bpb.fat_size() as usize;
+
+        let fs_info_sector = bpb.fs_info_sector() as usize;
+        let fs_info = if fs_info_sector != 0 && fs_info_sector < fat_start {
+            inner.read_block(fs_info_sector, &mut block).unwrap();
+            FsInfo::parse(fs_info_sector, block.as_ref())
+        } else {
+            FsInfo::unknown()
+        };
+
+        Self {
+            bpb,
+            inner: Box::new(inner),
+            fat_start,
+            first_data_sector,
+            fs_info: Mutex::new(fs_info),
+        }
+    }
+
+    pub fn fs_info(&self) -> FsInfo {
+        *self.fs_info.lock()
+    }
+
+    /// The root directory is referred as `Cluster::ROOT_DIR`
+    fn resolve(&self, cluster: &Cluster) -> Cluster {
+        match *cluster {
+            Cluster::ROOT_DIR => Cluster(self.bpb.root_cluster()),
+            cluster => cluster,
+        }
+    }
+
+    /// Sector offset in a FAT and byte offset in the sector of the entry
+    fn fat_entry_pos(&self, cluster: &Cluster) -> (usize, usize) {
+        let fat_offset = self.resolve(cluster).0 as usize * 4;
+        (fat_offset / BLOCK_SIZE, fat_offset % BLOCK_SIZE)
+    }
+
+    /// The FAT read by the driver, 0 unless mirroring is disabled
+    fn active_fat_start(&self) -> usize {
+        let active = if self.bpb.mirroring_disabled() {
+            self.bpb.active_fat() as usize
+        } else {
+            0
+        };
+        self.fat_start + active * self.bpb.fat_size() as usize
+    }
+
+    /// Update the FSInfo after a cluster is allocated or freed
+    fn update_fs_info(&self, allocated: Option<u32>, freed: bool) -> Result {
+        let mut fs_info = self.fs_info.lock();
+
+        if let Some(cluster) = allocated {
+            if fs_info.free_count != FsInfo::UNKNOWN {
+                fs_info.free_count = fs_info.free_count.saturating_sub(1);
+            }
+            fs_info.next_free = cluster + 1;
+        }
+        if freed && fs_info.free_count != FsInfo::UNKNOWN {
+            fs_info.free_count += 1;
+        }
+
+        let sector = match fs_info.sector {
+            Some(sector) => sector,
+            None => return Ok(()),
+        };
+
+        let mut block: Block<512> = Block::default();
+        self.inner.read_block(sector, &mut block)?;
+        block[0x1e8..0x1ec].copy_from_slice(&fs_info.free_count.to_le_bytes());
+        block[0x1ec..0x1f0].copy_from_slice(&fs_info.next_free.to_le_bytes());
+        self.inner.write_block(sector, &block)
+    }
+}
+
+impl FatVolume for Fat32Impl {
+    fn device(&self) -> &dyn BlockDevice<Block512> {
+        self.inner.as_ref()
+    }
+
+    fn sectors_per_cluster(&self) -> usize {
+        self.bpb.sectors_per_cluster() as usize
+    }
+
+    fn cluster_count(&self) -> u32 {
+        let data_sectors = self.bpb.total_sectors() as usize - self.first_data_sector;
+        let count = data_sectors / self.bpb.sectors_per_cluster() as usize + 2;
+        count.min(FAT32_BAD as usize) as u32
+    }
+
+    fn cluster_to_sector(&self, cluster: &Cluster) -> usize {
+        // FirstSectorofCluster = ((N – 2) * BPB_SecPerClus) + FirstDataSector;
+        let Cluster(c) = self.resolve(cluster);
+        (c as usize - 2) * self.bpb.sectors_per_cluster() as usize + self.first_data_sector
+    }
+
+    fn get_next_cluster(&self, cluster: &Cluster) -> Result<Cluster> {
+        let (sector, offset) = self.fat_entry_pos(cluster);
+
+        let mut block: Block<512> = Block::default();
+        self.inner.read_block(self.active_fat_start() + sector, &mut block)?;
+        let next = u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap()) & FAT32_MASK;
+
+        match next {
+            0 => Ok(Cluster::EMPTY),
+            FAT32_BAD => Err(FsError::BadCluster),
+            c if (2..FAT32_BAD).contains(&c) => Ok(Cluster(c)),
+            c if c >= 0x0FFF_FFF8 => Ok(Cluster::END_OF_FILE),
+            _ => Ok(Cluster::INVALID),
+        }
+    }
+
+    // 写入所有 FAT 副本（禁用镜像时只写活动 FAT），保留高 4 位
+    fn set_next_cluster(&self, cluster: &Cluster, next: &Cluster) -> Result {
+        let value = match *next {
+            Cluster::END_OF_FILE => FAT32_EOC,
+            Cluster::BAD => FAT32_BAD,
+            next => self.resolve(&next).0 & FAT32_MASK,
+        };
+
+        let (sector, offset) = self.fat_entry_pos(cluster);
+        let fats = if self.bpb.mirroring_disabled() {
+            self.bpb.active_fat() as usize..self.bpb.active_fat() as usize + 1
+        } else {
+            0..self.bpb.fat_count() as usize
+        };
+
+        let mut old = 0;
+        let mut block: Block<512> = Block::default();
+        for fat in fats {
+            let sector = self.fat_start + fat * self.bpb.fat_size() as usize + sector;
+            self.inner.read_block(sector, &mut block)?;
+            let entry = u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+            old = entry & FAT32_MASK;
+            let entry = (entry & !FAT32_MASK) | value;
+            block[offset..offset + 4].copy_from_slice(&entry.to_le_bytes());
+            self.inner.write_block(sector, &block)?;
+        }
+
+        if old != 0 && value == 0 {
+            self.update_fs_info(None, true)?;
+        }
+        Ok(())
+    }
+
+    fn alloc_cluster(&self) -> Result<Cluster> {
+        let count = self.cluster_count();
+        let hint = match self.fs_info.lock().next_free {
+            next if (2..count).contains(&next) => next,
+            _ => 2,
+        };
+
+        let fat_start = self.active_fat_start();
+        let mut block: Block<512> = Block::default();
+        let mut loaded = None;
+
+        // search from the hint, then wrap around
+        for c in (hint..count).chain(2..hint) {
+            let (sector, offset) = self.fat_entry_pos(&Cluster(c));
+            if loaded != Some(sector) {
+                self.inner.read_block(fat_start + sector, &mut block)?;
+                loaded = Some(sector);
+            }
+
+            let entry = u32::from_le_bytes(block[offset..offset + 4].try_into().unwrap());
+            if entry & FAT32_MASK == 0 {
+                let cluster = Cluster(c);
+                self.set_next_cluster(&cluster, &Cluster::END_OF_FILE)?;
+                self.update_fs_info(Some(c), false)?;
+                self.zero_cluster(&cluster)?;
+                return Ok(cluster);
+            }
+        }
+
+        Err(FsError::WriteZero)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::MemDisk;
+
+    const SECTORS: usize = 8192;
+    const FAT_SIZE: usize = 64;
+    const RESERVED: usize = 32;
+
+    /// 4 MiB volume: 32 reserved with FSInfo at 1, 2 FATs, root at cluster 2
+    fn mem_fat32() -> (Fat32, Arc<Mutex<Vec<u8>>>) {
+        let mut data = vec![0u8; SECTORS * BLOCK_SIZE];
+        data[0..3].copy_from_slice(&[0xEB, 0x58, 0x90]);
+        data[3..11].copy_from_slice(b"MSWIN4.1");
+        data[0x0b..0x0d].copy_from_slice(&512u16.to_le_bytes());
+        data[0x0d] = 1;
+        data[0x0e..0x10].copy_from_slice(&(RESERVED as u16).to_le_bytes());
+        data[0x10] = 2;
+        data[0x15] = 0xF8;
+        data[0x20..0x24].copy_from_slice(&(SECTORS as u32).to_le_bytes());
+        data[0x24..0x28].copy_from_slice(&(FAT_SIZE as u32).to_le_bytes());
+        data[0x2c..0x30].copy_from_slice(&2u32.to_le_bytes());
+        data[0x30..0x32].copy_from_slice(&1u16.to_le_bytes());
+        data[0x1fe..0x200].copy_from_slice(&[0x55, 0xAA]);
+
+        let data_clusters = (SECTORS - RESERVED - 2 * FAT_SIZE) as u32;
+        let fs_info = BLOCK_SIZE;
+        data[fs_info..fs_info + 4].copy_from_slice(&FSINFO_LEAD_SIG.to_le_bytes());
+        data[fs_info + 0x1e4..fs_info + 0x1e8].copy_from_slice(&FSINFO_STRUCT_SIG.to_le_bytes());
+        data[fs_info + 0x1e8..fs_info + 0x1ec].copy_from_slice(&(data_clusters - 1).to_le_bytes());
+        data[fs_info + 0x1ec..fs_info + 0x1f0].copy_from_slice(&3u32.to_le_bytes());
+
+        for fat in 0..2 {
+            let start = (RESERVED + fat * FAT_SIZE) * BLOCK_SIZE;
+            data[start..start + 12].copy_from_slice(&hex_literal::hex!(
+                "F8 FF FF 0F FF FF FF 0F FF FF FF 0F"
+            ));
+        }
+
+        let data = Arc::new(Mutex::new(data));
+        (Fat32::new(MemDisk(data.clone())), data)
+    }
+
+    #[test]
+    fn test_fat32_detect() {
+        let mut data = fat32::bpb::tests::fat32_bpb_data();
+        data.resize(BLOCK_SIZE, 0);
+        let disk = MemDisk(Arc::new(Mutex::new(data)));
+        assert_eq!(FatType::detect(&disk), Ok(FatType::Fat32));
+    }
+
+    #[test]
+    fn test_fat32_root_chain() {
+        let (fs, disk) = mem_fat32();
+        let free = fs.handle.fs_info().free_count;
+
+        // 16 entries per cluster, the root directory has to grow
+        for i in 0..20 {
+            let mut file = fs.create_file(&format!("/file{i}.txt")).unwrap();
+            file.write_all(format!("content of {i}").as_bytes()).unwrap();
+        }
+
+        let names: Vec<String> = fs.read_dir("/").unwrap().map(|m| m.name).collect();
+        assert_eq!(names.len(), 20);
+        assert_eq!(names[19], "file19.txt");
+        assert_ne!(fs.handle.get_next_cluster(&Cluster::ROOT_DIR).unwrap(), Cluster::END_OF_FILE);
+
+        let mut file = fs.open_file("/FILE13.TXT").unwrap();
+        let mut buf = [0u8; 32];
+        let len = file.read(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"content of 13");
+
+        // 20 files with one cluster each, plus the extended root directory
+        assert!(fs.handle.fs_info().free_count < free - 20);
+
+        let data = disk.lock();
+        let fat1 = &data[RESERVED * BLOCK_SIZE..(RESERVED + FAT_SIZE) * BLOCK_SIZE];
+        let fat2 = &data[(RESERVED + FAT_SIZE) * BLOCK_SIZE..(RESERVED + 2 * FAT_SIZE) * BLOCK_SIZE];
+        assert_eq!(fat1, fat2);
+    }
+
+    #[test]
+    fn test_fat32_write_and_remove() {
+        let (fs, disk) = mem_fat32();
+
+        // keep the reserved high 4 bits of FAT entries
+        disk.lock()[RESERVED * BLOCK_SIZE + 3 * 4 + 3] = 0xF0;
+
+        fs.create_dir("/logs").unwrap();
+        let content = vec![0x5a; 1500];
+        let mut file = fs.create_file("/logs/a long log name.txt").unwrap();
+        file.write_all(&content).unwrap();
+        assert_eq!(fs.metadata("/logs/a long log name.txt").unwrap().len, 1500);
+
+        let free = fs.handle.fs_info().free_count;
+        fs.remove_file("/logs/a long log name.txt").unwrap();
+        assert_eq!(fs.handle.fs_info().free_count, free + 3);
+        fs.remove_dir("/logs").unwrap();
+
+        let data = disk.lock();
+        assert_eq!(data[RESERVED * BLOCK_SIZE + 3 * 4 + 3], 0xF0);
+    }
+}
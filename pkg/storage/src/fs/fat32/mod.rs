@@ -0,0 +1,56 @@
+pub mod bpb;
+pub mod impls;
+
+use crate::fat16::direntry::*;
+use crate::fat16::volume::*;
+use crate::*;
+use spin::Mutex;
+
+use bpb::Fat32Bpb;
+
+const BLOCK_SIZE: usize = 512;
+
+/// Identifies a Fat32 filesystem on the disk.
+pub type Fat32 = FatFs<Fat32Impl>;
+
+impl Fat32 {
+    pub fn new(inner: impl BlockDevice<Block512>) -> Self {
+        Self {
+            handle: Arc::new(Fat32Impl::new(inner)),
+        }
+    }
+}
+
+/// The Fat32 filesystem.
+///
+/// The root directory is a cluster chain starting at `root_cluster`,
+/// FSInfo sector keeps hints of free clusters.
+///
+/// [ BPB ] [ FSInfo ] [ Reserved ] [ FAT ] [ Data ]
+pub struct Fat32Impl {
+    pub(crate) inner: Box<dyn BlockDevice<Block512>>,
+    pub bpb: Fat32Bpb,
+    pub fat_start: usize,
+    pub first_data_sector: usize,
+    fs_info: Mutex<FsInfo>,
+}
+
+/// Hints from the FSInfo sector, `FsInfo::UNKNOWN` if not available
+#[derive(Debug, Clone, Copy)]
+pub struct FsInfo {
+    /// The FSInfo sector, `None` if the volume has no valid FSInfo
+    pub sector: Option<usize>,
+    /// Number of free clusters
+    pub free_count: u32,
+    /// The cluster to start searching for free clusters
+    pub next_free: u32,
+}
+
+impl core::fmt::Debug for Fat32Impl {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Fat32Impl")
+            .field("bpb", &self.bpb)
+            .field("fs_info", &*self.fs_info.lock())
+            .finish()
+    }
+}
@@ -1 +1,74 @@
 pub mod fat16;
+pub mod fat32;
+pub mod tmpfs;
+
+use crate::*;
+
+/// FAT variants, decided by the count of clusters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Detect the FAT variant from the BPB in the first sector
+    ///
+    /// reference: Microsoft FAT Specification, 3.5 FAT Type Determination
+    pub fn detect(device: &impl BlockDevice<Block512>) -> FsResult<FatType> {
+        let mut block = Block512::default();
+        device.read_block(0, &mut block)?;
+        let bpb = fat32::bpb::Fat32Bpb::new(block.as_ref())?;
+
+        let root_dir_sectors = (bpb.root_entries_count() as usize * 32).div_ceil(512);
+        let meta_sectors = bpb.reserved_sector_count() as usize
+            + bpb.fat_count() as usize * bpb.fat_size() as usize
+            + root_dir_sectors;
+        let data_sectors = (bpb.total_sectors() as usize)
+            .checked_sub(meta_sectors)
+            .ok_or(FsError::InvalidOperation)?;
+
+        match data_sectors / bpb.sectors_per_cluster() as usize {
+            ..4085 => Ok(FatType::Fat12),
+            4085..65525 => Ok(FatType::Fat16),
+            65525.. => Ok(FatType::Fat32),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use spin::Mutex;
+
+    /// A disk image in memory
+    #[derive(Debug, Clone)]
+    pub struct MemDisk(pub Arc<Mutex<Vec<u8>>>);
+
+    impl BlockDevice<Block512> for MemDisk {
+        fn block_count(&self) -> FsResult<usize> {
+            Ok(self.0.lock().len() / Block512::size())
+        }
+
+        fn read_block(&self, offset: usize, block: &mut Block512) -> FsResult {
+            let data = self.0.lock();
+            let start = offset * Block512::size();
+            let src = data
+                .get(start..start + Block512::size())
+                .ok_or(FsError::InvalidOffset)?;
+            block.copy_from_slice(src);
+            Ok(())
+        }
+
+        fn write_block(&self, offset: usize, block: &Block512) -> FsResult {
+            let mut data = self.0.lock();
+            let start = offset * Block512::size();
+            let dst = data
+                .get_mut(start..start + Block512::size())
+                .ok_or(FsError::InvalidOffset)?;
+            dst.copy_from_slice(block.as_ref());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! File of the tmpfs
+//!
+//! Open files share the node with the tree, so a removed file stays
+//! readable until the last handle is dropped.
+
+use super::*;
+
+#[derive(Debug)]
+pub struct TmpFile {
+    node: NodeRef,
+    /// Current offset
+    offset: usize,
+    /// Always write at the end of file
+    append: bool,
+}
+
+impl TmpFile {
+    pub(crate) fn new(node: NodeRef, append: bool) -> Self {
+        Self {
+            node,
+            offset: 0,
+            append,
+        }
+    }
+
+    pub fn length(&self) -> usize {
+        match &self.node.read().kind {
+            NodeKind::File(file) => file.data.len(),
+            NodeKind::Directory(_) => 0,
+        }
+    }
+}
+
+impl Read for TmpFile {
+    fn read(&mut self, buf: &mut [u8]) -> FsResult<usize> {
+        let mut node = self.node.write();
+        node.accessed = now();
+
+        let NodeKind::File(file) = &node.kind else {
+            return Err(FsError::NotAFile);
+        };
+
+        let data = file.data.get(self.offset..).unwrap_or(&[]);
+        let len = buf.len().min(data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+impl Seek for TmpFile {
+    fn seek(&mut self, pos: SeekFrom) -> FsResult<usize> {
+        let length = self.length();
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.offset.checked_add_signed(offset),
+        };
+
+        // can not seek before the start or past the end of file
+        match offset {
+            Some(offset) if offset <= length => {
+                self.offset = offset;
+                Ok(offset)
+            }
+            _ => Err(FsError::InvalidOffset),
+        }
+    }
+}
+
+impl Write for TmpFile {
+    fn write(&mut self, buf: &[u8]) -> FsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let mut node = self.node.write();
+        let NodeKind::File(file) = &mut node.kind else {
+            return Err(FsError::NotAFile);
+        };
+
+        // another handle may have truncated the file since
+        if self.append || self.offset > file.data.len() {
+            self.offset = file.data.len();
+        }
+
+        // overwrite in place, only the growing part takes new space
+        let overwrite = buf.len().min(file.data.len().saturating_sub(self.offset));
+        let grow = file.usage.reserve(buf.len() - overwrite);
+        let len = overwrite + grow;
+        if len == 0 {
+            return Err(FsError::WriteZero);
+        }
+
+        file.data[self.offset..self.offset + overwrite].copy_from_slice(&buf[..overwrite]);
+        file.data.extend_from_slice(&buf[overwrite..len]);
+
+        node.modified = now();
+        self.offset += len;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> FsResult {
+        Ok(())
+    }
+}
@@ -0,0 +1,437 @@
+//! In-memory File System
+//!
+//! Everything lives in the heap and is lost on reboot.
+
+pub mod file;
+
+use crate::*;
+use alloc::collections::BTreeMap;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use file::TmpFile;
+use spin::RwLock;
+
+pub(crate) type NodeRef = Arc<RwLock<Node>>;
+
+#[derive(Debug)]
+pub(crate) enum NodeKind {
+    File(FileData),
+    Directory(BTreeMap<String, NodeRef>),
+}
+
+/// A file or directory in the tmpfs
+#[derive(Debug)]
+pub(crate) struct Node {
+    name: String,
+    kind: NodeKind,
+    created: FsTime,
+    modified: FsTime,
+    accessed: FsTime,
+}
+
+impl Node {
+    fn new(name: &str, kind: NodeKind) -> NodeRef {
+        let time = now();
+        Arc::new(RwLock::new(Node {
+            name: name.into(),
+            kind,
+            created: time,
+            modified: time,
+            accessed: time,
+        }))
+    }
+
+    fn as_meta(&self) -> Metadata {
+        let (entry_type, len) = match &self.kind {
+            NodeKind::File(file) => (FileType::File, file.data.len()),
+            NodeKind::Directory(_) => (FileType::Directory, 0),
+        };
+
+        Metadata::new(
+            self.name.clone(),
+            entry_type,
+            len,
+            Some(self.created),
+            Some(self.modified),
+            Some(self.accessed),
+        )
+    }
+}
+
+/// Content of a file, its bytes are released when the last reference is gone
+#[derive(Debug)]
+pub(crate) struct FileData {
+    data: Vec<u8>,
+    usage: Arc<Usage>,
+}
+
+impl FileData {
+    fn new(usage: Arc<Usage>) -> Self {
+        Self {
+            data: Vec::new(),
+            usage,
+        }
+    }
+
+    /// Truncate the file to zero length
+    fn clear(&mut self) {
+        self.usage.release(self.data.len());
+        self.data = Vec::new();
+    }
+}
+
+impl Drop for FileData {
+    fn drop(&mut self) {
+        self.usage.release(self.data.len());
+    }
+}
+
+/// Bytes in use and the limit, shared with open files
+#[derive(Debug)]
+pub(crate) struct Usage {
+    used: AtomicUsize,
+    capacity: usize,
+}
+
+impl Usage {
+    /// Reserve up to `len` bytes, return how many are granted
+    fn reserve(&self, len: usize) -> usize {
+        let mut granted = 0;
+        let _ = self
+            .used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                granted = len.min(self.capacity.saturating_sub(used));
+                Some(used + granted)
+            });
+        granted
+    }
+
+    fn release(&self, len: usize) {
+        self.used.fetch_sub(len, Ordering::SeqCst);
+    }
+}
+
+/// A RAM-backed filesystem
+pub struct TmpFs {
+    root: NodeRef,
+    usage: Arc<Usage>,
+}
+
+impl TmpFs {
+    /// Create an empty tmpfs holding at most `capacity` bytes of file data
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            root: Node::new("", NodeKind::Directory(BTreeMap::new())),
+            usage: Arc::new(Usage {
+                used: AtomicUsize::new(0),
+                capacity,
+            }),
+        }
+    }
+
+    /// Bytes of file data in use
+    pub fn used(&self) -> usize {
+        self.usage.used.load(Ordering::SeqCst)
+    }
+
+    /// Walk the path from the root
+    fn lookup(&self, path: &str) -> FsResult<NodeRef> {
+        let mut node = self.root.clone();
+
+        for name in components(path) {
+            let next = match &node.read().kind {
+                NodeKind::Directory(children) => {
+                    children.get(name).cloned().ok_or(FsError::FileNotFound)?
+                }
+                NodeKind::File(_) => return Err(FsError::NotADirectory),
+            };
+            node = next;
+        }
+
+        Ok(node)
+    }
+
+    /// Find the parent directory and the name of the last component
+    fn lookup_parent<'a>(&self, path: &'a str) -> FsResult<(NodeRef, &'a str)> {
+        let (parent, name) = split_path(path.trim_start_matches(PATH_SEPARATOR));
+        if name.is_empty() || name == "." || name == ".." {
+            return Err(FsError::InvalidPath(path.into()));
+        }
+
+        let parent = self.lookup(parent)?;
+        if !matches!(parent.read().kind, NodeKind::Directory(_)) {
+            return Err(FsError::NotADirectory);
+        }
+
+        Ok((parent, name))
+    }
+
+    fn file_handle(&self, node: NodeRef, append: bool) -> FileHandle {
+        let meta = node.read().as_meta();
+        let file = TmpFile::new(node, append);
+        FileHandle::new(meta, Box::new(file))
+    }
+
+    /// Remove a child from `parent`, `check` decides if it can be removed
+    fn remove(&self, path: &str, check: impl FnOnce(&Node) -> FsResult) -> FsResult {
+        let (parent, name) = self.lookup_parent(path)?;
+        let mut parent = parent.write();
+
+        let NodeKind::Directory(children) = &mut parent.kind else {
+            return Err(FsError::NotADirectory);
+        };
+
+        let node = children.get(name).ok_or(FsError::FileNotFound)?;
+        check(&node.read())?;
+
+        children.remove(name);
+        parent.modified = now();
+        Ok(())
+    }
+}
+
+/// Split the path into names, empty components and `.` are skipped
+fn components(path: &str) -> impl Iterator<Item = &str> {
+    path.split(PATH_SEPARATOR)
+        .filter(|name| !name.is_empty() && *name != ".")
+}
+
+impl FileSystem for TmpFs {
+    fn read_dir(&self, path: &str) -> FsResult<Box<dyn Iterator<Item = Metadata> + Send>> {
+        let node = self.lookup(path)?;
+        let mut node = node.write();
+        node.accessed = now();
+
+        let NodeKind::Directory(children) = &node.kind else {
+            return Err(FsError::NotADirectory);
+        };
+
+        let entries: Vec<Metadata> = children
+            .values()
+            .map(|child| child.read().as_meta())
+            .collect();
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn open_file(&self, path: &str) -> FsResult<FileHandle> {
+        let node = self.lookup(path)?;
+        if !matches!(node.read().kind, NodeKind::File(_)) {
+            return Err(FsError::NotAFile);
+        }
+
+        Ok(self.file_handle(node, false))
+    }
+
+    fn metadata(&self, path: &str) -> FsResult<Metadata> {
+        Ok(self.lookup(path)?.read().as_meta())
+    }
+
+    fn exists(&self, path: &str) -> FsResult<bool> {
+        match self.lookup(path) {
+            Ok(_) => Ok(true),
+            Err(FsError::FileNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn create_file(&self, path: &str) -> FsResult<FileHandle> {
+        let (parent, name) = self.lookup_parent(path)?;
+        let mut parent = parent.write();
+
+        let NodeKind::Directory(children) = &mut parent.kind else {
+            return Err(FsError::NotADirectory);
+        };
+
+        let node = match children.get(name) {
+            Some(node) => {
+                // truncate the existing file
+                let mut inner = node.write();
+                let NodeKind::File(data) = &mut inner.kind else {
+                    return Err(FsError::NotAFile);
+                };
+                data.clear();
+                inner.modified = now();
+                node.clone()
+            }
+            None => {
+                let node = Node::new(name, NodeKind::File(FileData::new(self.usage.clone())));
+                children.insert(name.into(), node.clone());
+                parent.modified = now();
+                node
+            }
+        };
+
+        Ok(self.file_handle(node, false))
+    }
+
+    fn append_file(&self, path: &str) -> FsResult<FileHandle> {
+        let node = self.lookup(path)?;
+        if !matches!(node.read().kind, NodeKind::File(_)) {
+            return Err(FsError::NotAFile);
+        }
+
+        Ok(self.file_handle(node, true))
+    }
+
+    fn remove_file(&self, path: &str) -> FsResult {
+        self.remove(path, |node| match &node.kind {
+            NodeKind::File(_) => Ok(()),
+            NodeKind::Directory(_) => Err(FsError::NotAFile),
+        })
+    }
+
+    fn create_dir(&self, path: &str) -> FsResult {
+        let (parent, name) = self.lookup_parent(path)?;
+        let mut parent = parent.write();
+
+        let NodeKind::Directory(children) = &mut parent.kind else {
+            return Err(FsError::NotADirectory);
+        };
+
+        if children.contains_key(name) {
+            return Err(FsError::AlreadyExists);
+        }
+
+        let node = Node::new(name, NodeKind::Directory(BTreeMap::new()));
+        children.insert(name.into(), node);
+        parent.modified = now();
+        Ok(())
+    }
+
+    fn remove_dir(&self, path: &str) -> FsResult {
+        self.remove(path, |node| match &node.kind {
+            NodeKind::Directory(children) if children.is_empty() => Ok(()),
+            NodeKind::Directory(_) => Err(FsError::DirectoryNotEmpty),
+            NodeKind::File(_) => Err(FsError::NotADirectory),
+        })
+    }
+}
+
+impl core::fmt::Debug for TmpFs {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TmpFs")
+            .field("used", &self.used())
+            .field("capacity", &self.usage.capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_to_end(handle: &mut FileHandle) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 7];
+        loop {
+            match handle.read(&mut buf).unwrap() {
+                0 => break data,
+                n => data.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_tmpfs_file() {
+        let fs = TmpFs::new(4096);
+
+        let mut file = fs.create_file("hello.txt").unwrap();
+        file.write_all(b"hello, world").unwrap();
+        assert_eq!(fs.metadata("hello.txt").unwrap().len, 12);
+        assert_eq!(fs.used(), 12);
+
+        // overwrite in the middle
+        file.seek(SeekFrom::Start(7)).unwrap();
+        file.write_all(b"tmpfs!").unwrap();
+        assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 13);
+        assert!(file.seek(SeekFrom::End(1)).is_err());
+        assert!(file.seek(SeekFrom::Current(-14)).is_err());
+
+        let mut file = fs.open_file("/hello.txt").unwrap();
+        assert_eq!(read_to_end(&mut file), b"hello, tmpfs!");
+
+        let mut file = fs.append_file("hello.txt").unwrap();
+        file.write_all(b"\n").unwrap();
+        let mut file = fs.open_file("hello.txt").unwrap();
+        file.seek(SeekFrom::End(-7)).unwrap();
+        assert_eq!(read_to_end(&mut file), b"tmpfs!\n");
+
+        // truncate
+        fs.create_file("hello.txt").unwrap();
+        assert_eq!(fs.metadata("hello.txt").unwrap().len, 0);
+        assert_eq!(fs.used(), 0);
+
+        // capacity limit
+        let mut file = fs.create_file("big").unwrap();
+        assert_eq!(file.write(&[1u8; 5000]).unwrap(), 4096);
+        assert_eq!(file.write(&[1u8; 1]), Err(FsError::WriteZero));
+        drop(file);
+        fs.remove_file("big").unwrap();
+        assert_eq!(fs.used(), 0);
+        assert!(!fs.exists("big").unwrap());
+    }
+
+    #[test]
+    fn test_tmpfs_dir() {
+        let fs = TmpFs::new(4096);
+
+        fs.create_dir("a").unwrap();
+        fs.create_dir("a/b").unwrap();
+        assert_eq!(fs.create_dir("a"), Err(FsError::AlreadyExists));
+        assert_eq!(fs.create_dir("x/y"), Err(FsError::FileNotFound));
+
+        fs.create_file("a/b/c.txt").unwrap().write_all(b"c").unwrap();
+        fs.create_file("a/z.txt").unwrap();
+        assert_eq!(fs.create_dir("a/z.txt/d"), Err(FsError::NotADirectory));
+        assert_eq!(fs.open_file("a/b").err(), Some(FsError::NotAFile));
+
+        let names: Vec<String> = fs.read_dir("a").unwrap().map(|m| m.name).collect();
+        assert_eq!(names, ["b", "z.txt"]);
+        assert!(fs.metadata("a/b").unwrap().is_dir());
+        assert!(fs.metadata("").unwrap().is_dir());
+
+        assert_eq!(fs.remove_dir("a/b"), Err(FsError::DirectoryNotEmpty));
+        assert_eq!(fs.remove_dir("a/z.txt"), Err(FsError::NotADirectory));
+        assert_eq!(fs.remove_file("a/b"), Err(FsError::NotAFile));
+
+        fs.remove_file("a/b/c.txt").unwrap();
+        fs.remove_dir("a/b").unwrap();
+        assert!(!fs.exists("a/b").unwrap());
+        assert_eq!(fs.read_dir("a").unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_tmpfs_open_after_remove() {
+        let fs = TmpFs::new(4096);
+
+        let mut file = fs.create_file("keep").unwrap();
+        file.write_all(b"still here").unwrap();
+        fs.remove_file("keep").unwrap();
+
+        // the open handle keeps the data alive
+        file.seek(SeekFrom::Start(0)).unwrap();
+        assert_eq!(read_to_end(&mut file), b"still here");
+        assert_eq!(fs.open_file("keep").err(), Some(FsError::FileNotFound));
+        assert_eq!(fs.used(), 10);
+
+        drop(file);
+        assert_eq!(fs.used(), 0);
+    }
+
+    #[test]
+    fn test_tmpfs_write_after_truncate() {
+        let fs = TmpFs::new(4096);
+
+        let mut file = fs.create_file("log").unwrap();
+        file.write_all(b"0123456789").unwrap();
+
+        // truncated through a second handle, the first one writes at the new end
+        fs.create_file("log").unwrap().write_all(b"ab").unwrap();
+        file.write_all(b"cd").unwrap();
+        assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 4);
+
+        let mut file = fs.open_file("log").unwrap();
+        assert_eq!(read_to_end(&mut file), b"abcd");
+        assert_eq!(fs.used(), 4);
+    }
+}
@@ -0,0 +1,255 @@
+//! GPT Header & Partition Entry
+//!
+//! reference: UEFI Specification, 5.3 GUID Partition Table (GPT) Disk Layout
+
+use super::*;
+
+/// A GUID, stored in the on-disk mixed-endian layout
+///
+/// the first three fields are little endian, the rest are big endian
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    pub const UNUSED: Guid = Guid([0; 16]);
+    /// EFI System Partition, C12A7328-F81F-11D2-BA4B-00A0C93EC93B
+    pub const EFI_SYSTEM: Guid = Guid::new(
+        0xC12A7328,
+        0xF81F,
+        0x11D2,
+        [0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+    );
+    /// Microsoft Basic Data, EBD0A0A2-B9E5-4433-87C0-68B6B72699C7
+    pub const BASIC_DATA: Guid = Guid::new(
+        0xEBD0A0A2,
+        0xB9E5,
+        0x4433,
+        [0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+    );
+    /// Linux Filesystem Data, 0FC63DAF-8483-4772-8E79-3D69D8477DE4
+    pub const LINUX_FS: Guid = Guid::new(
+        0x0FC63DAF,
+        0x8483,
+        0x4772,
+        [0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4],
+    );
+
+    /// Build a GUID from its textual fields
+    pub const fn new(d1: u32, d2: u16, d3: u16, d4: [u8; 8]) -> Guid {
+        let d1 = d1.to_le_bytes();
+        let d2 = d2.to_le_bytes();
+        let d3 = d3.to_le_bytes();
+        Guid([
+            d1[0], d1[1], d1[2], d1[3], d2[0], d2[1], d3[0], d3[1], d4[0], d4[1], d4[2], d4[3],
+            d4[4], d4[5], d4[6], d4[7],
+        ])
+    }
+
+    pub fn from_bytes(data: &[u8; 16]) -> Guid {
+        Guid(*data)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+
+    pub fn is_unused(&self) -> bool {
+        *self == Guid::UNUSED
+    }
+}
+
+impl core::fmt::Display for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let d = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-{:02X}{:02X}-",
+            u32::from_le_bytes([d[0], d[1], d[2], d[3]]),
+            u16::from_le_bytes([d[4], d[5]]),
+            u16::from_le_bytes([d[6], d[7]]),
+            d[8],
+            d[9]
+        )?;
+        for b in &d[10..] {
+            write!(f, "{b:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+impl core::fmt::Debug for Guid {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+/// The GPT Header, located at LBA 1 and the last LBA (backup)
+#[derive(Clone, Copy)]
+pub struct GptHeader {
+    data: [u8; GptHeader::LEN],
+}
+
+impl GptHeader {
+    /// Size of the fields defined by revision 1.0
+    pub const LEN: usize = 92;
+    pub const SIGNATURE: &'static [u8; 8] = b"EFI PART";
+
+    /// Get `&[u8]` from the signature field, at the start of the header
+    pub fn signature(&self) -> &[u8; 8] {
+        self.data[..8].try_into().unwrap_or(&[0; 8])
+    }
+
+    define_field!(u32, 0x08, revision);
+    define_field!(u32, 0x0C, header_size);
+    define_field!(u32, 0x10, header_crc32);
+    define_field!(u64, 0x18, my_lba);
+    define_field!(u64, 0x20, alternate_lba);
+    define_field!(u64, 0x28, first_usable_lba);
+    define_field!(u64, 0x30, last_usable_lba);
+    define_field!([u8; 16], 0x38, disk_guid_bytes);
+    define_field!(u64, 0x48, entries_lba);
+    define_field!(u32, 0x50, entry_count);
+    define_field!(u32, 0x54, entry_size);
+    define_field!(u32, 0x58, entries_crc32);
+
+    /// Parse the header from a whole block, checking the signature and CRC
+    pub fn parse(block: &[u8]) -> FsResult<GptHeader> {
+        let header = GptHeader {
+            data: block[..Self::LEN].try_into().unwrap(),
+        };
+
+        if header.signature() != Self::SIGNATURE {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        let size = header.header_size() as usize;
+        if size < Self::LEN || size > block.len() {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        // CRC32 of the header is computed with the CRC field zeroed
+        let mut data = block[..size].to_vec();
+        data[0x10..0x14].fill(0);
+        if crc32(&data) != header.header_crc32() {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        let entry_size = header.entry_size() as usize;
+        if entry_size < GptPartition::LEN || entry_size % GptPartition::LEN != 0 {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        Ok(header)
+    }
+
+    pub fn disk_guid(&self) -> Guid {
+        Guid::from_bytes(self.disk_guid_bytes())
+    }
+}
+
+impl core::fmt::Debug for GptHeader {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GPT Header")
+            .field("Revision", &format!("0x{:08x}", self.revision()))
+            .field("My LBA", &self.my_lba())
+            .field("Alternate LBA", &self.alternate_lba())
+            .field("First Usable LBA", &self.first_usable_lba())
+            .field("Last Usable LBA", &self.last_usable_lba())
+            .field("Disk GUID", &self.disk_guid())
+            .field("Entries LBA", &self.entries_lba())
+            .field("Entry Count", &self.entry_count())
+            .field("Entry Size", &self.entry_size())
+            .finish()
+    }
+}
+
+/// A GPT partition entry
+#[derive(Clone, Copy)]
+pub struct GptPartition {
+    data: [u8; GptPartition::LEN],
+}
+
+impl GptPartition {
+    pub const LEN: usize = 128;
+
+    /// Get `&[u8]` from the type GUID field, at the start of the entry
+    pub fn type_guid_bytes(&self) -> &[u8; 16] {
+        self.data[..16].try_into().unwrap_or(&[0; 16])
+    }
+
+    define_field!([u8; 16], 0x10, unique_guid_bytes);
+    define_field!(u64, 0x20, first_lba);
+    define_field!(u64, 0x28, last_lba);
+    define_field!(u64, 0x30, attributes);
+    define_field!([u8; 72], 0x38, name_bytes);
+
+    /// Parse a partition entry, extra bytes of larger entries are ignored
+    pub fn parse(data: &[u8]) -> GptPartition {
+        GptPartition {
+            data: data[..Self::LEN].try_into().unwrap(),
+        }
+    }
+
+    pub fn type_guid(&self) -> Guid {
+        Guid::from_bytes(self.type_guid_bytes())
+    }
+
+    pub fn unique_guid(&self) -> Guid {
+        Guid::from_bytes(self.unique_guid_bytes())
+    }
+
+    /// Entries with a zero type GUID are unused
+    pub fn is_used(&self) -> bool {
+        !self.type_guid().is_unused()
+    }
+
+    /// Count of blocks, the last LBA is inclusive
+    pub fn total_lba(&self) -> u64 {
+        (self.last_lba() + 1).saturating_sub(self.first_lba())
+    }
+
+    /// Partition name, UTF-16LE terminated by NUL
+    pub fn name(&self) -> String {
+        let units: Vec<u16> = self
+            .name_bytes()
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|&c| c != 0)
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+impl core::fmt::Debug for GptPartition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("GPT Partition")
+            .field("Name", &self.name())
+            .field("Type GUID", &self.type_guid())
+            .field("Unique GUID", &self.unique_guid())
+            .field("First LBA", &self.first_lba())
+            .field("Last LBA", &self.last_lba())
+            .field("Attributes", &format!("0x{:016x}", self.attributes()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guid_test() {
+        let data = hex_literal::hex!("28 73 2a c1 1f f8 d2 11 ba 4b 00 a0 c9 3e c9 3b");
+        let guid = Guid::from_bytes(&data);
+
+        assert_eq!(guid, Guid::EFI_SYSTEM);
+        assert_eq!(
+            format!("{guid}"),
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+        );
+        assert_eq!(
+            format!("{}", Guid::LINUX_FS),
+            "0FC63DAF-8483-4772-8E79-3D69D8477DE4"
+        );
+    }
+}
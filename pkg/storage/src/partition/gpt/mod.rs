@@ -0,0 +1,302 @@
+//! GptTable
+
+mod entry;
+
+use core::marker::PhantomData;
+
+use crate::mbr::MbrPartition;
+use crate::*;
+pub use entry::*;
+
+/// Partition type of the protective MBR entry
+pub const PROTECTIVE_MBR_TYPE: u8 = 0xEE;
+
+/// The GPT Table
+///
+/// LBA 0 keeps a protective MBR so legacy tools see the disk as in use.
+/// The primary header at LBA 1 is followed by the partition entry array,
+/// and a backup copy of both lives at the end of the disk.
+///
+/// [ PMBR ] [ Header ] [ Entries ] [ Partitions ... ] [ Entries ] [ Backup Header ]
+pub struct GptTable<T, B>
+where
+    T: BlockDevice<B> + Clone,
+    B: BlockTrait,
+{
+    inner: T,
+    header: GptHeader,
+    entries: Vec<GptPartition>,
+    _block: PhantomData<B>,
+}
+
+impl<T, B> GptTable<T, B>
+where
+    T: BlockDevice<B> + Clone,
+    B: BlockTrait,
+{
+    /// The header in use, may be the backup one
+    pub fn header(&self) -> &GptHeader {
+        &self.header
+    }
+
+    /// Used partition entries, with type GUIDs and names
+    pub fn entries(&self) -> &[GptPartition] {
+        &self.entries
+    }
+
+    /// Check LBA 0 holds a protective MBR
+    fn check_protective_mbr(inner: &T) -> FsResult {
+        let mut block = B::default();
+        inner.read_block(0, &mut block)?;
+        let buffer = block.as_ref();
+
+        if buffer[0x1FE..0x200] != [0x55, 0xAA] {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        let protective = (0..4).any(|i| {
+            let entry = MbrPartition::parse(
+                buffer[0x1BE + i * 16..0x1BE + (i + 1) * 16]
+                    .try_into()
+                    .unwrap(),
+            );
+            entry.partition_type() == PROTECTIVE_MBR_TYPE
+        });
+
+        if protective {
+            Ok(())
+        } else {
+            Err(FsError::InvalidPartitionTable)
+        }
+    }
+
+    /// Read and verify the header at `lba` and its entry array
+    fn load(inner: &T, lba: usize) -> FsResult<(GptHeader, Vec<GptPartition>)> {
+        let mut block = B::default();
+        inner.read_block(lba, &mut block)?;
+
+        let header = GptHeader::parse(block.as_ref())?;
+        if header.my_lba() != lba as u64 {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        let entry_size = header.entry_size() as usize;
+        let total = header.entry_count() as usize * entry_size;
+        let blocks = total.div_ceil(B::size());
+
+        let mut data = Vec::with_capacity(blocks * B::size());
+        for i in 0..blocks {
+            inner.read_block(header.entries_lba() as usize + i, &mut block)?;
+            data.extend_from_slice(block.as_ref());
+        }
+        data.truncate(total);
+
+        if crc32(&data) != header.entries_crc32() {
+            return Err(FsError::InvalidPartitionTable);
+        }
+
+        let entries = data
+            .chunks_exact(entry_size)
+            .map(GptPartition::parse)
+            .filter(|entry| entry.is_used())
+            .collect();
+
+        Ok((header, entries))
+    }
+}
+
+impl<T, B> PartitionTable<T, B> for GptTable<T, B>
+where
+    T: BlockDevice<B> + Clone,
+    B: BlockTrait,
+{
+    fn parse(inner: T) -> FsResult<Self> {
+        Self::check_protective_mbr(&inner)?;
+
+        // fall back to the backup header at the last LBA
+        let (header, entries) = match Self::load(&inner, 1) {
+            Ok(res) => res,
+            Err(err) => {
+                warn!("Primary GPT header is invalid: {err:?}, trying backup");
+                let last = inner
+                    .block_count()?
+                    .checked_sub(1)
+                    .ok_or(FsError::InvalidPartitionTable)?;
+                Self::load(&inner, last)?
+            }
+        };
+
+        for (i, entry) in entries.iter().enumerate() {
+            info!("Partition {i}: {entry:#?}");
+        }
+
+        Ok(Self {
+            inner,
+            header,
+            entries,
+            _block: PhantomData,
+        })
+    }
+
+    fn partitions(&self) -> FsResult<Vec<Partition<T, B>>> {
+        Ok(self
+            .entries
+            .iter()
+            .map(|entry| {
+                Partition::new(
+                    self.inner.clone(),
+                    entry.first_lba() as usize,
+                    entry.total_lba() as usize,
+                )
+            })
+            .collect())
+    }
+}
+
+/// CRC32 (IEEE 802.3), as used by the GPT header and entry array
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fs::tests::MemDisk;
+    use spin::Mutex;
+
+    const DISK_BLOCKS: usize = 128;
+    const ENTRY_COUNT: usize = 128;
+    const ENTRY_BLOCKS: usize = ENTRY_COUNT * GptPartition::LEN / 512;
+
+    fn entry(type_guid: Guid, first: u64, last: u64, name: &str) -> [u8; 128] {
+        let mut data = [0u8; 128];
+        data[0x00..0x10].copy_from_slice(type_guid.as_bytes());
+        data[0x10..0x20].copy_from_slice(&[0x42; 16]);
+        data[0x20..0x28].copy_from_slice(&first.to_le_bytes());
+        data[0x28..0x30].copy_from_slice(&last.to_le_bytes());
+        for (i, c) in name.encode_utf16().enumerate() {
+            data[0x38 + i * 2..0x38 + i * 2 + 2].copy_from_slice(&c.to_le_bytes());
+        }
+        data
+    }
+
+    fn header(my_lba: u64, alternate_lba: u64, entries_lba: u64, entries_crc: u32) -> [u8; 512] {
+        let mut data = [0u8; 512];
+        data[0x00..0x08].copy_from_slice(GptHeader::SIGNATURE);
+        data[0x08..0x0C].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+        data[0x0C..0x10].copy_from_slice(&(GptHeader::LEN as u32).to_le_bytes());
+        data[0x18..0x20].copy_from_slice(&my_lba.to_le_bytes());
+        data[0x20..0x28].copy_from_slice(&alternate_lba.to_le_bytes());
+        data[0x28..0x30].copy_from_slice(&(2 + ENTRY_BLOCKS as u64).to_le_bytes());
+        let last_usable = (DISK_BLOCKS - 2 - ENTRY_BLOCKS) as u64;
+        data[0x30..0x38].copy_from_slice(&last_usable.to_le_bytes());
+        data[0x48..0x50].copy_from_slice(&entries_lba.to_le_bytes());
+        data[0x50..0x54].copy_from_slice(&(ENTRY_COUNT as u32).to_le_bytes());
+        data[0x54..0x58].copy_from_slice(&(GptPartition::LEN as u32).to_le_bytes());
+        data[0x58..0x5C].copy_from_slice(&entries_crc.to_le_bytes());
+        let crc = crc32(&data[..GptHeader::LEN]);
+        data[0x10..0x14].copy_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    fn gpt_disk() -> Arc<Mutex<Vec<u8>>> {
+        let mut disk = vec![0u8; DISK_BLOCKS * 512];
+
+        // protective mbr
+        disk[0x1BE + 4] = PROTECTIVE_MBR_TYPE;
+        disk[0x1BE + 8..0x1BE + 12].copy_from_slice(&1u32.to_le_bytes());
+        disk[0x1BE + 12..0x1BE + 16].copy_from_slice(&(DISK_BLOCKS as u32 - 1).to_le_bytes());
+        disk[0x1FE] = 0x55;
+        disk[0x1FF] = 0xAA;
+
+        let mut entries = vec![0u8; ENTRY_COUNT * GptPartition::LEN];
+        entries[..128].copy_from_slice(&entry(Guid::EFI_SYSTEM, 40, 59, "EFI system"));
+        entries[256..384].copy_from_slice(&entry(Guid::BASIC_DATA, 60, 89, "YSOS 数据"));
+        let entries_crc = crc32(&entries);
+
+        let last = DISK_BLOCKS - 1;
+        let backup_entries = last - ENTRY_BLOCKS;
+
+        disk[512..1024].copy_from_slice(&header(1, last as u64, 2, entries_crc));
+        disk[1024..1024 + entries.len()].copy_from_slice(&entries);
+        disk[backup_entries * 512..last * 512].copy_from_slice(&entries);
+        disk[last * 512..].copy_from_slice(&header(
+            last as u64,
+            1,
+            backup_entries as u64,
+            entries_crc,
+        ));
+
+        Arc::new(Mutex::new(disk))
+    }
+
+    #[test]
+    fn crc32_test() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn gpt_parse_test() {
+        let table = GptTable::parse(MemDisk(gpt_disk())).unwrap();
+
+        assert_eq!(table.header().my_lba(), 1);
+        assert_eq!(table.entries().len(), 2);
+        assert_eq!(table.entries()[0].type_guid(), Guid::EFI_SYSTEM);
+        assert_eq!(table.entries()[0].name(), "EFI system");
+        assert_eq!(table.entries()[1].type_guid(), Guid::BASIC_DATA);
+        assert_eq!(table.entries()[1].name(), "YSOS 数据");
+        assert_eq!(table.entries()[1].total_lba(), 30);
+
+        let parts = table.partitions().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[1].block_count().unwrap(), 30);
+
+        let mut block = Block512::default();
+        parts[1].read_block(0, &mut block).unwrap();
+        assert!(parts[1].read_block(30, &mut block).is_err());
+    }
+
+    #[test]
+    fn gpt_backup_test() {
+        let disk = gpt_disk();
+
+        // corrupt the primary header
+        disk.lock()[512 + 0x20] ^= 0xFF;
+        let table = GptTable::parse(MemDisk(disk.clone())).unwrap();
+        assert_eq!(table.header().my_lba(), DISK_BLOCKS as u64 - 1);
+        assert_eq!(table.entries().len(), 2);
+
+        // corrupt the primary entry array
+        let disk = gpt_disk();
+        disk.lock()[1024 + 0x38] ^= 0xFF;
+        let table = GptTable::parse(MemDisk(disk.clone())).unwrap();
+        assert_eq!(table.header().my_lba(), DISK_BLOCKS as u64 - 1);
+        assert_eq!(table.entries()[0].name(), "EFI system");
+
+        // both copies broken
+        disk.lock()[(DISK_BLOCKS - 1) * 512] ^= 0xFF;
+        assert!(GptTable::parse(MemDisk(disk)).is_err());
+    }
+
+    #[test]
+    fn gpt_protective_mbr_test() {
+        let disk = gpt_disk();
+        disk.lock()[0x1BE + 4] = 0x0B;
+        assert_eq!(
+            GptTable::parse(MemDisk(disk)).err(),
+            Some(FsError::InvalidPartitionTable)
+        );
+    }
+}
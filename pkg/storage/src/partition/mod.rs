@@ -2,6 +2,7 @@ use core::marker::PhantomData;
 
 use crate::*;
 
+pub mod gpt;
 pub mod mbr;
 
 /// Partition table trait
@@ -65,7 +66,7 @@ where
     B: BlockTrait,
 {
     fn block_count(&self) -> FsResult<usize> {
-        self.inner.block_count()
+        Ok(self.size)
     }
 
     fn read_block(&self, offset: usize, block: &mut B) -> FsResult {
@@ -79,6 +80,13 @@ where
         // FIXME: read from the inner device
     }
 
+    fn read_blocks(&self, offset: usize, blocks: &mut [B]) -> FsResult {
+        if offset + blocks.len() > self.size {
+            return Err(FsError::InvalidOffset);
+        }
+        self.inner.read_blocks(offset + self.offset, blocks)
+    }
+
     fn write_block(&self, offset: usize, block: &B) -> FsResult {
         if offset >= self.size {
             return Err(FsError::InvalidOffset);
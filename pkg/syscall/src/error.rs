@@ -0,0 +1,140 @@
+use num_enum::FromPrimitive;
+
+/// Error codes shared by the kernel and user library
+///
+/// A syscall returns `-(err as isize)` in `rax` on failure, values follow errno
+#[repr(isize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+pub enum SysError {
+    NotPermitted = 1,     // EPERM
+    NotFound = 2,         // ENOENT
+    NoProcess = 3,        // ESRCH
+    IoError = 5,          // EIO
+    BadFd = 9,            // EBADF
+    WouldBlock = 11,      // EAGAIN
+    OutOfMemory = 12,     // ENOMEM
+    BadAddress = 14,      // EFAULT
+    Busy = 16,            // EBUSY
+    AlreadyExists = 17,   // EEXIST
+    NotADirectory = 20,   // ENOTDIR
+    IsADirectory = 21,    // EISDIR
+    InvalidArgument = 22, // EINVAL
+    TooManyFiles = 24,    // EMFILE
+    NoSpace = 28,         // ENOSPC
+    InvalidSeek = 29,     // ESPIPE
+    ReadOnly = 30,        // EROFS
+    BrokenPipe = 32,      // EPIPE
+    NameTooLong = 36,     // ENAMETOOLONG
+    NotSupported = 38,    // ENOSYS
+    NotEmpty = 39,        // ENOTEMPTY
+
+    #[num_enum(default)]
+    Unknown = 4095,
+}
+
+pub type SysResult<T = usize> = Result<T, SysError>;
+
+impl SysError {
+    /// Largest errno, return values in `[-MAX_ERRNO, -1]` are errors
+    pub const MAX_ERRNO: isize = 4095;
+
+    /// Encode the error as the raw syscall return value
+    #[inline]
+    pub fn encode(self) -> usize {
+        (-(self as isize)) as usize
+    }
+
+    /// Encode a syscall result as the raw return value
+    #[inline]
+    pub fn encode_result(ret: SysResult) -> usize {
+        match ret {
+            Ok(val) => val,
+            Err(err) => err.encode(),
+        }
+    }
+
+    /// Decode the raw syscall return value
+    #[inline]
+    pub fn decode(ret: usize) -> SysResult {
+        let val = ret as isize;
+        if (-Self::MAX_ERRNO..0).contains(&val) {
+            Err(SysError::from(-val))
+        } else {
+            Ok(ret)
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SysError::NotPermitted => "operation not permitted",
+            SysError::NotFound => "no such file or directory",
+            SysError::NoProcess => "no such process",
+            SysError::IoError => "i/o error",
+            SysError::BadFd => "bad file descriptor",
+            SysError::WouldBlock => "resource temporarily unavailable",
+            SysError::OutOfMemory => "out of memory",
+            SysError::BadAddress => "bad address",
+            SysError::Busy => "device or resource busy",
+            SysError::AlreadyExists => "file exists",
+            SysError::NotADirectory => "not a directory",
+            SysError::IsADirectory => "is a directory",
+            SysError::InvalidArgument => "invalid argument",
+            SysError::TooManyFiles => "too many open files",
+            SysError::NoSpace => "no space left on device",
+            SysError::InvalidSeek => "illegal seek",
+            SysError::ReadOnly => "read-only file system",
+            SysError::BrokenPipe => "broken pipe",
+            SysError::NameTooLong => "file name too long",
+            SysError::NotSupported => "function not implemented",
+            SysError::NotEmpty => "directory not empty",
+            SysError::Unknown => "unknown error",
+        }
+    }
+}
+
+impl core::fmt::Display for SysError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_round_trip() {
+        for err in [
+            SysError::NotPermitted,
+            SysError::BadFd,
+            SysError::TooManyFiles,
+            SysError::NotEmpty,
+            SysError::Unknown,
+        ] {
+            assert_eq!(SysError::decode(err.encode()), Err(err));
+            assert_eq!(SysError::decode(SysError::encode_result(Err(err))), Err(err));
+        }
+        // unassigned errnos still decode as errors
+        assert_eq!(SysError::decode(-4isize as usize), Err(SysError::Unknown));
+    }
+
+    #[test]
+    fn test_value_round_trip() {
+        for val in [0, 1, 4095, 0x7fff_ffff_ffff, isize::MAX as usize] {
+            assert_eq!(SysError::decode(SysError::encode_result(Ok(val))), Ok(val));
+        }
+    }
+
+    #[test]
+    fn test_error_boundary() {
+        let max = SysError::MAX_ERRNO as usize;
+        // the last `MAX_ERRNO` values are errors, anything below them is valid
+        assert_eq!(SysError::decode(usize::MAX), Err(SysError::NotPermitted));
+        assert_eq!(SysError::decode(0usize.wrapping_sub(max)), Err(SysError::Unknown));
+        assert_eq!(
+            SysError::decode(0usize.wrapping_sub(max + 1)),
+            Ok(0usize.wrapping_sub(max + 1))
+        );
+        assert_eq!(SysError::decode(isize::MIN as usize), Ok(isize::MIN as usize));
+    }
+}
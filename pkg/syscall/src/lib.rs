@@ -1,8 +1,14 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use num_enum::FromPrimitive;
+use bitflags::bitflags;
+use num_enum::{FromPrimitive, TryFromPrimitive};
 
 pub mod macros;
+mod error;
+mod stat;
+
+pub use error::*;
+pub use stat::*;
 
 #[repr(usize)]
 #[derive(Clone, Debug, FromPrimitive)]
@@ -12,15 +18,34 @@ pub enum Syscall {
 
     GetTime = 2,
 
+    FileStat = 4,
+
+    Lseek = 8,
+
+    Dup = 32,
+    Dup2 = 33,
+    FdLimit = 97,
+
     Brk = 12,
 
+    Pipe = 22,
+
     GetPid = 39,
 
     Sem = 40, // 0: new, 1: wait, 2: signal, 3: remove
     ListDir=42,
+    ReadDir = 78,
     OpenFile = 43,
     CloseFile = 44,
+
+    CreateDir = 83,
+    RemoveDir = 84,
+    RemoveFile = 87,
     
+    Mount = 165,
+    Umount = 166,
+
+    Thread = 56,
     Fork = 58,
     Spawn = 59,
     Exit = 60,
@@ -34,3 +59,27 @@ pub enum Syscall {
     #[num_enum(default)]
     Unknown = 65535,
 }
+
+/// `whence` of the lseek syscall
+#[repr(usize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum SeekWhence {
+    Start = 0,
+    Current = 1,
+    End = 2,
+}
+
+bitflags! {
+    /// `flags` of the open syscall, empty for read-only
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct OpenFlags: usize {
+        /// Create the file if it does not exist
+        const CREATE = 1 << 0;
+        /// Truncate the file to zero length if it exists
+        const TRUNCATE = 1 << 1;
+        /// Start writing at the end of the file
+        const APPEND = 1 << 2;
+        /// Open a directory for `ReadDir`
+        const DIRECTORY = 1 << 3;
+    }
+}
@@ -0,0 +1,175 @@
+//! File status record shared by the `StatFile` and `ReadDir` syscalls
+//!
+//! A record is a fixed header followed by the name, all little endian,
+//! padded to 8 bytes:
+//!
+//! | offset | size | field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 2    | record length, including name & padding |
+//! | 2      | 1    | version, [`STAT_VERSION`]               |
+//! | 3      | 1    | file type, [`FileKind`]                 |
+//! | 4      | 2    | name length in bytes                    |
+//! | 6      | 2    | reserved                                |
+//! | 8      | 8    | size in bytes                           |
+//! | 16     | 8    | created, unix ms                        |
+//! | 24     | 8    | modified, unix ms                       |
+//! | 32     | 8    | accessed, unix ms                       |
+//! | 40     | ..   | name (utf-8)                            |
+//!
+//! Unknown times are stored as `i64::MIN`.
+
+use num_enum::TryFromPrimitive;
+
+/// Current version of the record format
+pub const STAT_VERSION: u8 = 1;
+
+/// Size of the record header
+pub const STAT_HEADER_LEN: usize = 40;
+
+const TIME_UNKNOWN: i64 = i64::MIN;
+
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, TryFromPrimitive)]
+pub enum FileKind {
+    File = 0,
+    Directory = 1,
+}
+
+/// Status of a file, without its name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: FileKind,
+    pub size: u64,
+    /// Times in milliseconds since the unix epoch
+    pub created: Option<i64>,
+    pub modified: Option<i64>,
+    pub accessed: Option<i64>,
+}
+
+impl FileStat {
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Directory
+    }
+
+    /// Length of the record holding `name`
+    pub fn record_len(name: &str) -> usize {
+        (STAT_HEADER_LEN + name.len()).next_multiple_of(8)
+    }
+
+    /// Encode the record into `buf`, `None` if it does not fit
+    pub fn encode(&self, name: &str, buf: &mut [u8]) -> Option<usize> {
+        let len = Self::record_len(name);
+        if len > buf.len() || len > u16::MAX as usize {
+            return None;
+        }
+
+        let buf = &mut buf[..len];
+        buf.fill(0);
+        buf[0..2].copy_from_slice(&(len as u16).to_le_bytes());
+        buf[2] = STAT_VERSION;
+        buf[3] = self.kind as u8;
+        buf[4..6].copy_from_slice(&(name.len() as u16).to_le_bytes());
+        buf[8..16].copy_from_slice(&self.size.to_le_bytes());
+
+        let times = [self.created, self.modified, self.accessed];
+        for (i, time) in times.iter().enumerate() {
+            let time = time.unwrap_or(TIME_UNKNOWN);
+            buf[16 + i * 8..24 + i * 8].copy_from_slice(&time.to_le_bytes());
+        }
+
+        buf[STAT_HEADER_LEN..STAT_HEADER_LEN + name.len()].copy_from_slice(name.as_bytes());
+        Some(len)
+    }
+
+    /// Decode the first record of `buf`, return the status, name and record length
+    pub fn decode(buf: &[u8]) -> Option<(FileStat, &str, usize)> {
+        let header = buf.get(..STAT_HEADER_LEN)?;
+        let u16_at = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]) as usize;
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+
+        let len = u16_at(0);
+        let name_len = u16_at(4);
+        if header[2] != STAT_VERSION || len < STAT_HEADER_LEN + name_len || len > buf.len() {
+            return None;
+        }
+
+        let time_at = |i: usize| match u64_at(i) as i64 {
+            TIME_UNKNOWN => None,
+            time => Some(time),
+        };
+
+        let stat = FileStat {
+            kind: FileKind::try_from(header[3]).ok()?,
+            size: u64_at(8),
+            created: time_at(16),
+            modified: time_at(24),
+            accessed: time_at(32),
+        };
+        let name = core::str::from_utf8(&buf[STAT_HEADER_LEN..STAT_HEADER_LEN + name_len]).ok()?;
+
+        Some((stat, name, len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: FileStat = FileStat {
+        kind: FileKind::File,
+        size: 0x1234_5678_9abc,
+        created: Some(1_700_000_000_000),
+        modified: Some(-1),
+        accessed: None,
+    };
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut buf = [0xffu8; 128];
+        let len = STAT.encode("hello.txt", &mut buf).unwrap();
+        assert_eq!(len, 56);
+        assert_eq!(len, FileStat::record_len("hello.txt"));
+        assert_eq!(FileStat::decode(&buf), Some((STAT, "hello.txt", len)));
+
+        let dir = FileStat {
+            kind: FileKind::Directory,
+            size: 0,
+            created: None,
+            modified: None,
+            accessed: None,
+        };
+        let len = dir.encode("", &mut buf).unwrap();
+        assert_eq!(len, STAT_HEADER_LEN);
+        let (stat, name, _) = FileStat::decode(&buf).unwrap();
+        assert!(stat.is_dir());
+        assert_eq!((stat, name), (dir, ""));
+    }
+
+    #[test]
+    fn test_encode_records_back_to_back() {
+        let mut buf = [0u8; 128];
+        let first = STAT.encode("a", &mut buf).unwrap();
+        let second = STAT.encode("longer name", &mut buf[first..]).unwrap();
+
+        let (_, name, len) = FileStat::decode(&buf).unwrap();
+        assert_eq!((name, len), ("a", first));
+        let (_, name, len) = FileStat::decode(&buf[first..]).unwrap();
+        assert_eq!((name, len), ("longer name", second));
+    }
+
+    #[test]
+    fn test_encode_short_buffer() {
+        let mut buf = [0u8; 128];
+        let len = FileStat::record_len("hello.txt");
+        assert_eq!(STAT.encode("hello.txt", &mut buf[..len - 1]), None);
+        assert_eq!(STAT.encode("hello.txt", &mut buf[..STAT_HEADER_LEN - 1]), None);
+        assert_eq!(STAT.encode("hello.txt", &mut []), None);
+        // nothing was written
+        assert!(buf.iter().all(|&b| b == 0));
+        assert_eq!(STAT.encode("hello.txt", &mut buf[..len]), Some(len));
+
+        // a record cut short does not decode either
+        assert_eq!(FileStat::decode(&buf[..len - 1]), None);
+        assert_eq!(FileStat::decode(&buf[..STAT_HEADER_LEN - 1]), None);
+    }
+}